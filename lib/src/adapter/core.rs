@@ -1,5 +1,6 @@
 use crate::{
     common::{self, IError, IResult},
+    epub::diff::{diff_book, BookChange},
     mobi::{builder::MobiBuilder, core::MobiAssets, image::get_attr_value},
     prelude::{EpubBook, EpubBuilder, EpubHtml, EpubNav, MobiBook, MobiHtml, MobiNav},
 };
@@ -125,6 +126,12 @@ pub fn mobi_to_epub(mobi: &mut MobiBook) -> IResult<EpubBook> {
     if let Some(v) = mobi.subject() {
         builder = builder.with_subject(v);
     }
+    if let Some(v) = mobi.series() {
+        builder = builder.with_series(v);
+    }
+    if let Some(v) = mobi.series_index() {
+        builder = builder.with_series_index(v);
+    }
 
     builder.book()
 }
@@ -321,6 +328,12 @@ pub fn epub_to_mobi(epub: &mut EpubBook) -> IResult<MobiBook> {
     if let Some(v) = epub.subject() {
         builder = builder.with_subject(v);
     }
+    if let Some(v) = epub.series() {
+        builder = builder.with_series(v);
+    }
+    if let Some(v) = epub.series_index() {
+        builder = builder.with_series_index(v);
+    }
 
     let chap = epub.chapters_mut();
 
@@ -363,6 +376,33 @@ pub fn epub_to_mobi(epub: &mut EpubBook) -> IResult<MobiBook> {
     builder.book()
 }
 
+/// 比较两本 mobi
+///
+/// 先转换为 epub 再比较，章节的文件名由转换时生成
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::diff::diff_mobi;
+///
+/// let mut read = |f: &str| {
+///     std::fs::File::open(f)
+///         .map_err(IError::Io)
+///         .and_then(MobiReader::new)
+///         .and_then(|mut f| f.load())
+///         .unwrap()
+/// };
+/// let (mut old, mut new) = (read("old.mobi"), read("new.mobi"));
+/// for ele in diff_mobi(&mut old, &mut new).unwrap() {
+///     println!("{ele}");
+/// }
+/// ```
+pub fn diff_mobi(old: &mut MobiBook, new: &mut MobiBook) -> IResult<Vec<BookChange>> {
+    let mut old = mobi_to_epub(old)?;
+    let mut new = mobi_to_epub(new)?;
+    Ok(diff_book(&mut old, &mut new))
+}
+
 pub mod concat {
     use crate::{
         common::{get_css_content_url, get_media_type, IResult},
@@ -620,6 +660,10 @@ pub mod concat {
                     v.as_str(),
                 ));
             new_html.body_attribute = ele.body_attribute.clone();
+            new_html.html_attribute = ele.html_attribute.clone();
+            if let Some(head) = ele.head() {
+                new_html.set_head(head);
+            }
 
             for ele in ele.links().unwrap_or_default() {
                 // link 也需要调整引用路径
@@ -813,6 +857,40 @@ mod tests {
         EpubWriter::write_to_mem(&mut epub, false).unwrap();
     }
 
+    #[test]
+    fn test_diff_mobi() {
+        use crate::prelude::{diff::BookChange, MobiBuilder, MobiHtml};
+
+        let create = |title: &str, text: &str| {
+            MobiBuilder::new()
+                .with_title(title)
+                .cover(vec![1, 2, 3])
+                .add_chapter(
+                    MobiHtml::new(0)
+                        .with_title("第一章")
+                        .with_data(format!("<p>一</p><p>{text}</p>").as_bytes().to_vec()),
+                )
+                .book()
+                .unwrap()
+        };
+        let mut old = create("书名", "二");
+        let mut new = create("新书名", "三");
+
+        let changes = super::diff_mobi(&mut old, &mut new).unwrap();
+        assert!(changes.contains(&BookChange::Metadata {
+            field: "title".to_string(),
+            old: Some("书名".to_string()),
+            new: Some("新书名".to_string()),
+        }));
+        assert_eq!(
+            1,
+            changes
+                .iter()
+                .filter(|f| matches!(f, BookChange::ParagraphAdded { text, .. } if text == "三"))
+                .count()
+        );
+    }
+
     #[test]
     fn test_epub_to_mobi() {
         let img = crate::common::tests::get_req_mem(
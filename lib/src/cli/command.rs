@@ -5,6 +5,7 @@ use crate::{
     exec_err, msg,
 };
 use iepub::prelude::*;
+use iepub::prelude::{adapter, diff, search, stats, txt};
 
 // 是否覆盖文件
 fn is_overiade(global_opts: &[arg::ArgOption], opts: &[arg::ArgOption]) -> bool {
@@ -80,6 +81,24 @@ fn write_file(path: &str, data: &[u8]) {
         .map_err(|e| exec_err!("err: {}", e));
 }
 
+/// 流式写入文件，不需要把整个文件读取到内存中
+fn write_stream(path: &str, data: &mut impl std::io::Read) {
+    let p = std::path::Path::new(path);
+    if p.parent().map(|f| !f.exists()).unwrap_or(true) {
+        if let Some(v) = p.parent().map(|f| format!("{}", f.display())) {
+            create_dir(v.as_str());
+        }
+    }
+
+    let _ = std::fs::File::options()
+        .truncate(true)
+        .create(true)
+        .write(true)
+        .open(path)
+        .and_then(|mut f| std::io::copy(data, &mut f))
+        .map_err(|e| exec_err!("err: {}", e));
+}
+
 fn create_dir(path: &str) {
     if !std::path::Path::new(path).exists() {
         msg!("creating dir {}", path);
@@ -120,6 +139,117 @@ fn read_book(file: &str) -> IResult<OwnBook> {
     }
 }
 
+/// search 命令的搜索条件
+fn search_query(opts: &[arg::ArgOption]) -> search::SearchQuery {
+    let text: String = opts.get_value("q").unwrap();
+    let query = if opts.has_opt("r") {
+        search::SearchQuery::regex(text.as_str())
+            .unwrap_or_else(|e| exec_err!("invalid regex {}, reason: {:?}", text, e))
+    } else {
+        search::SearchQuery::new(text.as_str())
+    };
+    query.with_context(opts.get_value_or_default("c", 20))
+}
+
+/// 逐条输出搜索结果
+fn print_search_hits(hits: impl Iterator<Item = search::SearchHit>) {
+    let mut count = 0;
+    for ele in hits {
+        println!("{}", ele);
+        count += 1;
+    }
+    println!("{} results", count);
+}
+
+/// stats 命令的阅读速度
+fn reading_speed(opts: &[arg::ArgOption]) -> stats::ReadingSpeed {
+    let default = stats::ReadingSpeed::default();
+    stats::ReadingSpeed::new(
+        opts.get_value_or_default("cjk", default.cjk()),
+        opts.get_value_or_default("words", default.words()),
+    )
+}
+
+/// 以表格或者json输出统计结果
+fn print_stats(book_stats: &stats::BookStats, opts: &[arg::ArgOption]) {
+    if opts.has_opt("json") {
+        println!("{}", book_stats.to_json());
+        return;
+    }
+    let row = |name: &str, f: &stats::ChapterStats| {
+        println!(
+            "{:>8} {:>8} {:>10} {:>10} {:>6} {:>8.1}  {}",
+            f.cjk(),
+            f.words(),
+            f.characters(),
+            f.paragraphs(),
+            f.images(),
+            f.minutes(),
+            name
+        );
+    };
+    println!(
+        "{:>8} {:>8} {:>10} {:>10} {:>6} {:>8}  title",
+        "cjk", "words", "characters", "paragraphs", "images", "minutes"
+    );
+    for (index, ele) in book_stats.chapters().iter().enumerate() {
+        let name = match ele.file_name() {
+            Some(file_name) => format!("{} {}", file_name, ele.title()),
+            None => format!("#{} {}", index, ele.title()),
+        };
+        row(name.as_str(), ele);
+    }
+    row("total", book_stats.total());
+}
+
+/// convert 命令的输出文件是否是 txt
+fn is_txt(path: &str) -> bool {
+    path.to_lowercase().ends_with(".txt")
+}
+
+/// convert 命令导出 txt 的选项
+fn txt_options(opts: &[arg::ArgOption]) -> txt::TxtOptions {
+    let encoding = opts.get_value_or_default("e", "utf8".to_string());
+    let mut options = txt::TxtOptions::default()
+        .with_indent(opts.get_value_or_default("i", 2))
+        .with_blank_lines(opts.get_value_or_default("b", 0))
+        .with_title(!opts.has_opt("n"))
+        .with_encoding(match encoding.to_lowercase().as_str() {
+            "utf8" | "utf-8" => txt::TxtEncoding::Utf8,
+            "gb18030" | "gbk" => txt::TxtEncoding::Gb18030,
+            _ => exec_err!("unsupport encoding {}", encoding),
+        });
+    if opts.has_opt("ni") {
+        options = options.with_image_placeholder(None::<String>);
+    } else if let Some(v) = opts.get_value::<_, String>("img") {
+        options = options.with_image_placeholder(Some(v));
+    }
+    options
+}
+
+/// 和 -f 指定的电子书比较并输出差异，mobi 会先转换为 epub
+fn diff_with(book: &mut EpubBook, opts: &[arg::ArgOption]) {
+    let file: String = opts.get_value("f").unwrap();
+    let mut other = match read_book(file.as_str()) {
+        Ok(OwnBook::EPUB(v)) => v,
+        Ok(OwnBook::MOBI(mut v)) => adapter::mobi_to_epub(&mut v)
+            .unwrap_or_else(|e| exec_err!("convert mobi {} to epub fail, reason: {:?}", file, e)),
+        Err(e) => exec_err!("read file {} fail, reason: {:?}", file, e),
+    };
+    let changes = diff::diff_book(book, &mut other);
+    if opts.has_opt("json") {
+        println!("{}", diff::to_json(&changes));
+    } else {
+        for ele in &changes {
+            println!("{}", ele);
+        }
+        println!("{} changes", changes.len());
+    }
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
 pub(crate) mod epub {
 
     use std::hash::Hasher;
@@ -130,15 +260,29 @@ pub(crate) mod epub {
     use crate::cli::command::is_overiade;
     use crate::cli::command::out_file;
     use crate::cli::command::write_file;
+    use crate::cli::command::write_stream;
     use crate::exec_err;
     use crate::Book;
     use iepub::prelude::adapter::add_into_epub;
     use iepub::prelude::adapter::epub_to_mobi;
     use iepub::prelude::adapter::mobi_to_epub;
     use iepub::prelude::appender::write_metadata;
+    use iepub::prelude::appender::EpubAppender;
+    use iepub::prelude::convert::downgrade_to_epub2;
+    use iepub::prelude::convert::upgrade_to_epub3;
+    use iepub::prelude::search::search_epub;
+    use iepub::prelude::stats::stats_epub;
+    use iepub::prelude::txt::epub_to_txt;
+    use iepub::prelude::validate_dir;
+    use iepub::prelude::validate_file;
     use iepub::prelude::EpubWriter;
+    use iepub::prelude::Severity;
 
+    use iepub::prelude::decode_text;
+    use iepub::prelude::escape_xml;
+    use iepub::prelude::EpubBook;
     use iepub::prelude::EpubBuilder;
+    use iepub::prelude::EpubHtml;
     use iepub::prelude::EpubNav;
 
     use iepub::prelude::MobiWriter;
@@ -229,6 +373,12 @@ pub(crate) mod epub {
                 if let Some(v) = book.subject() {
                     builder = builder.with_subject(v);
                 }
+                if let Some(v) = book.series() {
+                    builder = builder.with_series(v);
+                }
+                if let Some(v) = book.series_index() {
+                    builder = builder.with_series_index(v);
+                }
 
                 if let Some(c) = book.cover_mut() {
                     let f = c.file_name().to_string();
@@ -394,6 +544,13 @@ pub(crate) mod epub {
                     OptionDef::create("format", "format", OptionType::NoParamter, false),
                     OptionDef::create("subject", "subject", OptionType::NoParamter, false),
                     OptionDef::create("contributor", "contributor", OptionType::NoParamter, false),
+                    OptionDef::create("series", "系列", OptionType::NoParamter, false),
+                    OptionDef::create(
+                        "series-index",
+                        "在系列中的序号",
+                        OptionType::NoParamter,
+                        false,
+                    ),
                     OptionDef::create("modify", "最后修改时间", OptionType::NoParamter, false),
                     OptionDef::create("generator", "电子书创建者", OptionType::NoParamter, false),
                     OptionDef::create("all", "所有元数据", OptionType::NoParamter, false),
@@ -419,6 +576,8 @@ pub(crate) mod epub {
                         "format" => println!("{}", book.format().unwrap_or("")),
                         "subject" => println!("{}", book.subject().unwrap_or("")),
                         "contributor" => println!("{}", book.contributor().unwrap_or("")),
+                        "series" => println!("{}", book.series().unwrap_or("")),
+                        "series-index" => println!("{}", book.series_index().unwrap_or("")),
                         "modify" => println!("{}", book.last_modify().unwrap_or("")),
                         "generator" => println!("{}", book.generator().unwrap_or("")),
                         "all" => {
@@ -431,6 +590,8 @@ pub(crate) mod epub {
                             println!("format: {}", book.format().unwrap_or(""));
                             println!("subject: {}", book.subject().unwrap_or(""));
                             println!("contributor: {}", book.contributor().unwrap_or(""));
+                            println!("series: {}", book.series().unwrap_or(""));
+                            println!("series-index: {}", book.series_index().unwrap_or(""));
                             println!("modify: {}", book.last_modify().unwrap_or(""));
                             println!("generator: {}", book.generator().unwrap_or(""));
                         }
@@ -600,7 +761,10 @@ pub(crate) mod epub {
                             }
                             msg!("writing file to {}", file);
                             // 写入文件
-                            write_file(&file, ele.data_mut().unwrap());
+                            match ele.reader() {
+                                Ok(mut reader) => write_stream(&file, &mut reader),
+                                Err(e) => exec_err!("read {} err: {}", ele.file_name(), e),
+                            }
                         }
                     }
                 }
@@ -734,6 +898,13 @@ pub(crate) mod epub {
                     OptionDef::create("format", "format", OptionType::String, false),
                     OptionDef::create("subject", "subject", OptionType::String, false),
                     OptionDef::create("contributor", "contributor", OptionType::String, false),
+                    OptionDef::create("series", "系列", OptionType::String, false),
+                    OptionDef::create(
+                        "series-index",
+                        "在系列中的序号，例如 1、1.5",
+                        OptionType::String,
+                        false,
+                    ),
                 ],
             }
         },
@@ -758,6 +929,8 @@ pub(crate) mod epub {
                         "format" => book.set_format(v),
                         "subject" => book.set_subject(v),
                         "contributor" => book.set_contributor(v),
+                        "series" => book.set_series(v),
+                        "series-index" => book.set_series_index(v),
                         _ => {}
                     }
                 }
@@ -781,11 +954,36 @@ pub(crate) mod epub {
             arg::CommandOptionDef {
                 command: "convert".to_string(),
                 support_args: 0,
-                desc: "转换成mobi".to_string(),
+                desc: "转换成mobi、txt，或者转换epub版本".to_string(),
                 opts: vec![
-                    OptionDef::create("f", "输出文件路径", OptionType::String, true),
+                    OptionDef::create(
+                        "f",
+                        "输出文件路径，以 .txt 结尾时导出txt",
+                        OptionType::String,
+                        true,
+                    ),
                     OptionDef::create("n", "不添加标题，默认添加", OptionType::NoParamter, false),
                     OptionDef::create("i", "缩进字符数", OptionType::Number, false),
+                    OptionDef::create("b", "导出txt时段落之间的空行数", OptionType::Number, false),
+                    OptionDef::create(
+                        "e",
+                        "导出txt时使用的编码，可选 utf8、gb18030，默认 utf8",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create(
+                        "img",
+                        "导出txt时图片的占位文本，默认 [图片]",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create("ni", "导出txt时删除图片", OptionType::NoParamter, false),
+                    OptionDef::create(
+                        "v",
+                        "转换为指定版本的epub而不是mobi，可选 2、3",
+                        OptionType::String,
+                        false,
+                    ),
                     OptionDef::over(),
                 ],
             }
@@ -801,6 +999,33 @@ pub(crate) mod epub {
 
             let append_title = !opts.has_opt("n");
 
+            if let (Book::EPUB(book), true) = (&mut *book, super::is_txt(path.as_str())) {
+                if out_file(global_opts, opts, path.as_str()) {
+                    let data = epub_to_txt(book, &super::txt_options(opts));
+                    msg!("writing file {}", path);
+                    write_file(path.as_str(), &data);
+                }
+                return;
+            }
+
+            if let (Book::EPUB(book), Some(version)) =
+                (&mut *book, opts.get_value::<_, String>("v"))
+            {
+                match version.as_str() {
+                    "2" => downgrade_to_epub2(book),
+                    "3" => upgrade_to_epub3(book),
+                    _ => exec_err!("unsupport version {}", version),
+                }
+                if out_file(global_opts, opts, path.as_str()) {
+                    msg!("writing file {}", path);
+                    // 章节已经包含标题，不再重复添加
+                    if let Err(e) = EpubWriter::write_to_file(path, book, false) {
+                        exec_err!("写入文件错误 {:?}", e);
+                    }
+                }
+                return;
+            }
+
             if let Book::EPUB(book) = book {
                 let _ = epub_to_mobi(book)
                     .map(|mobi| {
@@ -1107,11 +1332,303 @@ pub(crate) mod epub {
             }
         }
     );
+
+    create_command!(
+        Unpack,
+        "unpack",
+        {
+            arg::CommandOptionDef {
+                command: String::from("unpack"),
+                desc: "解包epub到指定文件夹，可以使用pack重新打包".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("d", "输出目录", OptionType::String, true),
+                    OptionDef::over(),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                let path: String = opts.get_value("d").unwrap();
+                if !out_file(global_opts, opts, path.as_str()) {
+                    return;
+                }
+                msg!("unpacking book to {}", path);
+                if let Err(e) = EpubWriter::write_to_dir(path, book, false) {
+                    exec_err!("写入目录错误 {:?}", e);
+                }
+            }
+        },
+    );
+
+    create_command!(
+        Pack,
+        "pack",
+        {
+            arg::CommandOptionDef {
+                command: String::from("pack"),
+                desc: "打包为epub文件，输入通常是unpack得到的文件夹".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("f", "输出文件路径", OptionType::String, true),
+                    OptionDef::over(),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                let path: String = opts.get_value("f").unwrap();
+                if !out_file(global_opts, opts, path.as_str()) {
+                    return;
+                }
+                msg!("writing book to {}", path);
+                if let Err(e) = EpubWriter::write_to_file(path, book, false) {
+                    exec_err!("写入文件错误 {:?}", e);
+                }
+            }
+        },
+    );
+
+    create_command!(
+        Append,
+        "append",
+        {
+            arg::CommandOptionDef {
+                command: String::from("append"),
+                desc: "追加章节到原文件，不会重新读取已有的章节".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create(
+                        "f",
+                        "章节文件，支持 xhtml、html 和 txt；txt 第一行为标题；可以使用*.txt语法批量添加",
+                        OptionType::Array,
+                        true,
+                    ),
+                    OptionDef::create("n", "不添加标题，默认添加", OptionType::NoParamter, false),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                let path: String = global_opts.get_value("i").unwrap();
+                if std::path::Path::new(path.as_str()).is_dir() {
+                    exec_err!("append 只支持epub文件");
+                }
+                // 新章节和最后一个章节放在同一目录
+                let dir = book
+                    .chapters()
+                    .last()
+                    .and_then(|f| f.file_name().rfind('/').map(|i| &f.file_name()[..=i]))
+                    .unwrap_or_default()
+                    .to_string();
+                let mut appender = EpubAppender::new().append_title(!opts.has_opt("n"));
+                for file in opts.get_values::<_, String>("f").unwrap_or_default() {
+                    let p = std::path::Path::new(file.as_str());
+                    let stem = p
+                        .file_stem()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    // txt 可能是 GBK 等编码
+                    let content = std::fs::read(p)
+                        .map(|f| decode_text(&f))
+                        .unwrap_or_else(|e| exec_err!("read {} fail, because {}", file, e));
+                    let mut chap = EpubHtml::default().with_file_name(format!("{dir}{stem}.xhtml"));
+                    if p.extension().is_some_and(|f| f.eq_ignore_ascii_case("txt")) {
+                        let mut lines = content.lines().map(|f| f.trim()).filter(|f| !f.is_empty());
+                        chap.set_title(lines.next().unwrap_or(stem.as_str()));
+                        let html: String =
+                            lines.map(|f| format!("<p>{}</p>", escape_xml(f))).collect();
+                        chap.set_data(html.into_bytes());
+                    } else if let Err(e) = chap.set_html(content.as_str()) {
+                        exec_err!("parse {} fail, because {:?}", file, e);
+                    }
+                    msg!("appending chapter {}", chap.file_name());
+                    appender = appender.add_chapter(chap);
+                }
+                if let Err(e) = appender.append(path.as_str()) {
+                    exec_err!("追加章节错误 {:?}", e);
+                }
+            }
+        }
+    );
+
+    create_command!(
+        Validate,
+        "validate",
+        {
+            arg::CommandOptionDef {
+                command: "validate".to_string(),
+                support_args: 0,
+                desc: "检查电子书结构是否符合规范，存在错误时返回码为1".to_string(),
+                opts: vec![
+                    OptionDef::create(
+                        "strict",
+                        "存在警告时也返回错误码",
+                        OptionType::NoParamter,
+                        false,
+                    ),
+                    OptionDef::over(),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            _book: &mut Book,
+            global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            let path: String = global_opts.get_value("i").unwrap();
+            let result = if std::path::Path::new(path.as_str()).is_dir() {
+                validate_dir(path.as_str())
+            } else {
+                validate_file(path.as_str())
+            };
+            let result = match result {
+                Ok(v) => v,
+                Err(e) => {
+                    exec_err!("检查文件错误 {:?}", e);
+                }
+            };
+            for ele in &result {
+                println!("{}", ele);
+            }
+            let errors = result
+                .iter()
+                .filter(|f| f.severity() == Severity::Error)
+                .count();
+            let warnings = result.len() - errors;
+            println!("{} errors, {} warnings", errors, warnings);
+            if errors > 0 || (opts.has_opt("strict") && warnings > 0) {
+                std::process::exit(1);
+            }
+        }
+    );
+
+    create_command!(
+        Diff,
+        "diff",
+        {
+            arg::CommandOptionDef {
+                command: String::from("diff"),
+                desc: "和另一本电子书比较，存在差异时返回码为1".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create(
+                        "f",
+                        "比较的电子书，支持epub和mobi",
+                        OptionType::String,
+                        true,
+                    ),
+                    OptionDef::create("json", "以json格式输出", OptionType::NoParamter, false),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                super::diff_with(book, opts);
+            }
+        }
+    );
+
+    create_command!(
+        Search,
+        "search",
+        {
+            arg::CommandOptionDef {
+                command: String::from("search"),
+                desc: "全文搜索，输出章节、目录标题、段落序号以及上下文".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("q", "搜索内容", OptionType::String, true),
+                    OptionDef::create("r", "使用正则表达式", OptionType::NoParamter, false),
+                    OptionDef::create("c", "上下文字符数，默认20", OptionType::String, false),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                let query = super::search_query(opts);
+                super::print_search_hits(search_epub(book, &query));
+            }
+        }
+    );
+
+    create_command!(
+        Stats,
+        "stats",
+        {
+            arg::CommandOptionDef {
+                command: String::from("stats"),
+                desc: "统计每个章节以及全书的字数、段落、图片和预计阅读时间".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("json", "以json格式输出", OptionType::NoParamter, false),
+                    OptionDef::create(
+                        "cjk",
+                        "每分钟阅读的中日韩文字数，默认400",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create(
+                        "words",
+                        "每分钟阅读的单词数，默认250",
+                        OptionType::String,
+                        false,
+                    ),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::EPUB(book) = book {
+                super::print_stats(&stats_epub(book, &super::reading_speed(opts)), opts);
+            }
+        }
+    );
 }
 
 pub(crate) mod mobi {
 
-    use iepub::prelude::{adapter::mobi_to_epub, EpubWriter, MobiNav, MobiWriter};
+    use iepub::prelude::{
+        adapter::mobi_to_epub, search::search_mobi, stats::stats_mobi, txt::mobi_to_txt,
+        EpubWriter, MobiNav, MobiWriter,
+    };
 
     use crate::{
         cli::{
@@ -1405,10 +1922,30 @@ pub(crate) mod mobi {
             arg::CommandOptionDef {
                 command: "convert".to_string(),
                 support_args: 0,
-                desc: "转换成epub".to_string(),
+                desc: "转换成epub或者txt".to_string(),
                 opts: vec![
-                    OptionDef::create("f", "输出文件路径", OptionType::String, true),
+                    OptionDef::create(
+                        "f",
+                        "输出文件路径，以 .txt 结尾时导出txt",
+                        OptionType::String,
+                        true,
+                    ),
                     OptionDef::create("n", "不添加标题，默认添加", OptionType::NoParamter, false),
+                    OptionDef::create("i", "导出txt时的缩进字符数", OptionType::Number, false),
+                    OptionDef::create("b", "导出txt时段落之间的空行数", OptionType::Number, false),
+                    OptionDef::create(
+                        "e",
+                        "导出txt时使用的编码，可选 utf8、gb18030，默认 utf8",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create(
+                        "img",
+                        "导出txt时图片的占位文本，默认 [图片]",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create("ni", "导出txt时删除图片", OptionType::NoParamter, false),
                     OptionDef::over(),
                 ],
             }
@@ -1423,6 +1960,15 @@ pub(crate) mod mobi {
             let path: String = opts.get_value("f").unwrap();
             let append_title = !opts.has_opt("n");
 
+            if let (Book::MOBI(book), true) = (&mut *book, super::is_txt(path.as_str())) {
+                if out_file(global_opts, opts, path.as_str()) {
+                    let data = mobi_to_txt(book, &super::txt_options(opts));
+                    msg!("writing file {}", path);
+                    write_file(path.as_str(), &data);
+                }
+                return;
+            }
+
             if let Book::MOBI(book) = book {
                 let _ = mobi_to_epub(book)
                     .map(|f| {
@@ -1683,4 +2229,105 @@ pub(crate) mod mobi {
             Vec::new()
         }
     );
+
+    create_command!(
+        Diff,
+        "diff",
+        {
+            arg::CommandOptionDef {
+                command: String::from("diff"),
+                desc: "和另一本电子书比较，存在差异时返回码为1".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create(
+                        "f",
+                        "比较的电子书，支持epub和mobi",
+                        OptionType::String,
+                        true,
+                    ),
+                    OptionDef::create("json", "以json格式输出", OptionType::NoParamter, false),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::MOBI(book) = book {
+                let mut epub = mobi_to_epub(book)
+                    .unwrap_or_else(|e| exec_err!("convert mobi to epub fail, reason: {:?}", e));
+                super::diff_with(&mut epub, opts);
+            }
+        }
+    );
+
+    create_command!(
+        Search,
+        "search",
+        {
+            arg::CommandOptionDef {
+                command: String::from("search"),
+                desc: "全文搜索，输出章节、目录标题、段落序号以及上下文".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("q", "搜索内容", OptionType::String, true),
+                    OptionDef::create("r", "使用正则表达式", OptionType::NoParamter, false),
+                    OptionDef::create("c", "上下文字符数，默认20", OptionType::String, false),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::MOBI(book) = book {
+                let query = super::search_query(opts);
+                super::print_search_hits(search_mobi(book, &query));
+            }
+        }
+    );
+
+    create_command!(
+        Stats,
+        "stats",
+        {
+            arg::CommandOptionDef {
+                command: String::from("stats"),
+                desc: "统计每个章节以及全书的字数、段落、图片和预计阅读时间".to_string(),
+                support_args: 0,
+                opts: vec![
+                    OptionDef::create("json", "以json格式输出", OptionType::NoParamter, false),
+                    OptionDef::create(
+                        "cjk",
+                        "每分钟阅读的中日韩文字数，默认400",
+                        OptionType::String,
+                        false,
+                    ),
+                    OptionDef::create(
+                        "words",
+                        "每分钟阅读的单词数，默认250",
+                        OptionType::String,
+                        false,
+                    ),
+                ],
+            }
+        },
+        fn exec(
+            &self,
+            book: &mut Book,
+            _global_opts: &[ArgOption],
+            opts: &[ArgOption],
+            _args: &[String],
+        ) {
+            if let Book::MOBI(book) = book {
+                super::print_stats(&stats_mobi(book, &super::reading_speed(opts)), opts);
+            }
+        }
+    );
 }
@@ -91,6 +91,10 @@ pub enum IError {
     IncompleteEncoding,
     InvalidHexChar(char),
     Utf8ConversionError,
+    /// 正则表达式错误
+    Regex(regex::Error),
+    /// 无效的 CFI 或者 CFI 指向的位置不存在
+    Cfi(String),
     #[cfg(feature = "cache")]
     Cache(String),
     Unknown,
@@ -140,6 +144,12 @@ impl From<FromUtf8Error> for IError {
     }
 }
 
+impl From<regex::Error> for IError {
+    fn from(value: regex::Error) -> Self {
+        IError::Regex(value)
+    }
+}
+
 /// 内容类型枚举
 #[derive(Debug, Clone)]
 pub enum ContentType {
@@ -259,6 +269,10 @@ cache_struct! {
         pub(crate) publisher: Option<String>,
         /// 主题？
         pub(crate) subject: Option<String>,
+        /// 系列
+        pub(crate) series: Option<String>,
+        /// 在系列中的序号，例如 1、1.5
+        pub(crate) series_index: Option<String>,
     }
 }
 impl BookInfo {
@@ -331,6 +345,277 @@ pub fn escape_xml<'a>(raw: impl Into<Cow<'a, str>>) -> Cow<'a, str> {
     quick_xml::escape::escape(raw)
 }
 
+/// xhtml 1.1 中的命名实体，即 html 4 的实体和 apos
+const HTML_ENTITIES: [(&str, char); 253] = [
+    ("quot", '"'),
+    ("amp", '&'),
+    ("apos", '\''),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("nbsp", '\u{a0}'),
+    ("iexcl", '\u{a1}'),
+    ("cent", '\u{a2}'),
+    ("pound", '\u{a3}'),
+    ("curren", '\u{a4}'),
+    ("yen", '\u{a5}'),
+    ("brvbar", '\u{a6}'),
+    ("sect", '\u{a7}'),
+    ("uml", '\u{a8}'),
+    ("copy", '\u{a9}'),
+    ("ordf", '\u{aa}'),
+    ("laquo", '\u{ab}'),
+    ("not", '\u{ac}'),
+    ("shy", '\u{ad}'),
+    ("reg", '\u{ae}'),
+    ("macr", '\u{af}'),
+    ("deg", '\u{b0}'),
+    ("plusmn", '\u{b1}'),
+    ("sup2", '\u{b2}'),
+    ("sup3", '\u{b3}'),
+    ("acute", '\u{b4}'),
+    ("micro", '\u{b5}'),
+    ("para", '\u{b6}'),
+    ("middot", '\u{b7}'),
+    ("cedil", '\u{b8}'),
+    ("sup1", '\u{b9}'),
+    ("ordm", '\u{ba}'),
+    ("raquo", '\u{bb}'),
+    ("frac14", '\u{bc}'),
+    ("frac12", '\u{bd}'),
+    ("frac34", '\u{be}'),
+    ("iquest", '\u{bf}'),
+    ("Agrave", '\u{c0}'),
+    ("Aacute", '\u{c1}'),
+    ("Acirc", '\u{c2}'),
+    ("Atilde", '\u{c3}'),
+    ("Auml", '\u{c4}'),
+    ("Aring", '\u{c5}'),
+    ("AElig", '\u{c6}'),
+    ("Ccedil", '\u{c7}'),
+    ("Egrave", '\u{c8}'),
+    ("Eacute", '\u{c9}'),
+    ("Ecirc", '\u{ca}'),
+    ("Euml", '\u{cb}'),
+    ("Igrave", '\u{cc}'),
+    ("Iacute", '\u{cd}'),
+    ("Icirc", '\u{ce}'),
+    ("Iuml", '\u{cf}'),
+    ("ETH", '\u{d0}'),
+    ("Ntilde", '\u{d1}'),
+    ("Ograve", '\u{d2}'),
+    ("Oacute", '\u{d3}'),
+    ("Ocirc", '\u{d4}'),
+    ("Otilde", '\u{d5}'),
+    ("Ouml", '\u{d6}'),
+    ("times", '\u{d7}'),
+    ("Oslash", '\u{d8}'),
+    ("Ugrave", '\u{d9}'),
+    ("Uacute", '\u{da}'),
+    ("Ucirc", '\u{db}'),
+    ("Uuml", '\u{dc}'),
+    ("Yacute", '\u{dd}'),
+    ("THORN", '\u{de}'),
+    ("szlig", '\u{df}'),
+    ("agrave", '\u{e0}'),
+    ("aacute", '\u{e1}'),
+    ("acirc", '\u{e2}'),
+    ("atilde", '\u{e3}'),
+    ("auml", '\u{e4}'),
+    ("aring", '\u{e5}'),
+    ("aelig", '\u{e6}'),
+    ("ccedil", '\u{e7}'),
+    ("egrave", '\u{e8}'),
+    ("eacute", '\u{e9}'),
+    ("ecirc", '\u{ea}'),
+    ("euml", '\u{eb}'),
+    ("igrave", '\u{ec}'),
+    ("iacute", '\u{ed}'),
+    ("icirc", '\u{ee}'),
+    ("iuml", '\u{ef}'),
+    ("eth", '\u{f0}'),
+    ("ntilde", '\u{f1}'),
+    ("ograve", '\u{f2}'),
+    ("oacute", '\u{f3}'),
+    ("ocirc", '\u{f4}'),
+    ("otilde", '\u{f5}'),
+    ("ouml", '\u{f6}'),
+    ("divide", '\u{f7}'),
+    ("oslash", '\u{f8}'),
+    ("ugrave", '\u{f9}'),
+    ("uacute", '\u{fa}'),
+    ("ucirc", '\u{fb}'),
+    ("uuml", '\u{fc}'),
+    ("yacute", '\u{fd}'),
+    ("thorn", '\u{fe}'),
+    ("yuml", '\u{ff}'),
+    ("OElig", '\u{152}'),
+    ("oelig", '\u{153}'),
+    ("Scaron", '\u{160}'),
+    ("scaron", '\u{161}'),
+    ("Yuml", '\u{178}'),
+    ("fnof", '\u{192}'),
+    ("circ", '\u{2c6}'),
+    ("tilde", '\u{2dc}'),
+    ("Alpha", '\u{391}'),
+    ("Beta", '\u{392}'),
+    ("Gamma", '\u{393}'),
+    ("Delta", '\u{394}'),
+    ("Epsilon", '\u{395}'),
+    ("Zeta", '\u{396}'),
+    ("Eta", '\u{397}'),
+    ("Theta", '\u{398}'),
+    ("Iota", '\u{399}'),
+    ("Kappa", '\u{39a}'),
+    ("Lambda", '\u{39b}'),
+    ("Mu", '\u{39c}'),
+    ("Nu", '\u{39d}'),
+    ("Xi", '\u{39e}'),
+    ("Omicron", '\u{39f}'),
+    ("Pi", '\u{3a0}'),
+    ("Rho", '\u{3a1}'),
+    ("Sigma", '\u{3a3}'),
+    ("Tau", '\u{3a4}'),
+    ("Upsilon", '\u{3a5}'),
+    ("Phi", '\u{3a6}'),
+    ("Chi", '\u{3a7}'),
+    ("Psi", '\u{3a8}'),
+    ("Omega", '\u{3a9}'),
+    ("alpha", '\u{3b1}'),
+    ("beta", '\u{3b2}'),
+    ("gamma", '\u{3b3}'),
+    ("delta", '\u{3b4}'),
+    ("epsilon", '\u{3b5}'),
+    ("zeta", '\u{3b6}'),
+    ("eta", '\u{3b7}'),
+    ("theta", '\u{3b8}'),
+    ("iota", '\u{3b9}'),
+    ("kappa", '\u{3ba}'),
+    ("lambda", '\u{3bb}'),
+    ("mu", '\u{3bc}'),
+    ("nu", '\u{3bd}'),
+    ("xi", '\u{3be}'),
+    ("omicron", '\u{3bf}'),
+    ("pi", '\u{3c0}'),
+    ("rho", '\u{3c1}'),
+    ("sigmaf", '\u{3c2}'),
+    ("sigma", '\u{3c3}'),
+    ("tau", '\u{3c4}'),
+    ("upsilon", '\u{3c5}'),
+    ("phi", '\u{3c6}'),
+    ("chi", '\u{3c7}'),
+    ("psi", '\u{3c8}'),
+    ("omega", '\u{3c9}'),
+    ("thetasym", '\u{3d1}'),
+    ("upsih", '\u{3d2}'),
+    ("piv", '\u{3d6}'),
+    ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'),
+    ("thinsp", '\u{2009}'),
+    ("zwnj", '\u{200c}'),
+    ("zwj", '\u{200d}'),
+    ("lrm", '\u{200e}'),
+    ("rlm", '\u{200f}'),
+    ("ndash", '\u{2013}'),
+    ("mdash", '\u{2014}'),
+    ("lsquo", '\u{2018}'),
+    ("rsquo", '\u{2019}'),
+    ("sbquo", '\u{201a}'),
+    ("ldquo", '\u{201c}'),
+    ("rdquo", '\u{201d}'),
+    ("bdquo", '\u{201e}'),
+    ("dagger", '\u{2020}'),
+    ("Dagger", '\u{2021}'),
+    ("bull", '\u{2022}'),
+    ("hellip", '\u{2026}'),
+    ("permil", '\u{2030}'),
+    ("prime", '\u{2032}'),
+    ("Prime", '\u{2033}'),
+    ("lsaquo", '\u{2039}'),
+    ("rsaquo", '\u{203a}'),
+    ("oline", '\u{203e}'),
+    ("frasl", '\u{2044}'),
+    ("euro", '\u{20ac}'),
+    ("image", '\u{2111}'),
+    ("weierp", '\u{2118}'),
+    ("real", '\u{211c}'),
+    ("trade", '\u{2122}'),
+    ("alefsym", '\u{2135}'),
+    ("larr", '\u{2190}'),
+    ("uarr", '\u{2191}'),
+    ("rarr", '\u{2192}'),
+    ("darr", '\u{2193}'),
+    ("harr", '\u{2194}'),
+    ("crarr", '\u{21b5}'),
+    ("lArr", '\u{21d0}'),
+    ("uArr", '\u{21d1}'),
+    ("rArr", '\u{21d2}'),
+    ("dArr", '\u{21d3}'),
+    ("hArr", '\u{21d4}'),
+    ("forall", '\u{2200}'),
+    ("part", '\u{2202}'),
+    ("exist", '\u{2203}'),
+    ("empty", '\u{2205}'),
+    ("nabla", '\u{2207}'),
+    ("isin", '\u{2208}'),
+    ("notin", '\u{2209}'),
+    ("ni", '\u{220b}'),
+    ("prod", '\u{220f}'),
+    ("sum", '\u{2211}'),
+    ("minus", '\u{2212}'),
+    ("lowast", '\u{2217}'),
+    ("radic", '\u{221a}'),
+    ("prop", '\u{221d}'),
+    ("infin", '\u{221e}'),
+    ("ang", '\u{2220}'),
+    ("and", '\u{2227}'),
+    ("or", '\u{2228}'),
+    ("cap", '\u{2229}'),
+    ("cup", '\u{222a}'),
+    ("int", '\u{222b}'),
+    ("there4", '\u{2234}'),
+    ("sim", '\u{223c}'),
+    ("cong", '\u{2245}'),
+    ("asymp", '\u{2248}'),
+    ("ne", '\u{2260}'),
+    ("equiv", '\u{2261}'),
+    ("le", '\u{2264}'),
+    ("ge", '\u{2265}'),
+    ("sub", '\u{2282}'),
+    ("sup", '\u{2283}'),
+    ("nsub", '\u{2284}'),
+    ("sube", '\u{2286}'),
+    ("supe", '\u{2287}'),
+    ("oplus", '\u{2295}'),
+    ("otimes", '\u{2297}'),
+    ("perp", '\u{22a5}'),
+    ("sdot", '\u{22c5}'),
+    ("lceil", '\u{2308}'),
+    ("rceil", '\u{2309}'),
+    ("lfloor", '\u{230a}'),
+    ("rfloor", '\u{230b}'),
+    ("lang", '\u{2329}'),
+    ("rang", '\u{232a}'),
+    ("loz", '\u{25ca}'),
+    ("spades", '\u{2660}'),
+    ("clubs", '\u{2663}'),
+    ("hearts", '\u{2665}'),
+    ("diams", '\u{2666}'),
+];
+
+///
+/// 解析 html 中的实体引用，包括命名实体和 `&#160;` 这样的字符引用，不认识的返回 None
+///
+pub(crate) fn resolve_entity(e: &quick_xml::events::BytesRef) -> Option<char> {
+    if let Ok(Some(v)) = e.resolve_char_ref() {
+        return Some(v);
+    }
+    let name = e.decode().ok()?;
+    HTML_ENTITIES
+        .iter()
+        .find(|(k, _)| *k == name.as_ref())
+        .map(|(_, v)| *v)
+}
+
 pub struct DateTimeFormater {
     timestamp: u64,
     start_year: u64,
@@ -551,11 +836,14 @@ pub(crate) fn get_media_type(file_name: &str) -> String {
     types.insert(".webp", String::from("image/webp"));
     types.insert(".mp3", String::from("audio/mpeg"));
     types.insert(".mp4", String::from("audio/mp4"));
+    types.insert(".m4a", String::from("audio/mp4"));
+    types.insert(".smil", String::from("application/smil+xml"));
     types.insert(".css", String::from("text/css"));
     types.insert(".ttf", String::from("application/font-sfnt"));
     types.insert(".oft", String::from("application/font-sfnt"));
+    types.insert(".otf", String::from("application/font-sfnt"));
     types.insert(".woff", String::from("application/font-woff"));
-    types.insert(".woff", String::from("font/woff2"));
+    types.insert(".woff2", String::from("font/woff2"));
     types.insert(".xhtml", String::from("application/xhtml+xml"));
     types.insert(".js", String::from("application/javascript"));
     types.insert(".opf", String::from("application/x-dtbncx+xml"));
@@ -601,6 +889,120 @@ pub fn urldecode_enhanced(input: &str) -> IResult<String> {
     String::from_utf8(result).map_err(|_| IError::Utf8ConversionError)
 }
 
+///
+/// 将文本转为utf-8
+///
+/// 优先使用 BOM、xml 声明中的 encoding 以及 meta 中的 charset，
+/// 没有声明并且不是合法的utf-8时根据内容猜测编码，例如 GBK、GB18030、Big5、Shift_JIS。
+/// 转换后声明中的编码会改为 utf-8
+///
+pub fn decode_text(data: &[u8]) -> String {
+    if let Some((encoding, len)) = encoding_rs::Encoding::for_bom(data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[len..]);
+        return replace_charset(text.into_owned());
+    }
+    let declared = find_charset(data)
+        .first()
+        .and_then(|(start, end)| encoding_rs::Encoding::for_label(&data[*start..*end]));
+    let encoding = match declared {
+        Some(encoding) if encoding != encoding_rs::UTF_8 => encoding,
+        _ => {
+            if let Ok(v) = std::str::from_utf8(data) {
+                return v.to_string();
+            }
+            // 声明为utf-8，或者没有声明，但实际不是utf-8
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(data, true);
+            detector.guess(None, true)
+        }
+    };
+    let (text, _) = encoding.decode_without_bom_handling(data);
+    if encoding == encoding_rs::UTF_8 {
+        text.into_owned()
+    } else {
+        replace_charset(text.into_owned())
+    }
+}
+
+/// 把声明中的编码改为 utf-8
+fn replace_charset(mut text: String) -> String {
+    for (start, end) in find_charset(text.as_bytes()).into_iter().rev() {
+        text.replace_range(start..end, "utf-8");
+    }
+    text
+}
+
+///
+/// 查找 xml 声明中的 encoding 以及 head 中 meta 的 charset，返回编码名称所在的位置
+///
+/// 只查找开头部分，正文中出现的 charset= 不是声明
+///
+fn find_charset(data: &[u8]) -> Vec<(usize, usize)> {
+    let head = data[..data.len().min(2048)].to_ascii_lowercase();
+    let find = |v: &[u8], start: usize, end: usize| {
+        head[start..end]
+            .windows(v.len())
+            .position(|f| f == v)
+            .map(|f| f + start)
+    };
+    let mut res = Vec::new();
+    // encoding 只能出现在 xml 声明中
+    if head.starts_with(b"<?xml") {
+        if let Some(end) = find(b"?>", 0, head.len()) {
+            res.extend(find_attr_value(&head, b"encoding", 0, end));
+        }
+    }
+    // charset 只能出现在 head 的 meta 中
+    let head_end = find(b"</head", 0, head.len())
+        .or_else(|| find(b"<body", 0, head.len()))
+        .unwrap_or(head.len());
+    let mut start = 0;
+    while let Some(index) = find(b"<meta", start, head_end) {
+        let end = find(b">", index, head.len()).unwrap_or(head.len());
+        res.extend(find_attr_value(&head, b"charset", index, end));
+        start = end;
+    }
+    res
+}
+
+/// 在 [start, end) 中查找 key=value，返回 value 所在的位置
+fn find_attr_value(data: &[u8], key: &[u8], start: usize, end: usize) -> Option<(usize, usize)> {
+    let data = &data[..end];
+    let mut start = start;
+    while let Some(index) = data[start..]
+        .windows(key.len())
+        .position(|f| f == key)
+        .map(|f| f + start)
+    {
+        let mut pos = index + key.len();
+        start = pos;
+        while data.get(pos).is_some_and(|f| f.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if data.get(pos) != Some(&b'=') {
+            continue;
+        }
+        pos += 1;
+        while data
+            .get(pos)
+            .is_some_and(|f| f.is_ascii_whitespace() || *f == b'"' || *f == b'\'')
+        {
+            pos += 1;
+        }
+        let begin = pos;
+        while data
+            .get(pos)
+            .is_some_and(|f| f.is_ascii_alphanumeric() || b"-_.:".contains(f))
+        {
+            pos += 1;
+        }
+        if pos > begin {
+            return Some((begin, pos));
+        }
+    }
+    None
+}
+
 fn decode_hex_byte(c1: char, c2: char) -> IResult<u8> {
     let high = hex_char_to_value(c1)?;
     let low = hex_char_to_value(c2)?;
@@ -678,12 +1080,88 @@ pub fn get_css_content_url<T: AsRef<str> + ?Sized>(css: &T) -> Vec<&str> {
 
     res
 }
+/// 转换为json字符串，包括两边的引号
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// SHA-1 摘要，用于字体混淆的密钥生成
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // 填充：追加 0x80，然后补 0 直到长度 ≡ 56 (mod 64)，最后是 64 位的原始比特长度
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, v) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*v);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut out = [0u8; 20];
+    for (i, v) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
 pub(crate) mod info {
     include!(concat!(env!("OUT_DIR"), "/version.rs"));
 }
 #[cfg(test)]
 pub(crate) mod tests {
-    use crate::common::{get_css_content_url, urldecode_enhanced, DateTimeFormater};
+    use crate::common::{
+        decode_text, get_css_content_url, json_string, sha1, urldecode_enhanced, DateTimeFormater,
+    };
 
     pub fn get_req_mem(url: &str) -> Vec<u8> {
         get_req(url).send().unwrap().bytes().unwrap().to_vec()
@@ -801,6 +1279,29 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn test_sha1() {
+        fn hex(v: [u8; 20]) -> String {
+            v.iter().map(|f| format!("{:02x}", f)).collect()
+        }
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", hex(sha1(b"")));
+        assert_eq!(
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            hex(sha1(b"abc"))
+        );
+        assert_eq!(
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(r#""a\"b\\c\n\u0001""#, json_string("a\"b\\c\n\u{1}"));
+    }
+
     #[test]
     fn decode_url() {
         assert_eq!(
@@ -851,4 +1352,59 @@ pub(crate) mod tests {
         );
         assert_eq!(vec!["../Images/contents.jpg"], v);
     }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!("<p>中文</p>", decode_text("<p>中文</p>".as_bytes()));
+
+        // 声明的编码
+        let (v, _, _) = encoding_rs::GBK
+            .encode(r#"<?xml version="1.0" encoding="gbk"?><html><p>中文</p></html>"#);
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="utf-8"?><html><p>中文</p></html>"#,
+            decode_text(&v)
+        );
+        let (v, _, _) = encoding_rs::BIG5.encode(
+            r#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=big5"/></head><p>繁體中文</p></html>"#,
+        );
+        assert_eq!(
+            r#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=utf-8"/></head><p>繁體中文</p></html>"#,
+            decode_text(&v)
+        );
+        let (v, _, _) = encoding_rs::SHIFT_JIS.encode(
+            r#"<html><head><meta charset='Shift_JIS'/></head><p>日本語のテキスト</p></html>"#,
+        );
+        assert_eq!(
+            "<html><head><meta charset='utf-8'/></head><p>日本語のテキスト</p></html>",
+            decode_text(&v)
+        );
+
+        // 没有声明
+        let text = "<p>第一章 天下大势，分久必合，合久必分。周末七国分争，并入于秦。</p>";
+        let (v, _, _) = encoding_rs::GB18030.encode(text);
+        assert_eq!(text, decode_text(&v));
+        let text = "<p>第一章 話說天下大勢，分久必合，合久必分。周末七國分爭，並入於秦。</p>";
+        let (v, _, _) = encoding_rs::BIG5.encode(text);
+        assert_eq!(text, decode_text(&v));
+
+        // 声明错误
+        let (v, _, _) = encoding_rs::GBK.encode(
+            r#"<?xml version="1.0" encoding="utf-8"?><p>天下大势，分久必合，合久必分。</p>"#,
+        );
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="utf-8"?><p>天下大势，分久必合，合久必分。</p>"#,
+            decode_text(&v)
+        );
+
+        // 正文中的 charset 不是声明
+        let text = r#"<html><head><title>编码</title></head><body><p>使用 charset=gbk 或者 <code>&lt;meta charset="big5"&gt;</code></p></body></html>"#;
+        assert_eq!(text, decode_text(text.as_bytes()));
+        let text = r#"<?xml version="1.0"?><html><head></head><p>encoding="gbk"</p></html>"#;
+        assert_eq!(text, decode_text(text.as_bytes()));
+
+        // BOM
+        let mut v = vec![0xFF, 0xFE];
+        v.extend("<p>中文</p>".encode_utf16().flat_map(|f| f.to_le_bytes()));
+        assert_eq!("<p>中文</p>", decode_text(&v));
+    }
 }
@@ -1,54 +1,285 @@
-//! 修改现有epub文件，目前仅支持修改元数据
+//! 修改现有epub文件，支持修改元数据以及追加章节
 //!
 //!
 use std::path::Path;
 
 use super::{
-    common, core,
+    common, convert, core,
     html::{to_opf, to_toc_xml},
+    reader::{get_ncx_location, get_opf_location},
     writer::{self, EpubWriterTrait},
 };
 use crate::prelude::*;
 
+///
+/// 向已有的epub追加章节、资源以及目录
+///
+/// 原有的文件直接复制压缩后的数据，不会解压，也不会读取原有章节的内容，
+/// 只重新生成 opf、toc.ncx 和 nav.xhtml。先写入同目录下的临时文件，成功后通过重命名替换原文件
+///
+/// 没有添加目录时使用新章节的标题生成
+///
+/// ```no_run
+/// use iepub::prelude::*;
+///
+/// appender::EpubAppender::new()
+///     .add_chapter(
+///         EpubHtml::default()
+///             .with_title("第十章")
+///             .with_file_name("10.xhtml")
+///             .with_data("<p>内容</p>".as_bytes().to_vec()),
+///     )
+///     .append("book.epub")
+///     .unwrap();
+/// ```
+///
+pub struct EpubAppender {
+    chapters: Vec<EpubHtml>,
+    assets: Vec<EpubAssets>,
+    nav: Vec<EpubNav>,
+    append_title: bool,
+}
+
+impl Default for EpubAppender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EpubAppender {
+    pub fn new() -> Self {
+        EpubAppender {
+            chapters: Vec::new(),
+            assets: Vec::new(),
+            nav: Vec::new(),
+            append_title: true,
+        }
+    }
+
+    /// 是否添加标题，默认true
+    pub fn append_title(mut self, append_title: bool) -> Self {
+        self.append_title = append_title;
+        self
+    }
+
+    pub fn add_chapter(mut self, chapter: EpubHtml) -> Self {
+        self.chapters.push(chapter);
+        self
+    }
+
+    ///
+    /// 添加资源文件
+    ///
+    /// 追加的字体不会被混淆
+    ///
+    pub fn add_assets(mut self, assets: EpubAssets) -> Self {
+        self.assets.push(assets);
+        self
+    }
+
+    pub fn add_nav(mut self, nav: EpubNav) -> Self {
+        self.nav.push(nav);
+        self
+    }
+
+    ///
+    /// 追加到文件
+    ///
+    /// [file] 原文件路径
+    ///
+    pub fn append<P: AsRef<Path>>(self, file: P) -> IResult<()> {
+        let file = file.as_ref();
+        let name = file
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // 和原文件在同一目录，保证可以直接重命名
+        let temp_file = file.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        match self.write_to(file, temp_file.as_path()) {
+            Ok(_) => {
+                std::fs::rename(&temp_file, file)?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp_file);
+                Err(e)
+            }
+        }
+    }
+
+    fn write_to(self, file: &Path, temp_file: &Path) -> IResult<()> {
+        let mut book = read_from_file(file)?;
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(file)?)?;
+        // nav.xhtml、封面页和封面图片会重新生成
+        convert::remove_generated(&mut book);
+        for name in self
+            .chapters
+            .iter()
+            .map(|f| f.file_name())
+            .chain(self.assets.iter().map(|f| f.file_name()))
+        {
+            if book.get_chapter(name).is_some() || book.get_assets(name).is_some() {
+                return Err(IError::InvalidArchive(std::borrow::Cow::from(format!(
+                    "{name} already exists"
+                ))));
+            }
+        }
+
+        let mut writer = writer::EpubWriter::new(std::fs::File::create(temp_file)?)
+            .with_append_title(self.append_title);
+        let root = writer.root(&book);
+        let opf = writer.opf_path(&book);
+
+        // 需要重新生成的文件
+        let mut generated = vec![
+            opf.clone(),
+            format!("{}{}", root, common::TOC.replace(common::EPUB, "")),
+            format!("{}{}", root, common::NAV.replace(common::EPUB, "")),
+        ];
+        if let Some(toc) = book.toc() {
+            generated.push(format!("{}{}", root, toc.file_name()));
+        }
+        // 封面页和封面图片由 write_cover 写入
+        if let Some(cover) = book.cover() {
+            generated.push(format!("{}{}", root, cover.file_name()));
+            generated.push(format!(
+                "{}{}",
+                root,
+                common::COVER.replace(common::EPUB, "")
+            ));
+        }
+
+        // 原有文件直接复制
+        for i in 0..zip.len() {
+            let f = zip.by_index_raw(i)?;
+            if generated.iter().any(|g| g == f.name()) {
+                continue;
+            }
+            if let writer::EpubOutput::Zip(out) = &mut writer.inner {
+                out.raw_copy_file(f)?;
+            }
+        }
+
+        let dir = book.direction.clone();
+        let epub2 = book.is_strict_epub2();
+        // 和生成的目录一样带上序号，接着原有目录编号
+        let start = book.nav().len();
+        let nav: Vec<EpubNav> = self
+            .chapters
+            .iter()
+            .filter(|f| self.nav.is_empty() && !f.title().is_empty())
+            .enumerate()
+            .map(|(index, f)| {
+                EpubNav::default()
+                    .with_title(format!("{}. {}", start + index + 1, f.title()))
+                    .with_file_name(f.file_name())
+            })
+            .collect();
+        for mut chap in self.chapters {
+            writer.write_chapter(root.as_str(), &mut chap, &dir, epub2)?;
+            book.add_chapter(chap);
+        }
+        for assets in self.assets {
+            if let Ok(mut reader) = assets.reader() {
+                writer.write_stream(
+                    format!("{}{}", root, assets.file_name()).as_str(),
+                    &mut reader,
+                )?;
+            }
+            book.add_assets(assets);
+        }
+        for ele in self.nav.into_iter().chain(nav) {
+            book.add_nav(ele);
+        }
+
+        book.set_last_modify(crate::common::DateTimeFormater::default().default_format());
+        writer.write_cover(&mut book)?;
+        writer.write_file(
+            opf.as_str(),
+            to_opf(&mut book, crate::common::info::PKG_NAME).as_bytes(),
+        )?;
+        writer.write_nav(&mut book)?;
+        if let writer::EpubOutput::Zip(out) = writer.inner {
+            out.finish()?;
+        }
+        Ok(())
+    }
+}
+
 /// 修改电子书元数据
 ///
 /// [file] 原文件路径
 ///
 pub fn write_metadata<P: AsRef<Path> + ?Sized>(file: &P, book: &mut EpubBook) -> IResult<()> {
+    // 同一进程内可能多次调用，临时文件名需要区分
+    static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
     let dir = std::env::temp_dir();
-    let temp_file = dir.join(format!("{}.update.epub", std::process::id()));
+    let temp_file = dir.join(format!(
+        "{}.{}.update.epub",
+        std::process::id(),
+        COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
     {
         let mut reader = zip::ZipArchive::new(std::fs::File::open(file)?)?;
+        // 从 container.xml 中找到实际的 opf 位置
+        let mut container = String::new();
+        std::io::Read::read_to_string(
+            &mut reader.by_name("META-INF/container.xml")?,
+            &mut container,
+        )?;
+        let opf = get_opf_location(container.as_str())?;
+        if opf.is_empty() {
+            return Err(IError::InvalidArchive(std::borrow::Cow::from("has no opf")));
+        }
+        let root = crate::path::Path::system(opf.as_str()).pop();
+        // 新的 opf 中 ncx 固定为 toc.ncx
+        let toc = root
+            .join(common::TOC.replace(common::EPUB, "").as_str())
+            .to_str();
+        // 原有的 ncx 由 spine@toc 指定，不一定是 toc.ncx，需要一起去掉
+        let origin_toc = {
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut reader.by_name(opf.as_str())?, &mut content)?;
+            get_ncx_location(content.as_str()).map(|f| root.join(f.as_str()).to_str())
+        };
         let mut fs = std::fs::OpenOptions::new()
             .create_new(true)
             .truncate(true)
             .write(true)
             .open(temp_file.display().to_string().as_str())?;
         let mut writer = writer::EpubWriter::new(&mut fs);
-        let index = reader.index_for_name(common::OPF).unwrap_or(usize::MAX);
-        let index2 = reader.index_for_name(common::TOC).unwrap_or(usize::MAX);
+        let index = reader.index_for_name(opf.as_str()).unwrap_or(usize::MAX);
+        let index2 = reader.index_for_name(toc.as_str()).unwrap_or(usize::MAX);
+        let index3 = origin_toc
+            .and_then(|f| reader.index_for_name(f.as_str()))
+            .unwrap_or(usize::MAX);
 
         // 首先写入元数据文件
         writer.write_file(
-            common::OPF,
+            opf.as_str(),
             to_opf(book, crate::common::info::PKG_NAME).as_bytes(),
         )?;
 
         // toc文件也需要重写一份
-        writer.write_file(common::TOC, to_toc_xml(book.title(), book.nav()).as_bytes())?;
+        writer.write_file(
+            toc.as_str(),
+            to_toc_xml(book.title(), book.nav(), book.page_list()).as_bytes(),
+        )?;
 
         // 遍历其他文件
 
         for i in 0..reader.len() {
-            if i == index || i == index2 {
+            if i == index || i == index2 || i == index3 {
                 continue;
             }
             let options = zip::write::SimpleFileOptions::default()
                 .compression_method(zip::CompressionMethod::Stored)
                 .unix_permissions(0o755);
             let mut f = reader.by_index(i)?;
-            writer.inner.start_file(f.name(), options)?;
-            std::io::copy(&mut f, &mut writer.inner)?;
+            if let writer::EpubOutput::Zip(zip) = &mut writer.inner {
+                zip.start_file(f.name(), options)?;
+                std::io::copy(&mut f, zip)?;
+            }
         }
     }
 
@@ -62,6 +293,8 @@ pub fn write_metadata<P: AsRef<Path> + ?Sized>(file: &P, book: &mut EpubBook) ->
         .open(file)?;
     // 降级到copy
     std::io::copy(&mut from, &mut to)?;
+    drop(from);
+    let _ = std::fs::remove_file(&temp_file);
 
     Ok(())
 }
@@ -105,4 +338,239 @@ mod tests {
         assert_eq!(book.title(), nb.title());
         let _ = std::fs::remove_file("temp.epub");
     }
+
+    #[test]
+    fn test_appender_opf_path() {
+        let file = "temp_opf_path.epub";
+        let mut book = EpubBuilder::new()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("ok")
+                    .with_file_name("Text/0.xhtml")
+                    .with_data("html".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        EpubWriter::new(std::fs::File::create(file).unwrap())
+            .with_options(EpubWriteOptions::default().with_opf_path("OPS/package.opf"))
+            .write(&mut book)
+            .unwrap();
+
+        book.set_title("修改后的名字");
+        write_metadata(file, &mut book).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(file).unwrap()).unwrap();
+        assert!(zip.by_name("OEBPS/content.opf").is_err());
+        assert!(zip.by_name("OPS/toc.ncx").is_ok());
+        drop(zip);
+        let nb = read_from_file(file).unwrap();
+        assert_eq!("修改后的名字", nb.title());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_appender_ncx_path() {
+        use std::io::{Read, Write};
+
+        let file = "temp_ncx_path.epub";
+        let mut book = EpubBuilder::new()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("0.xhtml")
+                    .with_data("html".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        // ncx 改为其他位置
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::fs::File::create(file).unwrap());
+        for i in 0..zip.len() {
+            let mut f = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            f.read_to_end(&mut content).unwrap();
+            let name = match f.name() {
+                "OEBPS/toc.ncx" => "OEBPS/Misc/book.ncx".to_string(),
+                "OEBPS/content.opf" => {
+                    content = String::from_utf8(content)
+                        .unwrap()
+                        .replace(r#"href="toc.ncx""#, r#"href="Misc/book.ncx""#)
+                        .into_bytes();
+                    f.name().to_string()
+                }
+                v => v.to_string(),
+            };
+            out.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            out.write_all(&content).unwrap();
+        }
+        out.finish().unwrap();
+
+        book.set_title("修改后的名字");
+        write_metadata(file, &mut book).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(file).unwrap()).unwrap();
+        assert!(zip.by_name("OEBPS/Misc/book.ncx").is_err());
+        let mut toc = String::new();
+        zip.by_name("OEBPS/toc.ncx")
+            .unwrap()
+            .read_to_string(&mut toc)
+            .unwrap();
+        assert!(toc.contains("修改后的名字"));
+        drop(zip);
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_append_chapters() {
+        use super::EpubAppender;
+        use std::io::Read;
+
+        let file = "temp_append.epub";
+        EpubBuilder::new()
+            .with_title("书名")
+            .with_version("3.0")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("text/0.xhtml")
+                    .with_data("<p>一</p><svg></svg>".as_bytes().to_vec()),
+            )
+            .file(file)
+            .unwrap();
+        let origin = {
+            let mut zip = zip::ZipArchive::new(std::fs::File::open(file).unwrap()).unwrap();
+            let mut v = String::new();
+            zip.by_name("OEBPS/text/0.xhtml")
+                .unwrap()
+                .read_to_string(&mut v)
+                .unwrap();
+            v
+        };
+
+        EpubAppender::new()
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第二章")
+                    .with_file_name("text/1.xhtml")
+                    .with_data("<p>二</p>".as_bytes().to_vec()),
+            )
+            .add_assets(
+                EpubAssets::default()
+                    .with_file_name("images/1.png")
+                    .with_data(vec![1, 2, 3]),
+            )
+            .append(file)
+            .unwrap();
+
+        // 文件名重复
+        assert!(EpubAppender::new()
+            .add_chapter(EpubHtml::default().with_file_name("text/1.xhtml"))
+            .append(file)
+            .is_err());
+        assert!(
+            !std::path::Path::new(format!(".{}.{}.tmp", file, std::process::id()).as_str())
+                .exists()
+        );
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(file).unwrap()).unwrap();
+        assert_eq!("mimetype", zip.by_index(0).unwrap().name());
+        let mut v = String::new();
+        zip.by_name("OEBPS/text/0.xhtml")
+            .unwrap()
+            .read_to_string(&mut v)
+            .unwrap();
+        // 原有章节原样保留
+        assert_eq!(origin, v);
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        // 没有读取的章节保留原有的 properties
+        assert!(opf.contains(
+            r#"<item href="text/0.xhtml" id="chap_0" media-type="application/xhtml+xml" properties="svg"/>"#
+        ));
+        assert_eq!(1, opf.matches(r#"href="nav.xhtml""#).count());
+        drop(zip);
+
+        let mut book = read_from_file(file).unwrap();
+        let titles: Vec<&str> = book.nav().map(|f| f.title()).collect();
+        assert_eq!(vec!["1. 第一章", "2. 第二章"], titles);
+        assert!(book
+            .get_chapter_mut("text/1.xhtml")
+            .unwrap()
+            .string_data()
+            .contains("<p>二</p>"));
+        assert_eq!(
+            Some([1, 2, 3].as_slice()),
+            book.get_assets_mut("images/1.png").unwrap().data_mut()
+        );
+        let _ = std::fs::remove_file(file);
+    }
+    #[test]
+    fn test_append_with_cover() {
+        use super::EpubAppender;
+
+        let file = "temp_append_cover.epub";
+        let mut book = EpubBuilder::new()
+            .with_title("书名")
+            .with_identifier("urn:uuid:12345678-9abc-def0-1234-56789abcdef0")
+            .with_version("3.0")
+            .cover("cover.jpg", vec![1, 2, 3])
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("0.xhtml")
+                    .with_data("<p>一</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        book.set_language("zh");
+        EpubWriter::write_to_file(file, &mut book, true).unwrap();
+        let origin = validate_file(file).unwrap();
+
+        for i in 1..3 {
+            EpubAppender::new()
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_title(format!("第{}章", i + 1))
+                        .with_file_name(format!("{i}.xhtml"))
+                        .with_data(format!("<p>{i}</p>").into_bytes()),
+                )
+                .append(file)
+                .unwrap();
+        }
+
+        // 追加后不会出现新的问题，例如重复的 manifest
+        assert_eq!(
+            origin.iter().map(|f| f.message()).collect::<Vec<&str>>(),
+            validate_file(file)
+                .unwrap()
+                .iter()
+                .map(|f| f.message())
+                .collect::<Vec<&str>>()
+        );
+        assert!(origin.iter().all(|f| !f.message().contains("重复")));
+        let book = read_from_file(file).unwrap();
+        let chapters: Vec<&str> = book.chapters().map(|f| f.file_name()).collect();
+        assert_eq!(
+            vec!["cover.xhtml", "nav.xhtml", "0.xhtml", "1.xhtml", "2.xhtml"],
+            chapters
+        );
+        // 多次追加时目录序号连续
+        let titles: Vec<&str> = book.nav().map(|f| f.title()).collect();
+        assert_eq!(vec!["1. 第一章", "2. 第2章", "3. 第3章"], titles);
+        assert_eq!("cover.jpg", book.cover().unwrap().file_name());
+        assert_eq!(
+            1,
+            book.assets()
+                .filter(|f| f.file_name() == "cover.jpg")
+                .count()
+        );
+        let _ = std::fs::remove_file(file);
+    }
 }
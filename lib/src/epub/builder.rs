@@ -22,6 +22,12 @@ pub struct EpubBuilder {
     /// 字体文件内容
     /// 用于生成封面图片
     font_byte: Option<Vec<u8>>,
+    /// 输出配置
+    write_options: EpubWriteOptions,
+    /// 固定布局的页面尺寸
+    page_size: Option<(u32, u32)>,
+    /// 通过 add_image_page 添加的章节，生成时自动设置跨页位置
+    image_pages: Vec<String>,
 }
 
 impl Default for EpubBuilder {
@@ -40,6 +46,9 @@ impl EpubBuilder {
             auto_gen_cover: false,
             font: None,
             font_byte: None,
+            write_options: EpubWriteOptions::default(),
+            page_size: None,
+            image_pages: Vec::new(),
         }
     }
     /// 是否添加标题，默认true
@@ -90,6 +99,69 @@ impl EpubBuilder {
         self.book.set_subject(subject);
         self
     }
+    pub fn with_language<T: AsRef<str>>(mut self, language: T) -> Self {
+        self.book.set_language(language);
+        self
+    }
+
+    ///
+    /// 添加标题，例如副标题
+    ///
+    pub fn add_title(mut self, title: EpubTitle) -> Self {
+        self.book.add_title(title);
+        self
+    }
+
+    ///
+    /// 添加标识，第一个标识会作为 unique-identifier
+    ///
+    pub fn add_identifier(mut self, identifier: EpubIdentifier) -> Self {
+        self.book.add_identifier(identifier);
+        self
+    }
+
+    ///
+    /// 添加作者
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// EpubBuilder::default()
+    ///     .add_creator(EpubPerson::new("作者").with_role("aut"))
+    ///     .add_creator(EpubPerson::new("译者").with_role("trl"));
+    /// ```
+    ///
+    pub fn add_creator(mut self, creator: EpubPerson) -> Self {
+        self.book.add_creator(creator);
+        self
+    }
+
+    pub fn add_contributor(mut self, contributor: EpubPerson) -> Self {
+        self.book.add_contributor(contributor);
+        self
+    }
+
+    pub fn add_subject<T: Into<String>>(mut self, subject: T) -> Self {
+        self.book.add_subject(subject);
+        self
+    }
+
+    ///
+    /// 设置系列，写入时同时生成 belongs-to-collection 和 calibre:series
+    ///
+    pub fn with_series<T: AsRef<str>>(mut self, series: T) -> Self {
+        self.book.set_series(series);
+        self
+    }
+
+    ///
+    /// 设置在系列中的序号，例如 1、1.5
+    ///
+    pub fn with_series_index<T: AsRef<str>>(mut self, index: T) -> Self {
+        self.book.set_series_index(index);
+        self
+    }
 
     pub fn with_last_modify<T: AsRef<str>>(mut self, last_modify: T) -> Self {
         self.book.set_last_modify(last_modify);
@@ -117,11 +189,101 @@ impl EpubBuilder {
         self.font_byte = Some(font);
         self
     }
+    ///
+    /// 设置输出配置，例如压缩方式、可重现输出
+    ///
+    pub fn with_write_options(mut self, options: EpubWriteOptions) -> Self {
+        self.write_options = options;
+        self
+    }
     /// 设置方向
     pub fn with_direction(mut self, dir: Direction) -> Self {
         self.book.set_direction(dir);
         self
     }
+    ///
+    /// 设置为固定布局，漫画、绘本常用
+    ///
+    /// 固定布局需要 epub3，会同时把版本设置为 3.0，并且不再给章节添加标题
+    ///
+    /// [width] [height] 页面尺寸，用于 add_image_page 生成的页面
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// EpubBuilder::default()
+    ///     .with_fixed_layout(1200, 1600)
+    ///     .with_spread(RenditionSpread::Landscape)
+    ///     .with_direction(Direction::RTL)
+    ///     .add_image_page("images/1.jpg", vec![])
+    ///     .add_image_page("images/2.jpg", vec![]);
+    /// ```
+    ///
+    pub fn with_fixed_layout(mut self, width: u32, height: u32) -> Self {
+        self.book.set_layout(Some(RenditionLayout::PrePaginated));
+        self.book.set_version("3.0");
+        self.page_size = Some((width, height));
+        self.append_title = false;
+        self
+    }
+
+    ///
+    /// 设置屏幕方向
+    ///
+    pub fn with_orientation(mut self, orientation: RenditionOrientation) -> Self {
+        self.book.set_orientation(Some(orientation));
+        self
+    }
+
+    ///
+    /// 设置何时显示双页
+    ///
+    pub fn with_spread(mut self, spread: RenditionSpread) -> Self {
+        self.book.set_spread(Some(spread));
+        self
+    }
+
+    ///
+    /// 添加图片页，生成一个用 svg 包裹图片的章节
+    ///
+    /// 页面尺寸使用 with_fixed_layout 设置的尺寸，需要先调用；
+    /// 生成时会根据阅读方向交替设置 page-spread-left、page-spread-right，已经手动设置的不受影响
+    ///
+    /// [file_name] 图片在epub中的文件名
+    ///
+    pub fn add_image_page<T: Into<String>>(mut self, file_name: T, data: Vec<u8>) -> Self {
+        let file_name = file_name.into();
+        let chap_name = format!("page_{:04}.xhtml", self.image_pages.len() + 1);
+        let (svg_size, image_size) = match self.page_size {
+            Some((width, height)) => (
+                format!(r#" viewBox="0 0 {width} {height}""#),
+                format!(r#" width="{width}" height="{height}""#),
+            ),
+            None => (String::new(), r#" width="100%" height="100%""#.to_string()),
+        };
+        let body = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" width="100%" height="100%"{svg_size}><image{image_size} xlink:href="{}"/></svg>"#,
+            escape_xml(&file_name)
+        );
+        let mut chap = EpubHtml::default()
+            .with_file_name(chap_name.as_str())
+            .with_title(format!("{}", self.image_pages.len() + 1))
+            .with_css("html,body{margin:0;padding:0;}")
+            .with_data(body.into_bytes());
+        if let Some((width, height)) = self.page_size {
+            chap.set_viewport(width, height);
+        }
+        self.book.add_chapter(chap);
+        self.book.add_assets(
+            EpubAssets::default()
+                .with_file_name(file_name)
+                .with_data(data),
+        );
+        self.image_pages.push(chap_name);
+        self
+    }
+
     ///
     /// 添加 metadata
     ///
@@ -174,6 +336,123 @@ impl EpubBuilder {
         self
     }
 
+    ///
+    /// 设置章节是否在默认阅读顺序中，为 false 时写入 linear="no"
+    ///
+    /// [file_name] 已添加章节的文件名
+    ///
+    pub fn with_chapter_linear<T: AsRef<str>>(mut self, file_name: T, linear: bool) -> Self {
+        if let Some(chap) = self.book.get_chapter_mut(file_name) {
+            chap.set_linear(linear);
+        }
+        self
+    }
+
+    ///
+    /// 设置章节的跨页位置，漫画、固定布局书籍常用
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// EpubBuilder::default()
+    ///     .add_chapter(EpubHtml::default().with_file_name("1.xhtml"))
+    ///     .with_chapter_page_spread("1.xhtml", PageSpread::Right);
+    /// ```
+    ///
+    pub fn with_chapter_page_spread<T: AsRef<str>>(
+        mut self,
+        file_name: T,
+        spread: PageSpread,
+    ) -> Self {
+        if let Some(chap) = self.book.get_chapter_mut(file_name) {
+            chap.set_page_spread(Some(spread));
+        }
+        self
+    }
+
+    ///
+    /// 给章节在 spine 中的 itemref 添加 properties，例如 rendition:layout-pre-paginated
+    ///
+    pub fn add_chapter_spine_property<T: AsRef<str>, P: Into<String>>(
+        mut self,
+        file_name: T,
+        property: P,
+    ) -> Self {
+        if let Some(chap) = self.book.get_chapter_mut(file_name) {
+            chap.add_spine_property(property);
+        }
+        self
+    }
+
+    ///
+    /// 给章节添加媒体覆盖，生成时写入 smil 文件以及 media:duration
+    ///
+    /// 媒体覆盖需要 epub3，会同时把版本设置为 3.0；音频文件需要通过 add_assets 添加
+    ///
+    /// [file_name] 已添加章节的文件名
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// use std::time::Duration;
+    /// EpubBuilder::default()
+    ///     .add_chapter(EpubHtml::default().with_file_name("1.xhtml"))
+    ///     .add_assets("audio/1.mp3", vec![])
+    ///     .add_media_overlay(
+    ///         "1.xhtml",
+    ///         EpubMediaOverlay::default()
+    ///             .with_clip(EpubClip::new("p1", "audio/1.mp3", Duration::ZERO, Duration::from_secs(2)))
+    ///             .with_clip(EpubClip::new("p2", "audio/1.mp3", Duration::from_secs(2), Duration::from_secs(5))),
+    ///     )
+    ///     .with_media_active_class("-epub-media-overlay-active");
+    /// ```
+    ///
+    pub fn add_media_overlay<T: AsRef<str>>(
+        mut self,
+        file_name: T,
+        overlay: EpubMediaOverlay,
+    ) -> Self {
+        if let Some(chap) = self.book.get_chapter_mut(file_name) {
+            chap.set_media_overlay(Some(overlay));
+            self.book.set_version("3.0");
+        }
+        self
+    }
+
+    ///
+    /// 设置媒体覆盖播放时当前元素的 class
+    ///
+    pub fn with_media_active_class<T: AsRef<str>>(mut self, class: T) -> Self {
+        self.book.set_media_active_class(class);
+        self
+    }
+
+    ///
+    /// 添加地标，例如正文开始的位置
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// EpubBuilder::default()
+    ///     .add_landmark(EpubLandmark::new("bodymatter", "1.xhtml").with_title("正文"));
+    /// ```
+    ///
+    pub fn add_landmark(mut self, landmark: EpubLandmark) -> Self {
+        self.book.add_landmark(landmark);
+        self
+    }
+
+    ///
+    /// 添加纸质书页码
+    ///
+    pub fn add_page(mut self, page: EpubPage) -> Self {
+        self.book.add_page(page);
+        self
+    }
+
     ///
     /// 添加目录导航
     ///
@@ -205,8 +484,28 @@ impl EpubBuilder {
         }
     }
 
+    ///
+    /// 给图片页交替设置跨页位置，从左往右时第一页在右，从右往左时第一页在左
+    ///
+    fn gen_page_spread(&mut self) {
+        let rtl = matches!(self.book.direction, Some(Direction::RTL));
+        for (index, name) in self.image_pages.iter().enumerate() {
+            if let Some(chap) = self.book.get_chapter_mut(name) {
+                if chap.page_spread().is_none() {
+                    chap.set_page_spread(Some(if (index % 2 == 0) != rtl {
+                        PageSpread::Right
+                    } else {
+                        PageSpread::Left
+                    }));
+                }
+            }
+        }
+    }
+
     fn gen_last_modify(&mut self) {
-        if self.book.last_modify().is_none() {
+        if let Some(modified) = self.write_options.modified() {
+            self.book.set_last_modify(modified);
+        } else if self.book.last_modify().is_none() && !self.write_options.reproducible() {
             self.book
                 .set_last_modify(crate::common::DateTimeFormater::default().default_format());
         }
@@ -243,6 +542,7 @@ impl EpubBuilder {
     pub fn book(mut self) -> IResult<EpubBook> {
         self.gen_last_modify();
         self.gen_nav();
+        self.gen_page_spread();
         self.gen_cover()?;
         Ok(self.book)
     }
@@ -253,6 +553,7 @@ impl EpubBuilder {
     pub fn file<T: AsRef<Path>>(mut self, file: T) -> IResult<()> {
         self.gen_last_modify();
         self.gen_nav();
+        self.gen_page_spread();
         self.gen_cover()?;
 
         std::fs::OpenOptions::new()
@@ -262,7 +563,11 @@ impl EpubBuilder {
             .open(file)
             .map_or_else(
                 |e| Err(IError::Io(e)),
-                |f| Ok(EpubWriter::new(f).with_append_title(self.append_title)),
+                |f| {
+                    Ok(EpubWriter::new(f)
+                        .with_append_title(self.append_title)
+                        .with_options(self.write_options.clone()))
+                },
             )
             .and_then(|mut w| w.write(&mut self.book))
     }
@@ -273,10 +578,12 @@ impl EpubBuilder {
     pub fn mem(mut self) -> IResult<Vec<u8>> {
         self.gen_last_modify();
         self.gen_nav();
+        self.gen_page_spread();
         self.gen_cover()?;
         let mut v = std::io::Cursor::new(Vec::new());
         EpubWriter::new(&mut v)
             .with_append_title(self.append_title)
+            .with_options(self.write_options.clone())
             .write(&mut self.book)?;
 
         Ok(v.into_inner())
@@ -370,4 +677,131 @@ mod tests {
 
             assert_eq!(2,book.nav().len());
     }
+
+    #[test]
+    fn test_write_options() {
+        use crate::prelude::{read_from_vec, EpubCompression, EpubWriteOptions};
+        use std::io::Read;
+
+        fn create() -> EpubBuilder {
+            EpubBuilder::default()
+                .with_title("书名")
+                .with_identifier("isbn")
+                .with_write_options(
+                    EpubWriteOptions::default()
+                        .with_compression(EpubCompression::Deflate(Some(9)))
+                        .with_reproducible(true)
+                        .with_modified("2024-06-28T03:07:07Z"),
+                )
+                .cover("cover.jpg", vec![1, 2, 3])
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_file_name("0.xhtml")
+                        .with_title("第一章")
+                        .with_data("<p>锻炼</p>".repeat(100).as_bytes().to_vec()),
+                )
+                .add_assets("1.css", "p{color:red}".repeat(100).as_bytes().to_vec())
+        }
+
+        let v1 = create().mem().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let v2 = create().mem().unwrap();
+        assert_eq!(v1, v2);
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(v1.clone())).unwrap();
+        assert_eq!("mimetype", zip.by_index(0).unwrap().name());
+        assert_eq!(
+            zip::CompressionMethod::Stored,
+            zip.by_name("mimetype").unwrap().compression()
+        );
+        assert_eq!(
+            zip::CompressionMethod::Stored,
+            zip.by_name("OEBPS/cover.jpg").unwrap().compression()
+        );
+        assert_eq!(
+            zip::CompressionMethod::Deflated,
+            zip.by_name("OEBPS/0.xhtml").unwrap().compression()
+        );
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains("2024-06-28T03:07:07Z"));
+
+        let book = read_from_vec(v1).unwrap();
+        assert_eq!(Some("2024-06-28T03:07:07Z"), book.last_modify());
+        assert_eq!("书名", book.title());
+    }
+
+    #[test]
+    fn test_fixed_layout() {
+        use crate::prelude::*;
+        use std::io::Read;
+
+        let v = EpubBuilder::default()
+            .with_title("漫画")
+            .with_identifier("isbn")
+            .with_fixed_layout(1200, 1600)
+            .with_orientation(RenditionOrientation::Portrait)
+            .with_spread(RenditionSpread::Landscape)
+            .with_direction(Direction::RTL)
+            .add_image_page("images/1.jpg", vec![1, 2, 3])
+            .add_image_page("images/2.jpg", vec![4, 5, 6])
+            .add_image_page("images/3.jpg", vec![7, 8, 9])
+            .with_chapter_page_spread("page_0003.xhtml", PageSpread::Center)
+            .mem()
+            .unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(v.clone())).unwrap();
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains(r#"version="3.0""#));
+        assert!(opf.contains(r#"<meta property="rendition:layout">pre-paginated</meta>"#));
+        assert!(opf.contains(
+            r#"<item href="page_0001.xhtml" id="chap_0" media-type="application/xhtml+xml" properties="svg"/>"#
+        ));
+        let mut html = String::new();
+        zip.by_name("OEBPS/page_0001.xhtml")
+            .unwrap()
+            .read_to_string(&mut html)
+            .unwrap();
+        assert!(html.contains(r#"<meta name="viewport" content="width=1200, height=1600"/>"#));
+        assert!(html.contains(r#"viewBox="0 0 1200 1600""#));
+        assert!(html.contains(r#"xlink:href="images/1.jpg""#));
+        assert!(!html.contains("<h1"));
+
+        let mut book = read_from_vec(v).unwrap();
+        assert!(book.is_fixed_layout());
+        assert_eq!(Some(RenditionOrientation::Portrait), book.orientation());
+        assert_eq!(Some(RenditionSpread::Landscape), book.spread());
+        assert!(book.meta().iter().all(|f| f
+            .get_attr("property")
+            .is_none_or(|v| !v.starts_with("rendition:"))));
+
+        let spreads: Vec<Option<PageSpread>> = book
+            .chapters()
+            .filter(|f| f.file_name().starts_with("page_"))
+            .map(|f| f.page_spread())
+            .collect();
+        // 从右往左，第一页在左
+        assert_eq!(
+            vec![
+                Some(PageSpread::Left),
+                Some(PageSpread::Right),
+                Some(PageSpread::Center)
+            ],
+            spreads
+        );
+
+        let chap = book.get_chapter_mut("page_0002.xhtml").unwrap();
+        chap.data_mut();
+        assert_eq!(Some((1200, 1600)), chap.viewport());
+        chap.set_viewport(800, 600);
+        assert_eq!(Some((800, 600)), chap.viewport());
+        assert_eq!(1, chap.head().unwrap().matches("viewport").count());
+    }
 }
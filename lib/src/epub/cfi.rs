@@ -0,0 +1,700 @@
+//! EPUB CFI（Canonical Fragment Identifier）
+//!
+//! 生成和解析形如 `epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)` 的位置，以及
+//! `epubcfi(/6/4!/4/10,/2/1:3,/3:5)` 这样的范围
+//!
+//! - 读取的书使用读取时章节在 spine 中的位置，spine 中找不到文件的 itemref 也会计算在内；
+//!   其他的书使用 [EpubBook::chapters] 中的序号
+//! - 章节使用读取时的原始文件，不是读取后的书使用写入时生成的 xhtml
+//! - 字符位置在 CFI 中按 UTF-16 计算，和浏览器中 JavaScript 的字符串下标一致；接口中按字符计算
+//! - 解析时忽略 `[]` 中的断言，以及时间、空间偏移
+//!
+
+use quick_xml::events::Event;
+
+use crate::prelude::*;
+
+/// package 中 spine 元素的序号，metadata、manifest、spine 依次为 2、4、6
+const SPINE_STEP: usize = 6;
+
+///
+/// 章节中的位置
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfiPosition {
+    chapter: usize,
+    path: Vec<usize>,
+    offset: Option<usize>,
+}
+
+impl CfiPosition {
+    ///
+    /// [chapter] 章节在 [EpubBook::chapters] 中的序号
+    ///
+    /// [path] 从 html 元素开始，每一层在父元素的子元素中的序号，只计算元素，从 0 开始，例如 `[1, 0]` 表示 body 的第一个子元素
+    ///
+    pub fn new(chapter: usize, path: Vec<usize>) -> Self {
+        CfiPosition {
+            chapter,
+            path,
+            offset: None,
+        }
+    }
+
+    ///
+    /// 元素文本中的位置，包括子元素中的文本，按字符计算
+    ///
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn chapter(&self) -> usize {
+        self.chapter
+    }
+
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+/// 章节中的节点，连续的文本合并为一个
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Default)]
+struct Element {
+    id: Option<String>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn push_text(&mut self, text: &str) {
+        if let Some(Node::Text(v)) = self.children.last_mut() {
+            v.push_str(text);
+        } else {
+            self.children.push(Node::Text(text.to_string()));
+        }
+    }
+
+    fn element(&self, index: usize) -> Option<&Element> {
+        self.children
+            .iter()
+            .filter_map(|f| match f {
+                Node::Element(e) => Some(e),
+                Node::Text(_) => None,
+            })
+            .nth(index)
+    }
+
+    /// 所有文本的字符数
+    fn text_len(&self) -> usize {
+        self.children
+            .iter()
+            .map(|f| match f {
+                Node::Element(e) => e.text_len(),
+                Node::Text(t) => t.chars().count(),
+            })
+            .sum()
+    }
+
+    ///
+    /// 文本节点之前的字符数，以及文本节点的内容
+    ///
+    /// [chunk] 文本节点前的元素数量，对应 CFI 中的 chunk * 2 + 1
+    ///
+    fn chunk(&self, chunk: usize) -> (usize, &str) {
+        let mut before = 0;
+        let mut elements = 0;
+        for ele in &self.children {
+            match ele {
+                Node::Element(e) => {
+                    if elements == chunk {
+                        break;
+                    }
+                    elements += 1;
+                    before += e.text_len();
+                }
+                Node::Text(t) => {
+                    if elements == chunk {
+                        return (before, t.as_str());
+                    }
+                    before += t.chars().count();
+                }
+            }
+        }
+        (before, "")
+    }
+}
+
+/// 解析章节，返回 html 元素
+fn parse_document(html: &str) -> IResult<Element> {
+    let html = html.replace("\r\n", "\n");
+    let mut reader = quick_xml::reader::Reader::from_str(html.as_str());
+    let mut stack: Vec<Element> = Vec::new();
+    let text = |stack: &mut Vec<Element>, value: &str| {
+        if let Some(last) = stack.last_mut() {
+            last.push_text(value);
+        }
+    };
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => stack.push(Element {
+                id: get_id(&e),
+                children: Vec::new(),
+            }),
+            Event::Empty(e) => {
+                let ele = Element {
+                    id: get_id(&e),
+                    children: Vec::new(),
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(ele)),
+                    None => return Ok(ele),
+                }
+            }
+            Event::End(_) => {
+                let ele = stack
+                    .pop()
+                    .ok_or(IError::Cfi("xhtml格式错误".to_string()))?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(ele)),
+                    None => return Ok(ele),
+                }
+            }
+            Event::Text(e) => text(&mut stack, e.decode().map_err(IError::Encoding)?.as_ref()),
+            Event::CData(e) => text(&mut stack, e.decode().map_err(IError::Encoding)?.as_ref()),
+            Event::GeneralRef(e) => {
+                if let Some(v) = crate::common::resolve_entity(&e) {
+                    text(&mut stack, v.to_string().as_str());
+                }
+            }
+            Event::Eof => return Err(IError::Cfi("xhtml格式错误".to_string())),
+            _ => {}
+        }
+    }
+}
+
+fn get_id(e: &quick_xml::events::BytesStart) -> Option<String> {
+    e.try_get_attribute("id")
+        .ok()
+        .flatten()
+        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+}
+
+/// 读取章节的原始内容，读取前没有加载的会在解析后释放，同时返回 itemref 的 id 和在 spine 中的位置
+fn load_document(book: &mut EpubBook, chapter: usize) -> IResult<(Element, Option<String>, usize)> {
+    let chap = book
+        .chapters_mut()
+        .nth(chapter)
+        .ok_or(IError::Cfi(format!("章节 {chapter} 不存在")))?;
+    let loaded = chap.raw_data.is_some();
+    let html = match chap.raw_data() {
+        Some(v) => v.to_string(),
+        None => chap.format().unwrap_or_default(),
+    };
+    if !loaded {
+        chap.release_raw_data();
+    }
+    Ok((
+        parse_document(html.as_str())?,
+        chap.spine_id().map(|f| f.to_string()),
+        chap.spine_index.unwrap_or(chapter),
+    ))
+}
+
+/// 断言中的特殊字符需要使用 ^ 转义
+fn assertion(id: Option<&str>) -> String {
+    match id {
+        Some(id) => {
+            let mut out = String::from("[");
+            for c in id.chars() {
+                if "^[](),;=".contains(c) {
+                    out.push('^');
+                }
+                out.push(c);
+            }
+            out.push(']');
+            out
+        }
+        None => String::new(),
+    }
+}
+
+/// 字符数转为 UTF-16 的长度
+fn utf16_len(text: &str, chars: usize) -> usize {
+    text.chars().take(chars).map(|f| f.len_utf16()).sum()
+}
+
+/// UTF-16 的位置转为字符数
+fn char_len(text: &str, utf16: usize) -> usize {
+    let mut len = 0;
+    let mut count = 0;
+    for c in text.chars() {
+        if len >= utf16 {
+            break;
+        }
+        len += c.len_utf16();
+        count += 1;
+    }
+    count
+}
+
+/// 生成 CFI 的每一步，第一个章节内的步骤以 ! 开头
+fn position_steps(book: &mut EpubBook, position: &CfiPosition) -> IResult<Vec<String>> {
+    let (root, spine_id, spine_index) = load_document(book, position.chapter)?;
+    let mut steps = vec![
+        format!("/{SPINE_STEP}"),
+        format!(
+            "/{}{}",
+            (spine_index + 1) * 2,
+            assertion(spine_id.as_deref())
+        ),
+    ];
+    let mut ele = &root;
+    for index in &position.path {
+        ele = ele
+            .element(*index)
+            .ok_or(IError::Cfi(format!("元素 {:?} 不存在", position.path)))?;
+        steps.push(format!(
+            "/{}{}",
+            (index + 1) * 2,
+            assertion(ele.id.as_deref())
+        ));
+    }
+    if let Some(offset) = position.offset {
+        text_steps(ele, offset, &mut steps)
+            .ok_or(IError::Cfi(format!("位置 {offset} 超出文本长度")))?;
+    }
+    if steps.len() > 2 {
+        steps[2].insert(0, '!');
+    }
+    Ok(steps)
+}
+
+/// 找到 [offset] 所在的文本节点
+fn text_steps(ele: &Element, mut offset: usize, steps: &mut Vec<String>) -> Option<()> {
+    let mut elements = 0;
+    for node in &ele.children {
+        match node {
+            Node::Text(t) => {
+                let len = t.chars().count();
+                if offset <= len {
+                    steps.push(format!("/{}:{}", elements * 2 + 1, utf16_len(t, offset)));
+                    return Some(());
+                }
+                offset -= len;
+            }
+            Node::Element(e) => {
+                elements += 1;
+                let len = e.text_len();
+                if offset < len {
+                    steps.push(format!("/{}{}", elements * 2, assertion(e.id.as_deref())));
+                    return text_steps(e, offset, steps);
+                }
+                offset -= len;
+            }
+        }
+    }
+    if offset == 0 {
+        // 最后一个元素之后的空文本
+        steps.push(format!("/{}:0", elements * 2 + 1));
+        return Some(());
+    }
+    None
+}
+
+///
+/// 生成指向某个位置的 CFI
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::cfi::{to_cfi, CfiPosition};
+///
+/// let mut book = read_from_file("example.epub").unwrap();
+/// // 第三个章节中 body 的第二个子元素，文本中的第十个字符
+/// let cfi = to_cfi(&mut book, &CfiPosition::new(2, vec![1, 1]).with_offset(10)).unwrap();
+/// ```
+pub fn to_cfi(book: &mut EpubBook, position: &CfiPosition) -> IResult<String> {
+    Ok(format!(
+        "epubcfi({})",
+        position_steps(book, position)?.concat()
+    ))
+}
+
+///
+/// 生成范围 CFI，公共的部分作为父路径
+///
+pub fn to_range_cfi(
+    book: &mut EpubBook,
+    start: &CfiPosition,
+    end: &CfiPosition,
+) -> IResult<String> {
+    let start = position_steps(book, start)?;
+    let end = position_steps(book, end)?;
+    let common = start
+        .iter()
+        .zip(end.iter())
+        .take(start.len().min(end.len()) - 1)
+        .take_while(|(s, e)| s == e)
+        .count();
+    Ok(format!(
+        "epubcfi({},{},{})",
+        start[..common].concat(),
+        start[common..].concat(),
+        end[common..].concat()
+    ))
+}
+
+/// CFI 中的一步
+struct Step {
+    index: usize,
+    /// 以 ! 开头，进入章节
+    indirect: bool,
+    /// UTF-16 的字符位置
+    offset: Option<usize>,
+}
+
+fn cfi_err<T>(cfi: &str) -> IResult<T> {
+    Err(IError::Cfi(format!("无效的CFI {cfi}")))
+}
+
+fn parse_steps(cfi: &str) -> IResult<Vec<Step>> {
+    let chars: Vec<char> = cfi.chars().collect();
+    let number = |i: &mut usize| -> Option<usize> {
+        let start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse().ok()
+    };
+    let mut steps: Vec<Step> = Vec::new();
+    let mut indirect = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '!' => {
+                indirect = true;
+                i += 1;
+            }
+            '/' => {
+                i += 1;
+                let Some(index) = number(&mut i) else {
+                    return cfi_err(cfi);
+                };
+                steps.push(Step {
+                    index,
+                    indirect,
+                    offset: None,
+                });
+                indirect = false;
+            }
+            ':' => {
+                i += 1;
+                match (number(&mut i), steps.last_mut()) {
+                    (Some(offset), Some(step)) => step.offset = Some(offset),
+                    _ => return cfi_err(cfi),
+                }
+            }
+            '[' => {
+                // 断言
+                while i < chars.len() && chars[i] != ']' {
+                    i += if chars[i] == '^' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            '~' | '@' => {
+                // 时间、空间偏移
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || ".:".contains(chars[i])) {
+                    i += 1;
+                }
+            }
+            _ => return cfi_err(cfi),
+        }
+    }
+    Ok(steps)
+}
+
+/// 拆分范围，忽略断言中的逗号
+fn split_range(cfi: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = cfi.chars();
+    let mut in_assertion = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => {
+                parts.last_mut().unwrap().push(c);
+                if let Some(n) = chars.next() {
+                    parts.last_mut().unwrap().push(n);
+                }
+                continue;
+            }
+            '[' => in_assertion = true,
+            ']' => in_assertion = false,
+            ',' if !in_assertion => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+}
+
+fn resolve_steps(book: &mut EpubBook, cfi: &str, steps: &[Step]) -> IResult<CfiPosition> {
+    if steps.len() < 2 || !steps[1].index.is_multiple_of(2) || steps[1].index == 0 {
+        return cfi_err(cfi);
+    }
+    if steps.len() > 2 && !steps[2].indirect {
+        return cfi_err(cfi);
+    }
+    let spine_index = steps[1].index / 2 - 1;
+    let chapter = book
+        .chapters()
+        .enumerate()
+        .find(|(index, f)| f.spine_index.unwrap_or(*index) == spine_index)
+        .map(|(index, _)| index)
+        .ok_or(IError::Cfi(format!("章节 {spine_index} 不存在")))?;
+    let (root, _, _) = load_document(book, chapter)?;
+    let mut position = CfiPosition::new(chapter, Vec::new());
+    let mut ele = &root;
+    for (index, step) in steps.iter().enumerate().skip(2) {
+        if step.index.is_multiple_of(2) {
+            let Some(e) = step.index.checked_sub(2).and_then(|f| ele.element(f / 2)) else {
+                return Err(IError::Cfi(format!("元素不存在 {cfi}")));
+            };
+            ele = e;
+            position.path.push(step.index / 2 - 1);
+            if let Some(offset) = step.offset {
+                // 元素上的位置按元素的文本计算
+                let mut text = String::new();
+                collect_text(ele, &mut text);
+                position.offset = Some(char_len(text.as_str(), offset));
+            }
+        } else {
+            if index != steps.len() - 1 {
+                return cfi_err(cfi);
+            }
+            let (before, text) = ele.chunk(step.index / 2);
+            position.offset = Some(before + char_len(text, step.offset.unwrap_or_default()));
+        }
+    }
+    Ok(position)
+}
+
+fn collect_text(ele: &Element, out: &mut String) {
+    for node in &ele.children {
+        match node {
+            Node::Element(e) => collect_text(e, out),
+            Node::Text(t) => out.push_str(t),
+        }
+    }
+}
+
+fn strip_cfi(cfi: &str) -> IResult<&str> {
+    match cfi
+        .trim()
+        .strip_prefix("epubcfi(")
+        .and_then(|f| f.strip_suffix(')'))
+    {
+        Some(v) => Ok(v),
+        None => cfi_err(cfi),
+    }
+}
+
+///
+/// 解析范围 CFI，返回开始和结束的位置；不是范围时两者相同
+///
+/// 返回的位置是文本所在的最内层元素
+///
+pub fn resolve_range_cfi(book: &mut EpubBook, cfi: &str) -> IResult<(CfiPosition, CfiPosition)> {
+    let parts = split_range(strip_cfi(cfi)?);
+    match parts.as_slice() {
+        [point] => {
+            let position = resolve_steps(book, cfi, &parse_steps(point)?)?;
+            Ok((position.clone(), position))
+        }
+        [parent, start, end] => Ok((
+            resolve_steps(
+                book,
+                cfi,
+                &parse_steps(format!("{parent}{start}").as_str())?,
+            )?,
+            resolve_steps(book, cfi, &parse_steps(format!("{parent}{end}").as_str())?)?,
+        )),
+        _ => cfi_err(cfi),
+    }
+}
+
+///
+/// 解析 CFI，返回章节以及文本所在的最内层元素和位置；范围 CFI 返回开始的位置
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::cfi::resolve_cfi;
+///
+/// let mut book = read_from_file("example.epub").unwrap();
+/// let position = resolve_cfi(&mut book, "epubcfi(/6/4!/4/2/1:3)").unwrap();
+/// println!("{} {:?} {:?}", position.chapter(), position.path(), position.offset());
+/// ```
+pub fn resolve_cfi(book: &mut EpubBook, cfi: &str) -> IResult<CfiPosition> {
+    resolve_range_cfi(book, cfi).map(|f| f.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_book() -> EpubBook {
+        let mut book = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>一</p>".as_bytes().to_vec()),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_title("第二章")
+                    .with_data(
+                        r#"<p id="p1">天气<b>很好</b>，😀&amp;x&hellip;</p><p>第二段</p>"#
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+            )
+            .book()
+            .unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        // spine 中加入找不到文件的 itemref
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for i in 0..zip.len() {
+            let mut f = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut f, &mut content).unwrap();
+            if f.name() == "OEBPS/content.opf" {
+                content = String::from_utf8(content)
+                    .unwrap()
+                    .replace(
+                        r#"<itemref idref="chap_1"/>"#,
+                        r#"<itemref idref="missing"/><itemref idref="chap_1"/>"#,
+                    )
+                    .into_bytes();
+            }
+            out.start_file(f.name(), zip::write::SimpleFileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut out, &content).unwrap();
+        }
+        read_from_vec(out.finish().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let steps = parse_steps("/6/4[chap^[1^]]!/4[body01]/10/3:10[;s=b]~2.5@1:2").unwrap();
+        assert_eq!(
+            vec![6, 4, 4, 10, 3],
+            steps.iter().map(|f| f.index).collect::<Vec<usize>>()
+        );
+        assert!(steps[2].indirect);
+        assert_eq!(Some(10), steps[4].offset);
+        assert!(parse_steps("/6/a").is_err());
+
+        assert_eq!(
+            vec!["/6/4!/4[a^,b]", "/2/1:3", "/3:5"],
+            split_range("/6/4!/4[a^,b],/2/1:3,/3:5")
+        );
+    }
+
+    #[test]
+    fn test_cfi() {
+        let mut book = create_book();
+        // 读取到的章节中，nav.xhtml 是第一个，spine 中前面还有一个找不到文件的 itemref
+        let chapter = book
+            .chapters()
+            .position(|f| f.file_name() == "1.xhtml")
+            .unwrap();
+        assert_eq!(2, chapter);
+        let step = 8;
+
+        let p = vec![1, 0];
+        let cfi = to_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(1),
+        )
+        .unwrap();
+        assert_eq!(format!("epubcfi(/6/{step}!/4/2[p1]/1:1)"), cfi);
+        assert_eq!(
+            CfiPosition::new(chapter, p.clone()).with_offset(1),
+            resolve_cfi(&mut book, cfi.as_str()).unwrap()
+        );
+
+        // 位于子元素中的文本
+        let cfi = to_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(3),
+        )
+        .unwrap();
+        assert_eq!(format!("epubcfi(/6/{step}!/4/2[p1]/2/1:1)"), cfi);
+        assert_eq!(
+            CfiPosition::new(chapter, vec![1, 0, 0]).with_offset(1),
+            resolve_cfi(&mut book, cfi.as_str()).unwrap()
+        );
+
+        // emoji 在 UTF-16 中占两个位置，实体按一个字符计算
+        let cfi = to_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(8),
+        )
+        .unwrap();
+        assert_eq!(format!("epubcfi(/6/{step}!/4/2[p1]/3:5)"), cfi);
+        assert_eq!(
+            CfiPosition::new(chapter, p.clone()).with_offset(8),
+            resolve_cfi(&mut book, cfi.as_str()).unwrap()
+        );
+        // 命名实体
+        let cfi = to_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(9),
+        )
+        .unwrap();
+        assert_eq!(format!("epubcfi(/6/{step}!/4/2[p1]/3:6)"), cfi);
+        assert!(to_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(11)
+        )
+        .is_err());
+        assert!(to_cfi(&mut book, &CfiPosition::new(chapter, vec![1, 9])).is_err());
+
+        let range = to_range_cfi(
+            &mut book,
+            &CfiPosition::new(chapter, p.clone()).with_offset(0),
+            &CfiPosition::new(chapter, vec![1, 1]).with_offset(2),
+        )
+        .unwrap();
+        assert_eq!(format!("epubcfi(/6/{step}!/4,/2[p1]/1:0,/4/1:2)"), range);
+        assert_eq!(
+            (
+                CfiPosition::new(chapter, p).with_offset(0),
+                CfiPosition::new(chapter, vec![1, 1]).with_offset(2)
+            ),
+            resolve_range_cfi(&mut book, range.as_str()).unwrap()
+        );
+
+        // 解析后释放章节
+        assert!(book.chapters().all(|f| f.raw_data.is_none()));
+        assert!(resolve_cfi(&mut book, "epubcfi(/6/100!/4)").is_err());
+        assert!(resolve_cfi(&mut book, "/6/4!/4").is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! epub2 和 epub3 之间的转换
+//!
+//! 写入时章节会重新生成，doctype 和命名空间由版本决定
+//!
+use super::common;
+use crate::prelude::*;
+
+///
+/// 升级为 epub3
+///
+/// - 根据 toc.ncx 中的目录生成 nav.xhtml，没有目录时使用章节标题生成
+/// - 写入 dcterms:modified
+/// - guide 在读取时已经转换为 landmarks，缺少正文起始位置时使用第一个章节
+/// - manifest 中写入 nav、cover-image、svg、scripted 等 properties
+/// - 章节使用 html5 的 doctype 并声明 epub 命名空间
+///
+pub fn upgrade_to_epub3(book: &mut EpubBook) {
+    book.set_version("3.0");
+    book.set_strict_version(false);
+    remove_nav_file(book);
+
+    if book.last_modify().is_none() {
+        book.set_last_modify(crate::common::DateTimeFormater::default().default_format());
+    }
+
+    if book.nav().next().is_none() {
+        let nav: Vec<EpubNav> = book
+            .chapters()
+            .filter(|f| !f.title().is_empty())
+            .map(|f| {
+                EpubNav::default()
+                    .with_title(f.title())
+                    .with_file_name(f.file_name())
+            })
+            .collect();
+        for ele in nav {
+            book.add_nav(ele);
+        }
+    }
+    add_bodymatter(book);
+
+    // opf 先于章节写入，需要提前读取章节才能判断 svg、scripted 等属性
+    for chap in book.chapters_mut() {
+        chap.data_mut();
+    }
+}
+
+///
+/// 降级为严格的 epub2，适用于只支持 epub2 的老旧阅读器
+///
+/// - opf 中只写入 2.0 支持的元素，不再写入 property、refines、properties 等
+/// - 只使用 toc.ncx 导航，不再生成 nav.xhtml
+/// - landmarks 写入 guide，缺少正文起始位置时使用第一个章节
+/// - 不再写入媒体覆盖以及固定布局的属性
+/// - 章节使用 xhtml 1.1 的 doctype，移除 epub:type 等属性，section 等 html5 元素改为 div
+///
+pub fn downgrade_to_epub2(book: &mut EpubBook) {
+    book.set_version("2.0");
+    book.set_strict_version(true);
+    remove_nav_file(book);
+    add_bodymatter(book);
+
+    for chap in book.chapters_mut() {
+        let Some(data) = chap.data_mut().map(crate::common::decode_text) else {
+            continue;
+        };
+        // html 和 body 标签上的属性也需要移除，否则 epub: 前缀没有声明
+        for attributes in [&mut chap.html_attribute, &mut chap.body_attribute] {
+            *attributes = attributes
+                .take()
+                .map(|f| super::html::remove_epub_attributes(&f))
+                .filter(|f| !f.is_empty());
+        }
+        // 无法解析的内容保持原样
+        if let Ok(html) = to_epub2_html(data.as_str()) {
+            if html != data {
+                chap.set_data(html.into_bytes());
+            }
+        }
+    }
+}
+
+/// 没有正文起始位置时，使用第一个线性阅读的章节
+fn add_bodymatter(book: &mut EpubBook) {
+    if book
+        .landmarks()
+        .iter()
+        .any(|f| f.epub_type() == "bodymatter")
+    {
+        return;
+    }
+    if let Some(first) = book
+        .chapters()
+        .find(|f| f.linear())
+        .map(|f| f.file_name().to_string())
+    {
+        book.add_landmark(EpubLandmark::new("bodymatter".to_string(), first));
+    }
+}
+
+///
+/// 移除写入时会重新生成的导航页、封面页、封面图片以及元数据，否则会重复写入
+///
+pub(crate) fn remove_generated(book: &mut EpubBook) {
+    remove_nav_file(book);
+    remove_cover_files(book);
+    remove_generated_meta(book);
+}
+
+/// 移除读取到的 nav.xhtml，写入时会重新生成
+pub(crate) fn remove_nav_file(book: &mut EpubBook) {
+    let nav = common::NAV.replace(common::EPUB, "");
+    if let Some(index) = book.chapters().position(|f| f.file_name() == nav) {
+        book.remove_chapter(index);
+    }
+    if let Some(index) = book.assets().position(|f| f.file_name() == nav) {
+        book.remove_assets(index);
+    }
+}
+
+///
+/// 移除读取到的封面页和封面图片，写入时由 cover_chapter 和 cover 生成
+///
+/// 只移除自动生成的 cover.xhtml，其他文件名的封面页作为普通章节保留
+///
+pub(crate) fn remove_cover_files(book: &mut EpubBook) {
+    let cover_chapter = common::COVER.replace(common::EPUB, "");
+    let cover = book.cover().map(|f| f.file_name().to_string());
+    if cover.is_none() {
+        return;
+    }
+    if let Some(index) = book.chapters().position(|f| f.file_name() == cover_chapter) {
+        book.remove_chapter(index);
+    }
+    for index in book
+        .assets()
+        .enumerate()
+        .filter(|(_, f)| f.file_name() == cover_chapter || Some(f.file_name()) == cover.as_deref())
+        .map(|(index, _)| index)
+        .rev()
+        .collect::<Vec<usize>>()
+    {
+        book.remove_assets(index);
+    }
+}
+
+///
+/// 移除读取时保留在自定义元数据中的封面、generator 和 dcterms:modified
+///
+/// 写入 opf 时会根据对应的字段重新生成
+///
+fn remove_generated_meta(book: &mut EpubBook) {
+    for index in (0..book.meta_len()).rev() {
+        let generated = book.get_meta(index).is_some_and(|f| {
+            matches!(
+                f.get_attr("name").map(|v| v.as_str()),
+                Some("cover" | "generator")
+            ) || f
+                .get_attr("property")
+                .is_some_and(|v| v == "dcterms:modified")
+        });
+        if generated {
+            book.remove_meta(index);
+        }
+    }
+}
+
+/// html5 新增的块级元素，xhtml 1.1 中没有
+const HTML5_BLOCKS: [&[u8]; 9] = [
+    b"article",
+    b"aside",
+    b"figcaption",
+    b"figure",
+    b"footer",
+    b"header",
+    b"main",
+    b"nav",
+    b"section",
+];
+
+///
+/// 章节内容转为 xhtml 1.1 支持的形式
+///
+/// - 移除 epub: 命名空间下的属性，例如 epub:type
+/// - epub:switch 只保留 epub:default 中的内容
+/// - section、nav、aside 等 html5 元素改为 div
+///
+fn to_epub2_html(html: &str) -> IResult<String> {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+    let mut reader = quick_xml::Reader::from_str(html);
+    reader.config_mut().check_end_names = false;
+    let mut xml = quick_xml::Writer::new(std::io::Cursor::new(Vec::new()));
+
+    let convert = |e: &BytesStart| -> IResult<BytesStart<'static>> {
+        let mut attrs = Vec::new();
+        for attr in e.attributes().with_checks(false) {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            let key = attr.key.as_ref();
+            if !key.starts_with(b"epub:") && key != b"xmlns:epub" {
+                attrs.push(attr);
+            }
+        }
+        let mut tag = e.to_owned();
+        tag.clear_attributes();
+        tag.extend_attributes(attrs);
+        if HTML5_BLOCKS.contains(&e.name().as_ref()) {
+            tag.set_name(b"div");
+        }
+        Ok(tag)
+    };
+    // epub:case 中的内容需要阅读器支持对应的命名空间，不输出
+    let mut skip: usize = 0;
+    loop {
+        let event = reader.read_event()?;
+        if skip > 0 {
+            match event {
+                Event::Start(_) => skip += 1,
+                Event::End(_) => skip -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(e) if e.name().as_ref() == b"epub:case" => skip = 1,
+            // epub:switch、epub:default 等标签本身不输出
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref().starts_with(b"epub:") => {}
+            Event::End(e) if e.name().as_ref().starts_with(b"epub:") => {}
+            Event::Start(e) => xml.write_event(Event::Start(convert(&e)?))?,
+            Event::Empty(e) => xml.write_event(Event::Empty(convert(&e)?))?,
+            Event::End(e) if HTML5_BLOCKS.contains(&e.name().as_ref()) => {
+                xml.write_event(Event::End(BytesEnd::new("div")))?
+            }
+            Event::Eof => break,
+            e => xml.write_event(e)?,
+        }
+    }
+    String::from_utf8(xml.into_inner().into_inner()).map_err(IError::Utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_epub2_html() {
+        assert_eq!(
+            r#"<div><p class="a">epub: text &amp; a epub:type="x"</p></div>"#,
+            to_epub2_html(
+                r#"<section epub:type="chapter"><p class="a" epub:prefix='x'>epub: text &amp; a epub:type="x"</p></section>"#
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            r#"<div><p>1</p><img src="1.png"/></div><div><p>2</p></div>"#,
+            to_epub2_html(
+                r#"<nav xmlns:epub="http://www.idpf.org/2007/ops"><p>1</p><img src="1.png" epub:type="x"/></nav><aside epub:type="footnote"><p>2</p></aside>"#
+            )
+            .unwrap()
+        );
+        // epub:switch 只保留 epub:default
+        assert_eq!(
+            r#"<p>前</p><img src="formula.png"/><p>后</p>"#,
+            to_epub2_html(
+                r#"<p>前</p><epub:switch id="s"><epub:case required-namespace="http://www.w3.org/1998/Math/MathML"><math><mi>x</mi></math></epub:case><epub:default><img src="formula.png"/></epub:default></epub:switch><p>后</p>"#
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_downgrade_attributes() {
+        use std::io::{Read, Write};
+
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_version("3.0")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>一</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        // 章节的 html 和 body 标签上有 epub: 属性
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for i in 0..zip.len() {
+            let mut f = zip.by_index(i).unwrap();
+            let mut content = String::new();
+            f.read_to_string(&mut content).unwrap();
+            if f.name() == "OEBPS/0.xhtml" {
+                content = content
+                    .replace("<body>", r#"<body epub:type="bodymatter" class="b">"#)
+                    .replace(r#"lang="""#, r#"lang="" epub:type="frontmatter""#);
+            }
+            out.start_file(f.name(), zip::write::SimpleFileOptions::default())
+                .unwrap();
+            out.write_all(content.as_bytes()).unwrap();
+        }
+        let data = out.finish().unwrap().into_inner();
+
+        let mut book = read_from_vec(data).unwrap();
+        downgrade_to_epub2(&mut book);
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut html = String::new();
+        zip.by_name("OEBPS/0.xhtml")
+            .unwrap()
+            .read_to_string(&mut html)
+            .unwrap();
+        assert!(!html.contains("epub:"), "{html}");
+        assert!(html.contains(r#"<body class="b">"#), "{html}");
+    }
+
+    #[test]
+    fn test_convert() {
+        use std::io::Read;
+
+        let mut book = EpubBuilder::default()
+            .with_title("书名")
+            .cover("cover.jpg", vec![1, 2, 3])
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data(
+                        r#"<section epub:type="chapter"><p>一</p></section>"#
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_title("第二章")
+                    .with_data("<p>二</p><script>let a = 1;</script>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let read = |data: &[u8], name: &str| -> Option<String> {
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+            let mut v = String::new();
+            zip.by_name(name).ok()?.read_to_string(&mut v).unwrap();
+            Some(v)
+        };
+
+        downgrade_to_epub2(&mut book);
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let opf = read(&data, common::OPF).unwrap();
+        assert!(opf.contains(r#"version="2.0""#));
+        for ele in ["properties", "property=", "prefix=", "nav.xhtml"] {
+            assert!(!opf.contains(ele), "{ele}");
+        }
+        assert!(opf.contains(r#"<dc:date opf:event="modification">"#));
+        assert!(opf.contains(r#"<reference href="0.xhtml" title="" type="text"/>"#));
+        assert!(read(&data, common::NAV).is_none());
+        assert!(read(&data, common::TOC).is_some());
+        let html = read(&data, "OEBPS/0.xhtml").unwrap();
+        assert!(html.contains("XHTML 1.1"));
+        assert!(!html.contains("epub:"));
+        assert!(!html.contains("<section"));
+        assert!(html.contains("<div><p>一</p></div>"));
+
+        let mut book = read_from_vec(data).unwrap();
+        upgrade_to_epub3(&mut book);
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let opf = read(&data, common::OPF).unwrap();
+        assert!(opf.contains(r#"version="3.0""#));
+        assert!(opf.contains(r#"<meta property="dcterms:modified">"#));
+        assert!(opf.contains(r#"properties="nav""#));
+        assert!(opf.contains(
+            r#"<item href="1.xhtml" id="chap_1" media-type="application/xhtml+xml" properties="scripted"/>"#
+        ));
+        assert_eq!(1, opf.matches(r#"href="nav.xhtml""#).count());
+        let nav = read(&data, common::NAV).unwrap();
+        // 目录来自 toc.ncx
+        assert!(nav.contains(r#"<a href="1.xhtml">2. 第二章</a>"#));
+        assert!(nav.contains(r#"epub:type="bodymatter""#));
+        let html = read(&data, "OEBPS/0.xhtml").unwrap();
+        assert!(html.contains("<!DOCTYPE html>"));
+        assert!(html.contains(r#"xmlns:epub="http://www.idpf.org/2007/ops""#));
+
+        // 读取到的封面页和封面图片不会重复，也不会丢失
+        let mut book = read_from_vec(data.clone()).unwrap();
+        downgrade_to_epub2(&mut book);
+        for data in [data, EpubWriter::write_to_mem(&mut book, false).unwrap()] {
+            let opf = read(&data, common::OPF).unwrap();
+            assert_eq!(1, opf.matches(r#"<item href="cover.xhtml""#).count());
+            assert_eq!(1, opf.matches(r#"<item href="cover.jpg""#).count());
+            assert!(read(&data, "OEBPS/cover.jpg").is_some());
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 
 use super::common::{self};
@@ -8,7 +8,9 @@ use super::html::{get_html_info, to_html};
 use crate::cache_struct;
 use crate::common::{escape_xml, urldecode_enhanced, IError, IResult};
 use crate::epub::common::LinkRel;
+use crate::epub::encryption::FontObfuscation;
 use crate::epub::html;
+use crate::epub::overlay::EpubMediaOverlay;
 use crate::parser::HtmlParser;
 crate::cache_enum! {
     #[derive(Clone)]
@@ -29,6 +31,138 @@ impl Display for Direction {
     }
 }
 
+crate::cache_enum! {
+    ///
+    /// 跨页位置，对应 itemref 的 page-spread-* 属性
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum PageSpread {
+        Left,
+        Right,
+        Center,
+    }
+}
+
+impl PageSpread {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PageSpread::Left => "page-spread-left",
+            PageSpread::Right => "page-spread-right",
+            PageSpread::Center => "rendition:page-spread-center",
+        }
+    }
+
+    pub(crate) fn from_property(value: &str) -> Option<Self> {
+        match value.strip_prefix("rendition:").unwrap_or(value) {
+            "page-spread-left" => Some(PageSpread::Left),
+            "page-spread-right" => Some(PageSpread::Right),
+            "page-spread-center" => Some(PageSpread::Center),
+            _ => None,
+        }
+    }
+}
+
+crate::cache_enum! {
+    ///
+    /// 排版方式，对应 rendition:layout
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum RenditionLayout {
+        /// 流式排版
+        Reflowable,
+        /// 固定布局，漫画、绘本常用
+        PrePaginated,
+    }
+}
+
+impl RenditionLayout {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            RenditionLayout::Reflowable => "reflowable",
+            RenditionLayout::PrePaginated => "pre-paginated",
+        }
+    }
+
+    pub(crate) fn from_str(value: &str) -> Option<Self> {
+        match value.trim() {
+            "reflowable" => Some(RenditionLayout::Reflowable),
+            "pre-paginated" => Some(RenditionLayout::PrePaginated),
+            _ => None,
+        }
+    }
+}
+
+crate::cache_enum! {
+    ///
+    /// 屏幕方向，对应 rendition:orientation
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum RenditionOrientation {
+        Auto,
+        /// 横屏
+        Landscape,
+        /// 竖屏
+        Portrait,
+    }
+}
+
+impl RenditionOrientation {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            RenditionOrientation::Auto => "auto",
+            RenditionOrientation::Landscape => "landscape",
+            RenditionOrientation::Portrait => "portrait",
+        }
+    }
+
+    pub(crate) fn from_str(value: &str) -> Option<Self> {
+        match value.trim() {
+            "auto" => Some(RenditionOrientation::Auto),
+            "landscape" => Some(RenditionOrientation::Landscape),
+            "portrait" => Some(RenditionOrientation::Portrait),
+            _ => None,
+        }
+    }
+}
+
+crate::cache_enum! {
+    ///
+    /// 何时显示双页，对应 rendition:spread
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum RenditionSpread {
+        /// 始终单页
+        None,
+        /// 横屏时双页
+        Landscape,
+        /// 始终双页
+        Both,
+        Auto,
+    }
+}
+
+impl RenditionSpread {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            RenditionSpread::None => "none",
+            RenditionSpread::Landscape => "landscape",
+            RenditionSpread::Both => "both",
+            RenditionSpread::Auto => "auto",
+        }
+    }
+
+    pub(crate) fn from_str(value: &str) -> Option<Self> {
+        match value.trim() {
+            "none" => Some(RenditionSpread::None),
+            "landscape" => Some(RenditionSpread::Landscape),
+            // portrait 已废弃，规范要求按 both 处理
+            "both" | "portrait" => Some(RenditionSpread::Both),
+            "auto" => Some(RenditionSpread::Auto),
+            _ => None,
+        }
+    }
+}
+
 impl From<String> for Direction {
     fn from(value: String) -> Self {
         if value.eq_ignore_ascii_case("rtl") {
@@ -144,11 +278,27 @@ epub_base_field! {
         /// 自定义的css
         css: Option<String>,
         /// 文件初始内容
-        raw_data:Option<String>,
+        pub(crate) raw_data:Option<String>,
         /// 方向
         pub(crate) direction: Option<Direction>,
         /// body 标签上的attribute
         pub(crate) body_attribute: Option<Vec<u8>>,
+        /// html 标签上的attribute，不包括生成时会写入的 lang、dir 等
+        pub(crate) html_attribute: Option<Vec<u8>>,
+        /// head 中除 title、link、style 外的其他内容，例如 meta、script
+        head: Option<String>,
+        /// spine 中 itemref 的 id
+        pub(crate) spine_id: Option<String>,
+        /// spine 中 itemref 的 properties
+        pub(crate) spine_properties: Vec<String>,
+        /// 不在默认阅读顺序中，对应 linear="no"
+        pub(crate) non_linear: bool,
+        /// 读取时在 spine 中的位置，spine 中找不到文件的 itemref 也会计算在内
+        pub(crate) spine_index: Option<usize>,
+        /// 媒体覆盖
+        media_overlay: Option<EpubMediaOverlay>,
+        /// 流式写入后内容会被释放，这里保留 manifest 中的 properties
+        pub(crate) manifest_properties: Option<Vec<String>>,
     }
 }
 
@@ -163,6 +313,9 @@ impl Debug for EpubHtml {
             .field("links", &self.links)
             .field("title", &self.title)
             .field("css", &self.css)
+            .field("spine_id", &self.spine_id)
+            .field("spine_properties", &self.spine_properties)
+            .field("non_linear", &self.non_linear)
             .finish()
     }
 }
@@ -175,7 +328,7 @@ impl EpubHtml {
             self.data_mut();
         }
         if let Some(data) = &mut self._data {
-            String::from_utf8(data.clone()).unwrap_or_else(|_e| String::new())
+            crate::common::decode_text(data)
         } else {
             String::new()
         }
@@ -185,6 +338,23 @@ impl EpubHtml {
         self._data.as_deref()
     }
 
+    ///
+    /// 流式读取epub中的原始文件
+    ///
+    /// 不是从epub中读取的章节返回当前的数据
+    ///
+    pub fn reader(&self) -> IResult<impl Read + Send + '_> {
+        let stream: Box<dyn Read + Send + '_> = match (&self.reader, &self._data) {
+            (Some(reader), _) => {
+                let origin = self._file_name.split('#').next().unwrap_or_default();
+                open_stream(reader, origin)?
+            }
+            (None, Some(data)) => Box::new(data.as_slice()),
+            (None, None) => return Err(IError::FileNotFound),
+        };
+        Ok(stream)
+    }
+
     pub fn parser(&mut self) -> Option<HtmlParser> {
         let mut obj = None;
         let html = self.string_data();
@@ -197,7 +367,7 @@ impl EpubHtml {
         obj
     }
 
-    pub(crate) fn read_data(&mut self, reader: &mut impl EpubReaderTrait) {
+    pub(crate) fn read_data(&mut self, reader: &mut (impl EpubReaderTrait + ?Sized)) {
         let (id, origin) = if let Some(index) = self._file_name.find('#') {
             (
                 Some(&self._file_name[(index + 1)..]),
@@ -212,31 +382,17 @@ impl EpubHtml {
                 // 添加 前缀再次读取
                 f = format!("{prefix}{origin}");
                 let d = reader.read_string(f.as_str());
+                let lenient = reader.lenient();
                 match d {
                     Ok(v) => {
-                        if let Ok(html::HtmlInfo {
-                            title,
-                            content,
-                            language,
-                            direction,
-                            link,
-                            style,
-                            body_attribute,
-                        }) = get_html_info(v.as_str(), id)
-                        {
-                            if !title.is_empty() {
-                                self.set_title(&title);
-                            }
-                            self.set_data(content);
-                            if let Some(lang) = language {
-                                self.set_language(lang);
+                        if let Ok(info) = get_html_info(v.as_str(), id).or_else(|e| {
+                            if lenient {
+                                Ok(html::get_html_info_lenient(v.as_str()))
+                            } else {
+                                Err(e)
                             }
-                            self.direction = direction;
-                            if !link.is_empty() {
-                                self.links = Some(link);
-                            }
-                            self.css = style;
-                            self.body_attribute = body_attribute;
+                        }) {
+                            self.set_html_info(info);
                         }
                         break;
                     }
@@ -269,32 +425,20 @@ impl EpubHtml {
                 // 添加 前缀再次读取
                 f = format!("{prefix}{origin}");
                 let s = self.reader.as_mut().unwrap();
-                let d = s.lock().unwrap().read_string(f.as_str());
+                let (d, lenient) = {
+                    let mut r = s.lock().unwrap();
+                    (r.read_string(f.as_str()), r.lenient())
+                };
                 match d {
                     Ok(v) => {
-                        if let Ok(html::HtmlInfo {
-                            title,
-                            content,
-                            language,
-                            direction,
-                            link,
-                            style,
-                            body_attribute,
-                        }) = get_html_info(v.as_str(), id)
-                        {
-                            if !title.is_empty() {
-                                self.set_title(&title);
-                            }
-                            self.set_data(content);
-                            if let Some(lang) = language {
-                                self.set_language(lang);
-                            }
-                            self.direction = direction;
-                            if !link.is_empty() {
-                                self.links = Some(link);
+                        if let Ok(info) = get_html_info(v.as_str(), id).or_else(|e| {
+                            if lenient {
+                                Ok(html::get_html_info_lenient(v.as_str()))
+                            } else {
+                                Err(e)
                             }
-                            self.css = style;
-                            self.body_attribute = body_attribute;
+                        }) {
+                            self.set_html_info(info);
                         }
                         break;
                     }
@@ -308,6 +452,34 @@ impl EpubHtml {
         self._data.as_deref()
     }
 
+    fn set_html_info(&mut self, info: html::HtmlInfo) {
+        if !info.title.is_empty() {
+            self.set_title(&info.title);
+        }
+        self.set_data(info.content);
+        if let Some(lang) = info.language {
+            self.set_language(lang);
+        }
+        self.direction = info.direction;
+        if !info.link.is_empty() {
+            self.links = Some(info.link);
+        }
+        self.css = info.style;
+        self.body_attribute = info.body_attribute;
+        self.html_attribute = info.html_attribute;
+        self.head = info.head;
+    }
+
+    ///
+    /// 设置完整的 xhtml 内容
+    ///
+    /// 和读取时一样解析出标题、正文、样式、head 等，写入时重新生成
+    ///
+    pub fn set_html(&mut self, html: &str) -> IResult<()> {
+        self.set_html_info(get_html_info(html, None)?);
+        Ok(())
+    }
+
     pub fn release_data(&mut self) {
         if let Some(data) = &mut self._data {
             data.clear();
@@ -317,7 +489,7 @@ impl EpubHtml {
 
     pub fn format(&mut self) -> Option<String> {
         self.data_mut();
-        Some(to_html(self, false, &None))
+        Some(to_html(self, false, &None, false))
     }
 
     pub fn raw_data(&mut self) -> Option<&str> {
@@ -376,6 +548,22 @@ impl EpubHtml {
         self.css.as_deref()
     }
 
+    ///
+    /// head 中除 title、link、style 外的其他内容，例如 meta、script
+    ///
+    /// 读取时原样保存，生成时原样写入
+    ///
+    pub fn head(&self) -> Option<&str> {
+        self.head.as_deref()
+    }
+    pub fn set_head<T: Into<String>>(&mut self, head: T) {
+        self.head = Some(head.into());
+    }
+    pub fn with_head<T: Into<String>>(mut self, head: T) -> Self {
+        self.set_head(head);
+        self
+    }
+
     pub fn set_language<T: Into<String>>(&mut self, lang: T) {
         self.lang = lang.into();
     }
@@ -418,6 +606,161 @@ impl EpubHtml {
         self.set_direction(dir);
         self
     }
+
+    ///
+    /// 是否在默认阅读顺序中，为 false 时写入 linear="no"
+    ///
+    pub fn linear(&self) -> bool {
+        !self.non_linear
+    }
+
+    pub fn set_linear(&mut self, linear: bool) {
+        self.non_linear = !linear;
+    }
+
+    pub fn with_linear(mut self, linear: bool) -> Self {
+        self.set_linear(linear);
+        self
+    }
+
+    ///
+    /// spine 中 itemref 的 id
+    ///
+    pub fn spine_id(&self) -> Option<&str> {
+        self.spine_id.as_deref()
+    }
+
+    pub fn set_spine_id<T: Into<String>>(&mut self, id: T) {
+        self.spine_id = Some(id.into());
+    }
+
+    pub fn with_spine_id<T: Into<String>>(mut self, id: T) -> Self {
+        self.set_spine_id(id);
+        self
+    }
+
+    ///
+    /// spine 中 itemref 的 properties，例如 page-spread-left、rendition:layout-pre-paginated
+    ///
+    pub fn spine_properties(&self) -> std::slice::Iter<'_, String> {
+        self.spine_properties.iter()
+    }
+
+    pub fn add_spine_property<T: Into<String>>(&mut self, property: T) {
+        let property = property.into();
+        if !property.is_empty() && !self.spine_properties.contains(&property) {
+            self.spine_properties.push(property);
+        }
+    }
+
+    pub fn with_spine_property<T: Into<String>>(mut self, property: T) -> Self {
+        self.add_spine_property(property);
+        self
+    }
+
+    ///
+    /// 跨页位置，从 properties 中解析
+    ///
+    pub fn page_spread(&self) -> Option<PageSpread> {
+        self.spine_properties
+            .iter()
+            .find_map(|f| PageSpread::from_property(f))
+    }
+
+    ///
+    /// 设置跨页位置，会替换已有的 page-spread-*
+    ///
+    pub fn set_page_spread(&mut self, spread: Option<PageSpread>) {
+        self.spine_properties
+            .retain(|f| PageSpread::from_property(f).is_none());
+        if let Some(spread) = spread {
+            self.spine_properties.push(spread.as_str().to_string());
+        }
+    }
+
+    pub fn with_page_spread(mut self, spread: PageSpread) -> Self {
+        self.set_page_spread(Some(spread));
+        self
+    }
+
+    ///
+    /// 固定布局页面的尺寸，从 head 中的 viewport meta 解析
+    ///
+    /// 读取的书籍需要先加载章节数据
+    ///
+    pub fn viewport(&self) -> Option<(u32, u32)> {
+        let head = self.head.as_deref()?;
+        let (_, content) = find_viewport(head)?;
+        let mut width = None;
+        let mut height = None;
+        for ele in content.split([',', ';']) {
+            if let Some((key, value)) = ele.split_once('=') {
+                match key.trim() {
+                    "width" => width = value.trim().parse().ok(),
+                    "height" => height = value.trim().parse().ok(),
+                    _ => {}
+                }
+            }
+        }
+        width.zip(height)
+    }
+
+    ///
+    /// 设置固定布局页面的尺寸，会替换 head 中已有的 viewport meta
+    ///
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        let meta = format!(r#"<meta name="viewport" content="width={width}, height={height}"/>"#);
+        let head = self.head.take().unwrap_or_default();
+        self.head = Some(match find_viewport(&head) {
+            Some((range, _)) => format!("{}{meta}{}", &head[..range.start], &head[range.end..]),
+            None if head.is_empty() => meta,
+            None => format!("{meta}\n{head}"),
+        });
+    }
+
+    pub fn with_viewport(mut self, width: u32, height: u32) -> Self {
+        self.set_viewport(width, height);
+        self
+    }
+
+    ///
+    /// 媒体覆盖，对应 manifest 中的 media-overlay
+    ///
+    pub fn media_overlay(&self) -> Option<&EpubMediaOverlay> {
+        self.media_overlay.as_ref()
+    }
+
+    pub fn set_media_overlay(&mut self, overlay: Option<EpubMediaOverlay>) {
+        self.media_overlay = overlay;
+    }
+
+    pub fn with_media_overlay(mut self, overlay: EpubMediaOverlay) -> Self {
+        self.set_media_overlay(Some(overlay));
+        self
+    }
+}
+
+///
+/// 查找 viewport meta，返回其在 head 中的位置以及 content
+///
+fn find_viewport(head: &str) -> Option<(std::ops::Range<usize>, &str)> {
+    let mut start = 0;
+    while let Some(i) = head[start..].find("<meta") {
+        let begin = start + i;
+        let end = begin + head[begin..].find('>')? + 1;
+        let tag = &head[begin..end];
+        start = end;
+        if !tag.contains(r#"name="viewport""#) && !tag.contains("name='viewport'") {
+            continue;
+        }
+        let content = ["content=\"", "content='"].iter().find_map(|key| {
+            let from = tag.find(key)? + key.len();
+            let quote = &key[key.len() - 1..];
+            tag[from..].find(quote).map(|to| &tag[from..from + to])
+        })?;
+        return Some((begin..end, content));
+    }
+    None
 }
 
 epub_base_field! {
@@ -429,6 +772,8 @@ epub_base_field! {
 #[derive(Default,Clone)]
 pub struct EpubAssets {
    pub(crate) version:String,
+   /// 字体混淆算法
+   pub(crate) obfuscation: Option<FontObfuscation>,
 }
 }
 
@@ -437,6 +782,25 @@ impl EpubAssets {
         self.version = version.into();
     }
 
+    ///
+    /// 字体混淆算法
+    ///
+    /// 读取时表示原文件中使用的算法，读取到的数据已经是反混淆后的；
+    /// 写入时将使用该算法混淆，并生成 encryption.xml
+    ///
+    pub fn obfuscation(&self) -> Option<FontObfuscation> {
+        self.obfuscation
+    }
+
+    pub fn set_obfuscation(&mut self, obfuscation: Option<FontObfuscation>) {
+        self.obfuscation = obfuscation;
+    }
+
+    pub fn with_obfuscation(mut self, obfuscation: FontObfuscation) -> Self {
+        self.set_obfuscation(Some(obfuscation));
+        self
+    }
+
     pub fn data(&self) -> Option<&[u8]> {
         self._data.as_deref()
     }
@@ -469,6 +833,20 @@ impl EpubAssets {
         self._data.as_deref()
     }
 
+    ///
+    /// 流式读取数据
+    ///
+    /// 数据已经在内存中时直接读取，否则从epub中读取，不会把整个文件缓存到内存中，适用于较大的图片、音视频等文件
+    ///
+    pub fn reader(&self) -> IResult<impl Read + Send + '_> {
+        let stream: Box<dyn Read + Send + '_> = match (&self._data, &self.reader) {
+            (Some(data), _) => Box::new(data.as_slice()),
+            (None, Some(reader)) => open_stream(reader, self._file_name.as_str())?,
+            (None, None) => return Err(IError::FileNotFound),
+        };
+        Ok(stream)
+    }
+
     pub fn write_to<W: Write>(&mut self, writer: &mut W) -> IResult<()> {
         if let Some(data) = self.data_mut() {
             writer.write_all(data)?;
@@ -510,6 +888,7 @@ impl Debug for EpubAssets {
             .field("_file_name", &self._file_name)
             .field("media_type", &self.media_type)
             .field("_data", &self._data)
+            .field("obfuscation", &self.obfuscation)
             .field("reader_mode", &self.reader.is_some())
             .finish()
     }
@@ -636,98 +1015,495 @@ impl EpubMetaData {
         self.attr.get(key.as_ref())
     }
 }
-crate::cache_struct! {
-/// 书本
-#[derive(Default)]
-pub struct EpubBook {
-    /// 上次修改时间
-    last_modify: Option<String>,
-    /// epub电子书创建者信息
-    generator: Option<String>,
-    /// 书本信息
-    info: crate::common::BookInfo,
-    /// 元数据
-    meta: Vec<EpubMetaData>,
-    /// 目录信息
-    nav: Vec<EpubNav>,
-    /// 资源
-    assets: Vec<EpubAssets>,
-    /// 章节
-    chapters: Vec<EpubHtml>,
-    /// 封面页
-    pub(crate) cover_chapter: Option<EpubHtml>,
-    /// 封面
-    cover: Option<EpubAssets>,
-    /// 版本号
-    version: String,
-    /// 处于读模式
-    #[cfg(not(feature="cache"))]
-    reader:Option<std::sync::Arc<std::sync::Mutex< Box<dyn EpubReaderTrait+Send+Sync>>>>,
-    #[cfg(feature="cache")]
-    #[serde(skip)]
-    reader:Option<std::sync::Arc<std::sync::Mutex< Box<dyn EpubReaderTrait+Send+Sync>>>>,
-    /// PREFIX
-    pub(crate) prefix: String,
-    /// 方向
-   pub(crate) direction: Option<Direction>,
-   /// 语言
-   language: Option<String>,
-   /// toc.ncx
-   toc: Option<EpubAssets>,
-}
-}
 
-impl Display for EpubBook {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f,"last_modify={:?},info={:?},meta={:?},nav={:?},assets={:?},chapters={:?},cover={:?},is in read mode={}",
-        self.last_modify,
-        self.info,
-        self.meta,
-        self.nav,
-        self.assets,
-        self.chapters,
-        self.cover,
-        self.reader.is_some()
-    )
-    }
-}
-impl Drop for EpubBook {
-    fn drop(&mut self) {
-        self.release_memory();
+crate::cache_enum! {
+    ///
+    /// 标题类型，对应 EPUB3 的 title-type
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum TitleType {
+        /// 主标题
+        Main,
+        /// 副标题
+        Subtitle,
+        /// 简短标题
+        Short,
+        /// 所属合集
+        Collection,
+        /// 版本
+        Edition,
+        /// 完整标题
+        Expanded,
     }
 }
 
-impl EpubBook {
-    iepub_derive::option_string_method!("作者", info, creator);
-    iepub_derive::option_string_method!("简介", info, description);
-    iepub_derive::option_string_method!("电子书创建者", info, contributor);
-    iepub_derive::option_string_method!("出版日期", info, date);
-    iepub_derive::option_string_method!("format", info, format);
-    iepub_derive::option_string_method!("出版社", info, publisher);
-    iepub_derive::option_string_method!("主题", info, subject);
-    // /
-    // / 设置epub最后修改时间
-    // /
-    // / # Examples
-    // /
-    // / ```
-    // / let mut epub = EpubBook::default();
-    // / epub.set_last_modify("2024-06-28T08:07:07UTC");
-    // / ```
-    // /
-    iepub_derive::option_string_method!("电子书最后修改时间", last_modify);
-    iepub_derive::option_string_method!("电子书生成者", generator);
-    iepub_derive::option_string_method!("语言", language);
-}
-
-// 元数据
-impl EpubBook {
-    pub(crate) fn toc(&self) -> Option<&EpubAssets> {
-        self.toc.as_ref()
+impl TitleType {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            TitleType::Main => "main",
+            TitleType::Subtitle => "subtitle",
+            TitleType::Short => "short",
+            TitleType::Collection => "collection",
+            TitleType::Edition => "edition",
+            TitleType::Expanded => "expanded",
+        }
     }
 
-    pub(crate) fn set_toc(&mut self, toc: EpubAssets) {
-        self.toc = Some(toc)
+    pub(crate) fn from_str(value: &str) -> Option<Self> {
+        match value.trim() {
+            "main" => Some(TitleType::Main),
+            "subtitle" => Some(TitleType::Subtitle),
+            "short" => Some(TitleType::Short),
+            "collection" => Some(TitleType::Collection),
+            "edition" => Some(TitleType::Edition),
+            "expanded" => Some(TitleType::Expanded),
+            _ => None,
+        }
+    }
+}
+
+cache_struct! {
+///
+/// 书名，对应 dc:title
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubTitle {
+    title: String,
+    /// 标题类型
+    title_type: Option<TitleType>,
+    /// 排序用的名称
+    file_as: Option<String>,
+    /// 其他语言的写法，(语言, 文本)
+    alternate_script: Vec<(String, String)>,
+}
+}
+
+impl EpubTitle {
+    pub fn new<T: Into<String>>(title: T) -> Self {
+        Self {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title<T: Into<String>>(&mut self, title: T) {
+        self.title = title.into();
+    }
+
+    pub fn title_type(&self) -> Option<TitleType> {
+        self.title_type
+    }
+
+    pub fn set_title_type(&mut self, title_type: Option<TitleType>) {
+        self.title_type = title_type;
+    }
+
+    pub fn with_title_type(mut self, title_type: TitleType) -> Self {
+        self.set_title_type(Some(title_type));
+        self
+    }
+
+    pub fn file_as(&self) -> Option<&str> {
+        self.file_as.as_deref()
+    }
+
+    pub fn set_file_as<T: Into<String>>(&mut self, file_as: T) {
+        self.file_as = Some(file_as.into());
+    }
+
+    pub fn with_file_as<T: Into<String>>(mut self, file_as: T) -> Self {
+        self.set_file_as(file_as);
+        self
+    }
+
+    ///
+    /// 其他语言的写法
+    ///
+    /// 返回 (语言, 文本)
+    ///
+    pub fn alternate_script(&self) -> &[(String, String)] {
+        &self.alternate_script
+    }
+
+    pub fn add_alternate_script<T: Into<String>>(&mut self, lang: T, title: T) {
+        self.alternate_script.push((lang.into(), title.into()));
+    }
+
+    pub fn with_alternate_script<T: Into<String>>(mut self, lang: T, title: T) -> Self {
+        self.add_alternate_script(lang, title);
+        self
+    }
+
+    /// 是否是主标题
+    fn is_main(&self) -> bool {
+        self.title_type.is_none_or(|f| f == TitleType::Main)
+    }
+}
+
+cache_struct! {
+///
+/// 作者、译者等参与者，对应 dc:creator 和 dc:contributor
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubPerson {
+    name: String,
+    /// MARC relator 角色代码，例如 aut 作者、trl 译者、ill 插画
+    role: Option<String>,
+    /// 排序用的名称
+    file_as: Option<String>,
+    /// 其他语言的写法，(语言, 文本)
+    alternate_script: Vec<(String, String)>,
+}
+}
+
+impl EpubPerson {
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name<T: Into<String>>(&mut self, name: T) {
+        self.name = name.into();
+    }
+
+    pub fn role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+
+    ///
+    /// 设置角色
+    ///
+    /// [role] MARC relator 代码，例如 aut、trl、ill、edt
+    ///
+    pub fn set_role<T: Into<String>>(&mut self, role: T) {
+        self.role = Some(role.into());
+    }
+
+    pub fn with_role<T: Into<String>>(mut self, role: T) -> Self {
+        self.set_role(role);
+        self
+    }
+
+    pub fn file_as(&self) -> Option<&str> {
+        self.file_as.as_deref()
+    }
+
+    pub fn set_file_as<T: Into<String>>(&mut self, file_as: T) {
+        self.file_as = Some(file_as.into());
+    }
+
+    pub fn with_file_as<T: Into<String>>(mut self, file_as: T) -> Self {
+        self.set_file_as(file_as);
+        self
+    }
+
+    ///
+    /// 其他语言的写法
+    ///
+    /// 返回 (语言, 文本)
+    ///
+    pub fn alternate_script(&self) -> &[(String, String)] {
+        &self.alternate_script
+    }
+
+    pub fn add_alternate_script<T: Into<String>>(&mut self, lang: T, name: T) {
+        self.alternate_script.push((lang.into(), name.into()));
+    }
+
+    pub fn with_alternate_script<T: Into<String>>(mut self, lang: T, name: T) -> Self {
+        self.add_alternate_script(lang, name);
+        self
+    }
+}
+
+cache_struct! {
+///
+/// 标识，对应 dc:identifier
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubIdentifier {
+    value: String,
+    /// 标识类型，例如 ISBN、UUID
+    scheme: Option<String>,
+}
+}
+
+impl EpubIdentifier {
+    pub fn new<T: Into<String>>(value: T) -> Self {
+        Self {
+            value: value.into(),
+            scheme: None,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn set_value<T: Into<String>>(&mut self, value: T) {
+        self.value = value.into();
+    }
+
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    pub fn set_scheme<T: Into<String>>(&mut self, scheme: T) {
+        self.scheme = Some(scheme.into());
+    }
+
+    pub fn with_scheme<T: Into<String>>(mut self, scheme: T) -> Self {
+        self.set_scheme(scheme);
+        self
+    }
+}
+
+/// epub2 guide 的 type 和 epub3 landmarks 的 epub:type 不一致的部分
+static GUIDE_TYPES: [(&str, &str); 4] = [
+    ("text", "bodymatter"),
+    ("title-page", "titlepage"),
+    ("acknowledgements", "acknowledgments"),
+    ("notes", "endnotes"),
+];
+
+cache_struct! {
+///
+/// 地标，对应 epub3 导航中的 landmarks 以及 epub2 的 guide
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubLandmark {
+    /// epub:type，例如 cover、toc、bodymatter
+    epub_type: String,
+    /// 链接，相对于opf文件所在目录，可以带有锚点
+    href: String,
+    title: String,
+}
+}
+
+impl EpubLandmark {
+    pub fn new<T: Into<String>, H: Into<String>>(epub_type: T, href: H) -> Self {
+        Self {
+            epub_type: epub_type.into(),
+            href: href.into(),
+            title: String::new(),
+        }
+    }
+
+    ///
+    /// 从 guide 中的 reference 创建
+    ///
+    /// [guide_type] epub2 的类型，例如 text 会转换为 bodymatter
+    ///
+    pub(crate) fn from_guide(guide_type: &str, href: &str, title: &str) -> Self {
+        let epub_type = GUIDE_TYPES
+            .iter()
+            .find(|(g, _)| *g == guide_type)
+            .map_or(guide_type, |(_, t)| *t);
+        EpubLandmark::new(epub_type, href).with_title(title)
+    }
+
+    /// 转换为 guide 中 reference 的 type
+    pub(crate) fn guide_type(&self) -> &str {
+        GUIDE_TYPES
+            .iter()
+            .find(|(_, t)| *t == self.epub_type)
+            .map_or(self.epub_type.as_str(), |(g, _)| *g)
+    }
+
+    pub fn epub_type(&self) -> &str {
+        &self.epub_type
+    }
+
+    pub fn set_epub_type<T: Into<String>>(&mut self, epub_type: T) {
+        self.epub_type = epub_type.into();
+    }
+
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn set_href<T: Into<String>>(&mut self, href: T) {
+        self.href = href.into();
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title<T: Into<String>>(&mut self, title: T) {
+        self.title = title.into();
+    }
+
+    pub fn with_title<T: Into<String>>(mut self, title: T) -> Self {
+        self.set_title(title);
+        self
+    }
+}
+
+cache_struct! {
+///
+/// 纸质书页码，对应 epub3 导航中的 page-list 以及 toc.ncx 的 pageList
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubPage {
+    /// 页码，例如 1、iv
+    label: String,
+    /// 链接，相对于opf文件所在目录，通常带有锚点
+    href: String,
+}
+}
+
+impl EpubPage {
+    pub fn new<T: Into<String>, H: Into<String>>(label: T, href: H) -> Self {
+        Self {
+            label: label.into(),
+            href: href.into(),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+}
+crate::cache_struct! {
+/// 书本
+#[derive(Default)]
+pub struct EpubBook {
+    /// 上次修改时间
+    last_modify: Option<String>,
+    /// epub电子书创建者信息
+    generator: Option<String>,
+    /// 书本信息
+    info: crate::common::BookInfo,
+    /// 书名，包括副标题等
+    titles: Vec<EpubTitle>,
+    /// 标识，第一个为 unique-identifier
+    identifiers: Vec<EpubIdentifier>,
+    /// 作者
+    creators: Vec<EpubPerson>,
+    /// 其他参与者
+    contributors: Vec<EpubPerson>,
+    /// 主题
+    subjects: Vec<String>,
+    /// 元数据
+    meta: Vec<EpubMetaData>,
+    /// 目录信息
+    nav: Vec<EpubNav>,
+    /// 地标
+    landmarks: Vec<EpubLandmark>,
+    /// 纸质书页码
+    page_list: Vec<EpubPage>,
+    /// 资源
+    pub(crate) assets: Vec<EpubAssets>,
+    /// 章节
+    pub(crate) chapters: Vec<EpubHtml>,
+    /// 封面页
+    pub(crate) cover_chapter: Option<EpubHtml>,
+    /// 封面
+    cover: Option<EpubAssets>,
+    /// 版本号
+    version: String,
+    /// 严格按照版本号写入
+    strict_version: bool,
+    /// 处于读模式
+    #[cfg(not(feature="cache"))]
+    reader:Option<std::sync::Arc<std::sync::Mutex< Box<dyn EpubReaderTrait+Send+Sync>>>>,
+    #[cfg(feature="cache")]
+    #[serde(skip)]
+    reader:Option<std::sync::Arc<std::sync::Mutex< Box<dyn EpubReaderTrait+Send+Sync>>>>,
+    /// opf 文件在 epub 中的路径，写入时保持原有的目录结构
+    opf_path: Option<String>,
+    /// 方向
+   pub(crate) direction: Option<Direction>,
+   /// 排版方式
+   layout: Option<RenditionLayout>,
+   /// 屏幕方向
+   orientation: Option<RenditionOrientation>,
+   /// 双页显示
+   spread: Option<RenditionSpread>,
+   /// 语言
+   language: Option<String>,
+   /// 媒体覆盖播放时当前元素的 class，对应 media:active-class
+   media_active_class: Option<String>,
+   /// toc.ncx
+   toc: Option<EpubAssets>,
+   /// 未在 opf 中声明的其他文件，例如 META-INF/com.apple.ibooks.display-options.xml
+   extra_files: Vec<EpubAssets>,
+}
+}
+
+impl Display for EpubBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,"last_modify={:?},info={:?},titles={:?},identifiers={:?},creators={:?},contributors={:?},subjects={:?},meta={:?},nav={:?},landmarks={:?},page_list={:?},assets={:?},chapters={:?},cover={:?},is in read mode={}",
+        self.last_modify,
+        self.info,
+        self.titles,
+        self.identifiers,
+        self.creators,
+        self.contributors,
+        self.subjects,
+        self.meta,
+        self.nav,
+        self.landmarks,
+        self.page_list,
+        self.assets,
+        self.chapters,
+        self.cover,
+        self.reader.is_some()
+    )
+    }
+}
+impl Drop for EpubBook {
+    fn drop(&mut self) {
+        self.release_memory();
+    }
+}
+
+impl EpubBook {
+    iepub_derive::option_string_method!("简介", info, description);
+    iepub_derive::option_string_method!("出版日期", info, date);
+    iepub_derive::option_string_method!("format", info, format);
+    iepub_derive::option_string_method!("出版社", info, publisher);
+    iepub_derive::option_string_method!("系列", info, series);
+    iepub_derive::option_string_method!("在系列中的序号", info, series_index);
+    // /
+    // / 设置epub最后修改时间
+    // /
+    // / # Examples
+    // /
+    // / ```
+    // / let mut epub = EpubBook::default();
+    // / epub.set_last_modify("2024-06-28T08:07:07UTC");
+    // / ```
+    // /
+    iepub_derive::option_string_method!("电子书最后修改时间", last_modify);
+    iepub_derive::option_string_method!("电子书生成者", generator);
+    iepub_derive::option_string_method!("语言", language);
+    iepub_derive::option_string_method!("媒体覆盖播放时当前元素的 class", media_active_class);
+}
+
+// 元数据
+impl EpubBook {
+    pub(crate) fn toc(&self) -> Option<&EpubAssets> {
+        self.toc.as_ref()
+    }
+
+    pub(crate) fn set_toc(&mut self, toc: EpubAssets) {
+        self.toc = Some(toc)
     }
 
     pub fn set_direction(&mut self, dir: Direction) {
@@ -739,13 +1515,80 @@ impl EpubBook {
         self
     }
 
+    ///
+    /// 排版方式，对应 rendition:layout
+    ///
+    pub fn layout(&self) -> Option<RenditionLayout> {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: Option<RenditionLayout>) {
+        self.layout = layout;
+    }
+
+    pub fn with_layout(mut self, layout: RenditionLayout) -> Self {
+        self.set_layout(Some(layout));
+        self
+    }
+
+    ///
+    /// 是否为固定布局
+    ///
+    pub fn is_fixed_layout(&self) -> bool {
+        self.layout == Some(RenditionLayout::PrePaginated)
+    }
+
+    ///
+    /// 屏幕方向，对应 rendition:orientation
+    ///
+    pub fn orientation(&self) -> Option<RenditionOrientation> {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Option<RenditionOrientation>) {
+        self.orientation = orientation;
+    }
+
+    pub fn with_orientation(mut self, orientation: RenditionOrientation) -> Self {
+        self.set_orientation(Some(orientation));
+        self
+    }
+
+    ///
+    /// 双页显示，对应 rendition:spread
+    ///
+    pub fn spread(&self) -> Option<RenditionSpread> {
+        self.spread
+    }
+
+    pub fn set_spread(&mut self, spread: Option<RenditionSpread>) {
+        self.spread = spread;
+    }
+
+    pub fn with_spread(mut self, spread: RenditionSpread) -> Self {
+        self.set_spread(Some(spread));
+        self
+    }
+
+    ///
+    /// 设置主标题，其他标题不受影响
+    ///
     pub fn set_title<T: AsRef<str>>(&mut self, title: T) {
-        self.info.title.clear();
-        self.info.title.push_str(title.as_ref());
+        match self.titles.iter().position(|f| f.is_main()) {
+            Some(index) => self.titles[index].set_title(title.as_ref()),
+            None => self.titles.insert(0, EpubTitle::new(title.as_ref())),
+        }
     }
 
+    ///
+    /// 主标题
+    ///
     pub fn title(&self) -> &str {
-        self.info.title.as_str()
+        self.titles
+            .iter()
+            .find(|f| f.is_main())
+            .or_else(|| self.titles.first())
+            .map_or("", |f| f.title())
     }
 
     pub fn with_title<T: AsRef<str>>(mut self, title: T) -> Self {
@@ -753,13 +1596,31 @@ impl EpubBook {
         self
     }
 
+    ///
+    /// 所有标题，包括副标题、合集名等
+    ///
+    pub fn titles(&self) -> &[EpubTitle] {
+        &self.titles
+    }
+
+    pub fn add_title(&mut self, title: EpubTitle) {
+        self.titles.push(title);
+    }
+
+    ///
+    /// 唯一标识，也就是 unique-identifier
+    ///
     pub fn identifier(&self) -> &str {
-        self.info.identifier.as_str()
+        self.identifiers.first().map_or("", |f| f.value())
     }
 
     pub fn set_identifier<T: AsRef<str>>(&mut self, identifier: T) {
-        self.info.identifier.clear();
-        self.info.identifier.push_str(identifier.as_ref());
+        match self.identifiers.first_mut() {
+            Some(v) => v.set_value(identifier.as_ref()),
+            None => self
+                .identifiers
+                .push(EpubIdentifier::new(identifier.as_ref())),
+        }
     }
 
     pub fn with_identifier<T: AsRef<str>>(mut self, identifier: T) -> Self {
@@ -767,6 +1628,102 @@ impl EpubBook {
         self
     }
 
+    ///
+    /// 所有标识，第一个为 unique-identifier
+    ///
+    pub fn identifiers(&self) -> &[EpubIdentifier] {
+        &self.identifiers
+    }
+
+    pub fn add_identifier(&mut self, identifier: EpubIdentifier) {
+        self.identifiers.push(identifier);
+    }
+
+    ///
+    /// 第一个作者
+    ///
+    pub fn creator(&self) -> Option<&str> {
+        self.creators.first().map(|f| f.name())
+    }
+
+    ///
+    /// 设置作者，会替换掉已有的所有作者
+    ///
+    pub fn set_creator<T: AsRef<str>>(&mut self, creator: T) {
+        self.creators.clear();
+        self.creators.push(EpubPerson::new(creator.as_ref()));
+    }
+
+    pub fn with_creator<T: AsRef<str>>(mut self, creator: T) -> Self {
+        self.set_creator(creator);
+        self
+    }
+
+    pub fn creators(&self) -> &[EpubPerson] {
+        &self.creators
+    }
+
+    pub fn add_creator(&mut self, creator: EpubPerson) {
+        self.creators.push(creator);
+    }
+
+    ///
+    /// 第一个参与者
+    ///
+    pub fn contributor(&self) -> Option<&str> {
+        self.contributors.first().map(|f| f.name())
+    }
+
+    ///
+    /// 设置参与者，会替换掉已有的所有参与者
+    ///
+    pub fn set_contributor<T: AsRef<str>>(&mut self, contributor: T) {
+        self.contributors.clear();
+        self.contributors
+            .push(EpubPerson::new(contributor.as_ref()));
+    }
+
+    pub fn with_contributor<T: AsRef<str>>(mut self, contributor: T) -> Self {
+        self.set_contributor(contributor);
+        self
+    }
+
+    pub fn contributors(&self) -> &[EpubPerson] {
+        &self.contributors
+    }
+
+    pub fn add_contributor(&mut self, contributor: EpubPerson) {
+        self.contributors.push(contributor);
+    }
+
+    ///
+    /// 第一个主题
+    ///
+    pub fn subject(&self) -> Option<&str> {
+        self.subjects.first().map(|f| f.as_str())
+    }
+
+    ///
+    /// 设置主题，会替换掉已有的所有主题
+    ///
+    pub fn set_subject<T: AsRef<str>>(&mut self, subject: T) {
+        self.subjects.clear();
+        self.subjects.push(subject.as_ref().to_string());
+    }
+
+    pub fn with_subject<T: AsRef<str>>(mut self, subject: T) -> Self {
+        self.set_subject(subject);
+        self
+    }
+
+    pub fn subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    pub fn add_subject<T: Into<String>>(&mut self, subject: T) {
+        self.subjects.push(subject.into());
+    }
+
     ///
     /// 添加元数据
     ///
@@ -794,6 +1751,10 @@ impl EpubBook {
         self.meta.get(index)
     }
 
+    pub fn remove_meta(&mut self, index: usize) -> EpubMetaData {
+        self.meta.remove(index)
+    }
+
     pub fn meta_len(&self) -> usize {
         self.meta.len()
     }
@@ -813,6 +1774,34 @@ impl EpubBook {
         self.nav.push(nav);
     }
 
+    ///
+    /// 添加地标，写入时同时生成 landmarks 和 guide
+    ///
+    /// 封面页由 [EpubBook::cover_chapter] 决定，不需要添加
+    ///
+    pub fn add_landmark(&mut self, landmark: EpubLandmark) {
+        self.landmarks.push(landmark);
+    }
+
+    pub fn landmarks(&self) -> &[EpubLandmark] {
+        &self.landmarks
+    }
+
+    pub(crate) fn set_landmarks(&mut self, landmarks: Vec<EpubLandmark>) {
+        self.landmarks = landmarks;
+    }
+
+    ///
+    /// 添加页码，写入时同时生成 page-list 和 toc.ncx 中的 pageList
+    ///
+    pub fn add_page(&mut self, page: EpubPage) {
+        self.page_list.push(page);
+    }
+
+    pub fn page_list(&self) -> &[EpubPage] {
+        &self.page_list
+    }
+
     pub fn add_assets(&mut self, mut assets: EpubAssets) {
         if let Some(r) = &self.reader {
             assets.reader = Some(Arc::clone(r));
@@ -854,6 +1843,39 @@ impl EpubBook {
         self.assets.remove(index)
     }
 
+    ///
+    /// 添加其他文件，写入时原样保留
+    ///
+    /// [EpubAssets::file_name] 为 zip 中的完整路径，例如 META-INF/calibre_bookmarks.txt
+    ///
+    pub fn add_extra_file(&mut self, mut file: EpubAssets) {
+        if let Some(r) = &self.reader {
+            file.reader = Some(Arc::clone(r));
+        }
+        self.extra_files.push(file);
+    }
+
+    ///
+    /// 未在 opf 中声明的其他文件
+    ///
+    /// 读取时记录，写入时原样保留
+    ///
+    pub fn extra_files(&self) -> std::slice::Iter<'_, EpubAssets> {
+        self.extra_files.iter()
+    }
+
+    pub fn extra_files_mut(&mut self) -> std::slice::IterMut<'_, EpubAssets> {
+        self.extra_files.iter_mut()
+    }
+
+    pub fn remove_extra_file(&mut self, index: usize) -> EpubAssets {
+        self.extra_files.remove(index)
+    }
+
+    pub fn clear_extra_files(&mut self) {
+        self.extra_files.clear();
+    }
+
     pub fn add_chapter(&mut self, mut chap: EpubHtml) {
         if let Some(r) = &self.reader {
             chap.reader = Some(Arc::clone(r));
@@ -876,6 +1898,32 @@ impl EpubBook {
         self.chapters.iter()
     }
 
+    ///
+    /// 并发读取并解析章节
+    ///
+    /// 每个线程使用独立的 zip 句柄，不会在同一个锁上排队；
+    /// 通过 [crate::prelude::read_from_reader] 读取的书本无法打开新的句柄，会退化为共用同一个句柄
+    ///
+    #[cfg(feature = "parallel")]
+    pub fn par_chapters_mut(
+        &mut self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = &mut EpubHtml> + '_ {
+        use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+        let reader = self.reader.clone();
+        self.chapters.par_iter_mut().map_init(
+            move || reader.as_ref().and_then(|f| f.lock().unwrap().fork()),
+            |fork, chap| {
+                match fork {
+                    Some(reader) => chap.read_data(reader.as_mut()),
+                    None => {
+                        chap.data_mut();
+                    }
+                }
+                chap
+            },
+        )
+    }
+
     pub fn remove_chapter(&mut self, index: usize) {
         self.chapters.remove(index);
     }
@@ -911,6 +1959,39 @@ impl EpubBook {
         self.version.as_ref()
     }
 
+    ///
+    /// 是否严格按照版本号写入
+    ///
+    /// 默认情况下即使版本为 2.0 也会写入 nav.xhtml、properties 等 epub3 的内容，
+    /// 严格模式下 epub2 只写入 opf 2.0 支持的内容
+    ///
+    pub fn strict_version(&self) -> bool {
+        self.strict_version
+    }
+
+    pub fn set_strict_version(&mut self, strict: bool) {
+        self.strict_version = strict;
+    }
+
+    ///
+    /// opf 文件在 epub 中的路径，例如 OPS/package.opf
+    ///
+    /// 读取时会记录原文件中的路径，章节和资源的路径都相对于 opf 所在的目录，
+    /// 写入时会保持这个目录结构，没有设置时使用 OEBPS/content.opf
+    ///
+    pub fn opf_path(&self) -> Option<&str> {
+        self.opf_path.as_deref()
+    }
+
+    pub fn set_opf_path(&mut self, opf_path: Option<String>) {
+        self.opf_path = opf_path;
+    }
+
+    /// 按照严格的 epub2 写入
+    pub(crate) fn is_strict_epub2(&self) -> bool {
+        self.strict_version && !self.version.starts_with('3')
+    }
+
     /// 获取目录
     pub fn nav(&self) -> std::slice::Iter<'_, EpubNav> {
         self.nav.iter()
@@ -990,6 +2071,9 @@ impl EpubBook {
         self.chapters.clear();
         self.assets.clear();
         self.nav.clear();
+        self.landmarks.clear();
+        self.page_list.clear();
+        self.extra_files.clear();
         self.meta.clear();
         self.cover = None;
         self.version = String::new();
@@ -1027,6 +2111,29 @@ fn flatten_nav(nav: &[EpubNav]) -> Vec<&EpubNav> {
     }
     n
 }
+///
+/// 流式读取文件，依次尝试添加前缀以及url解码后的文件名
+///
+fn open_stream(
+    reader: &Arc<Mutex<Box<dyn EpubReaderTrait + Send + Sync>>>,
+    file_name: &str,
+) -> IResult<Box<dyn Read + Send>> {
+    let decoded = urldecode_enhanced(file_name).ok();
+    for prefix in EpubHtml::PREFIXES.iter() {
+        for name in std::iter::once(file_name).chain(decoded.as_deref()) {
+            match reader
+                .lock()
+                .unwrap()
+                .open_stream(format!("{prefix}{name}").as_str())
+            {
+                Err(IError::FileNotFound) => {}
+                v => return v,
+            }
+        }
+    }
+    Err(IError::FileNotFound)
+}
+
 pub(crate) trait EpubReaderTrait: Send + Sync {
     fn read(&mut self, book: &mut EpubBook) -> IResult<()>;
     ///
@@ -1043,6 +2150,37 @@ pub(crate) trait EpubReaderTrait: Send + Sync {
     /// file epub中的文件目录
     ///
     fn read_to_path(&mut self, file_name: &str, file_path: &str) -> IResult<()>;
+
+    ///
+    /// 流式读取文件，不会把整个文件读取到内存中
+    ///
+    fn open_stream(&mut self, file_name: &str) -> IResult<Box<dyn Read + Send>> {
+        Ok(Box::new(std::io::Cursor::new(self.read_file(file_name)?)))
+    }
+
+    ///
+    /// 打开独立的 zip 句柄，用于多线程并发读取
+    ///
+    /// 无法重新打开数据源时返回 None
+    ///
+    #[cfg(feature = "parallel")]
+    fn fork(&self) -> Option<Box<dyn EpubReaderTrait + Send + Sync>> {
+        None
+    }
+
+    ///
+    /// 是否是宽松模式，宽松模式下章节解析失败时使用宽松方式解析
+    ///
+    fn lenient(&self) -> bool {
+        false
+    }
+
+    ///
+    /// 取出宽松模式下读取时做出的修复
+    ///
+    fn take_warnings(&mut self) -> Vec<super::reader::EpubWarning> {
+        Vec::new()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,656 @@
+//! 比较两本电子书
+//!
+//! 报告以下差异：
+//!
+//! - 元数据，包括标题、作者、语言等
+//! - 新增、删除以及调整了顺序的章节
+//! - 目录的变化
+//! - 内容不同的资源文件，使用 sha1 比较
+//! - 章节中的段落，使用 [`crate::parser::HtmlParser::extract_paragraphs`] 提取后逐段比较
+//!
+//! 章节和资源都以文件名作为标识，重命名的文件会被当作删除后再新增
+//!
+
+use std::io::Read;
+
+use super::common;
+use crate::prelude::*;
+
+///
+/// 两本书之间的一处差异
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookChange {
+    /// 元数据变化，没有值时为 None
+    Metadata {
+        field: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// 新增的章节，index 为在新书中的位置
+    ChapterAdded {
+        file_name: String,
+        title: String,
+        index: usize,
+    },
+    /// 删除的章节，index 为在旧书中的位置
+    ChapterRemoved {
+        file_name: String,
+        title: String,
+        index: usize,
+    },
+    /// 调整了顺序的章节
+    ChapterMoved {
+        file_name: String,
+        from: usize,
+        to: usize,
+    },
+    /// 新增的段落，index 为在新章节中的位置
+    ParagraphAdded {
+        file_name: String,
+        index: usize,
+        text: String,
+    },
+    /// 删除的段落，index 为在旧章节中的位置
+    ParagraphRemoved {
+        file_name: String,
+        index: usize,
+        text: String,
+    },
+    /// 段落没有变化，但是标题或者标签、样式等不同
+    ChapterModified {
+        file_name: String,
+    },
+    /// 新增的目录，path 为各级目录标题，使用 / 连接
+    NavAdded {
+        path: String,
+        file_name: String,
+    },
+    /// 删除的目录
+    NavRemoved {
+        path: String,
+        file_name: String,
+    },
+    AssetAdded {
+        file_name: String,
+    },
+    AssetRemoved {
+        file_name: String,
+    },
+    /// 文件名相同但是内容不同的资源
+    AssetModified {
+        file_name: String,
+    },
+}
+
+impl BookChange {
+    ///
+    /// 差异类型，也是json中的type字段
+    ///
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BookChange::Metadata { .. } => "metadata",
+            BookChange::ChapterAdded { .. } => "chapter_added",
+            BookChange::ChapterRemoved { .. } => "chapter_removed",
+            BookChange::ChapterMoved { .. } => "chapter_moved",
+            BookChange::ParagraphAdded { .. } => "paragraph_added",
+            BookChange::ParagraphRemoved { .. } => "paragraph_removed",
+            BookChange::ChapterModified { .. } => "chapter_modified",
+            BookChange::NavAdded { .. } => "nav_added",
+            BookChange::NavRemoved { .. } => "nav_removed",
+            BookChange::AssetAdded { .. } => "asset_added",
+            BookChange::AssetRemoved { .. } => "asset_removed",
+            BookChange::AssetModified { .. } => "asset_modified",
+        }
+    }
+
+    ///
+    /// 转换为json对象
+    ///
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![("type", Some(self.kind().to_string()))];
+        let mut numbers = Vec::new();
+        match self {
+            BookChange::Metadata { field, old, new } => {
+                fields.push(("field", Some(field.clone())));
+                fields.push(("old", old.clone()));
+                fields.push(("new", new.clone()));
+            }
+            BookChange::ChapterAdded {
+                file_name,
+                title,
+                index,
+            }
+            | BookChange::ChapterRemoved {
+                file_name,
+                title,
+                index,
+            } => {
+                fields.push(("file_name", Some(file_name.clone())));
+                fields.push(("title", Some(title.clone())));
+                numbers.push(("index", *index));
+            }
+            BookChange::ChapterMoved {
+                file_name,
+                from,
+                to,
+            } => {
+                fields.push(("file_name", Some(file_name.clone())));
+                numbers.push(("from", *from));
+                numbers.push(("to", *to));
+            }
+            BookChange::ParagraphAdded {
+                file_name,
+                index,
+                text,
+            }
+            | BookChange::ParagraphRemoved {
+                file_name,
+                index,
+                text,
+            } => {
+                fields.push(("file_name", Some(file_name.clone())));
+                fields.push(("text", Some(text.clone())));
+                numbers.push(("index", *index));
+            }
+            BookChange::NavAdded { path, file_name }
+            | BookChange::NavRemoved { path, file_name } => {
+                fields.push(("path", Some(path.clone())));
+                fields.push(("file_name", Some(file_name.clone())));
+            }
+            BookChange::ChapterModified { file_name }
+            | BookChange::AssetAdded { file_name }
+            | BookChange::AssetRemoved { file_name }
+            | BookChange::AssetModified { file_name } => {
+                fields.push(("file_name", Some(file_name.clone())));
+            }
+        }
+        json_object(&fields, &numbers)
+    }
+}
+
+impl std::fmt::Display for BookChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookChange::Metadata { field, old, new } => write!(
+                f,
+                "~ 元数据 {field}: {} -> {}",
+                old.as_deref().unwrap_or("(无)"),
+                new.as_deref().unwrap_or("(无)")
+            ),
+            BookChange::ChapterAdded {
+                file_name,
+                title,
+                index,
+            } => write!(f, "+ 章节 [{index}] {file_name} {title}"),
+            BookChange::ChapterRemoved {
+                file_name,
+                title,
+                index,
+            } => write!(f, "- 章节 [{index}] {file_name} {title}"),
+            BookChange::ChapterMoved {
+                file_name,
+                from,
+                to,
+            } => write!(f, "> 章节 {file_name} [{from}] -> [{to}]"),
+            BookChange::ParagraphAdded {
+                file_name,
+                index,
+                text,
+            } => write!(f, "+ {file_name} 段落 [{index}] {text}"),
+            BookChange::ParagraphRemoved {
+                file_name,
+                index,
+                text,
+            } => write!(f, "- {file_name} 段落 [{index}] {text}"),
+            BookChange::ChapterModified { file_name } => write!(f, "~ 章节 {file_name}"),
+            BookChange::NavAdded { path, file_name } => write!(f, "+ 目录 {path} ({file_name})"),
+            BookChange::NavRemoved { path, file_name } => {
+                write!(f, "- 目录 {path} ({file_name})")
+            }
+            BookChange::AssetAdded { file_name } => write!(f, "+ 资源 {file_name}"),
+            BookChange::AssetRemoved { file_name } => write!(f, "- 资源 {file_name}"),
+            BookChange::AssetModified { file_name } => write!(f, "~ 资源 {file_name}"),
+        }
+    }
+}
+
+///
+/// 转换为json数组
+///
+pub fn to_json(changes: &[BookChange]) -> String {
+    let items: Vec<String> = changes.iter().map(|f| f.to_json()).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn json_object(fields: &[(&str, Option<String>)], numbers: &[(&str, usize)]) -> String {
+    let mut items: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| match value {
+            Some(v) => format!("\"{key}\":{}", crate::common::json_string(v)),
+            None => format!("\"{key}\":null"),
+        })
+        .collect();
+    items.extend(
+        numbers
+            .iter()
+            .map(|(key, value)| format!("\"{key}\":{value}")),
+    );
+    format!("{{{}}}", items.join(","))
+}
+
+///
+/// 比较两本书
+///
+/// 需要读取章节和资源的内容，比较之前没有加载到内存中的章节会在比较后释放
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::diff::diff_book;
+///
+/// let mut old = read_from_file("old.epub").unwrap();
+/// let mut new = read_from_file("new.epub").unwrap();
+/// for ele in diff_book(&mut old, &mut new) {
+///     println!("{ele}");
+/// }
+/// ```
+pub fn diff_book(old: &mut EpubBook, new: &mut EpubBook) -> Vec<BookChange> {
+    let mut changes = diff_metadata(old, new);
+    diff_chapters(old, new, &mut changes);
+    diff_nav(old, new, &mut changes);
+    diff_assets(old, new, &mut changes);
+    changes
+}
+
+fn diff_metadata(old: &EpubBook, new: &EpubBook) -> Vec<BookChange> {
+    let persons = |v: &[EpubPerson]| {
+        Some(v.iter().map(|f| f.name()).collect::<Vec<&str>>().join(", ")).filter(|f| !f.is_empty())
+    };
+    let values = |book: &EpubBook| -> Vec<(&'static str, Option<String>)> {
+        let text = |v: &str| Some(v.to_string()).filter(|f| !f.is_empty());
+        vec![
+            ("title", text(book.title())),
+            ("identifier", text(book.identifier())),
+            ("creator", persons(book.creators())),
+            ("contributor", persons(book.contributors())),
+            ("subject", text(book.subjects().join(", ").as_str())),
+            ("description", book.description().map(|f| f.to_string())),
+            ("date", book.date().map(|f| f.to_string())),
+            ("publisher", book.publisher().map(|f| f.to_string())),
+            ("series", book.series().map(|f| f.to_string())),
+            ("series_index", book.series_index().map(|f| f.to_string())),
+            ("language", book.language().map(|f| f.to_string())),
+            ("version", text(book.version())),
+            ("last_modify", book.last_modify().map(|f| f.to_string())),
+        ]
+    };
+    values(old)
+        .into_iter()
+        .zip(values(new))
+        .filter(|((_, o), (_, n))| o != n)
+        .map(|((field, old), (_, new))| BookChange::Metadata {
+            field: field.to_string(),
+            old,
+            new,
+        })
+        .collect()
+}
+
+/// 读取到的 nav.xhtml 由目录生成，目录的变化单独比较
+fn is_nav_file(file_name: &str) -> bool {
+    file_name == common::NAV.replace(common::EPUB, "")
+}
+
+fn chapter_names(book: &EpubBook) -> Vec<(String, String)> {
+    book.chapters()
+        .filter(|f| !is_nav_file(f.file_name()))
+        .map(|f| (f.file_name().to_string(), f.title().to_string()))
+        .collect()
+}
+
+fn diff_chapters(old: &mut EpubBook, new: &mut EpubBook, changes: &mut Vec<BookChange>) {
+    let old_names = chapter_names(old);
+    let new_names = chapter_names(new);
+
+    for (index, (file_name, title)) in old_names.iter().enumerate() {
+        if !new_names.iter().any(|(f, _)| f == file_name) {
+            changes.push(BookChange::ChapterRemoved {
+                file_name: file_name.clone(),
+                title: title.clone(),
+                index,
+            });
+        }
+    }
+    for (index, (file_name, title)) in new_names.iter().enumerate() {
+        if !old_names.iter().any(|(f, _)| f == file_name) {
+            changes.push(BookChange::ChapterAdded {
+                file_name: file_name.clone(),
+                title: title.clone(),
+                index,
+            });
+        }
+    }
+
+    // 两本书都有的章节，不在最长公共子序列中的就是调整了顺序的
+    let common_old: Vec<&str> = old_names
+        .iter()
+        .map(|(f, _)| f.as_str())
+        .filter(|f| new_names.iter().any(|(n, _)| n == f))
+        .collect();
+    let common_new: Vec<&str> = new_names
+        .iter()
+        .map(|(f, _)| f.as_str())
+        .filter(|f| old_names.iter().any(|(o, _)| o == f))
+        .collect();
+    let (kept, _) = lcs(&common_old, &common_new);
+    for (index, (file_name, _)) in new_names.iter().enumerate() {
+        if let Some(i) = common_new.iter().position(|f| f == file_name) {
+            if !kept.contains(&i) {
+                changes.push(BookChange::ChapterMoved {
+                    file_name: file_name.clone(),
+                    from: old_names
+                        .iter()
+                        .position(|(f, _)| f == file_name)
+                        .unwrap_or_default(),
+                    to: index,
+                });
+            }
+        }
+    }
+
+    for file_name in common_new {
+        let (Some(o), Some(n)) = (
+            old.get_chapter_mut(file_name),
+            new.get_chapter_mut(file_name),
+        ) else {
+            continue;
+        };
+        diff_chapter(file_name, o, n, changes);
+    }
+}
+
+fn diff_chapter(
+    file_name: &str,
+    old: &mut EpubHtml,
+    new: &mut EpubHtml,
+    changes: &mut Vec<BookChange>,
+) {
+    let old_loaded = old.data().is_some();
+    let new_loaded = new.data().is_some();
+
+    let paragraphs = |chap: &mut EpubHtml| {
+        chap.parser()
+            .map(|f| f.extract_paragraphs())
+            .unwrap_or_default()
+    };
+    let o = paragraphs(old);
+    let n = paragraphs(new);
+    if o == n {
+        if old.title() != new.title() || old.data() != new.data() {
+            changes.push(BookChange::ChapterModified {
+                file_name: file_name.to_string(),
+            });
+        }
+    } else {
+        let (_, (removed, added)) = lcs(&o, &n);
+        for index in removed {
+            changes.push(BookChange::ParagraphRemoved {
+                file_name: file_name.to_string(),
+                index,
+                text: o[index].clone(),
+            });
+        }
+        for index in added {
+            changes.push(BookChange::ParagraphAdded {
+                file_name: file_name.to_string(),
+                index,
+                text: n[index].clone(),
+            });
+        }
+    }
+
+    if !old_loaded {
+        old.release_data();
+    }
+    if !new_loaded {
+        new.release_data();
+    }
+}
+
+///
+/// 最长公共子序列
+///
+/// 返回公共子序列在 new 中的位置，以及 old 中被删除、new 中新增的位置
+///
+#[allow(clippy::type_complexity)]
+fn lcs<T: PartialEq>(old: &[T], new: &[T]) -> (Vec<usize>, (Vec<usize>, Vec<usize>)) {
+    // 去掉相同的开头和结尾，减少需要计算的范围
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let o = &old[prefix..old.len() - suffix];
+    let n = &new[prefix..new.len() - suffix];
+
+    let mut table = vec![vec![0u32; n.len() + 1]; o.len() + 1];
+    for i in (0..o.len()).rev() {
+        for j in (0..n.len()).rev() {
+            table[i][j] = if o[i] == n[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    let mut kept: Vec<usize> = (0..prefix).collect();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < o.len() && j < n.len() {
+        if o[i] == n[j] {
+            kept.push(prefix + j);
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            removed.push(prefix + i);
+            i += 1;
+        } else {
+            added.push(prefix + j);
+            j += 1;
+        }
+    }
+    removed.extend((i..o.len()).map(|f| prefix + f));
+    added.extend((j..n.len()).map(|f| prefix + f));
+    kept.extend((new.len() - suffix)..new.len());
+    (kept, (removed, added))
+}
+
+fn flatten_nav(nav: std::slice::Iter<'_, EpubNav>, parent: &str, out: &mut Vec<(String, String)>) {
+    for ele in nav {
+        let path = if parent.is_empty() {
+            ele.title().to_string()
+        } else {
+            format!("{parent} / {}", ele.title())
+        };
+        out.push((path.clone(), ele.file_name().to_string()));
+        flatten_nav(ele.child(), path.as_str(), out);
+    }
+}
+
+fn diff_nav(old: &EpubBook, new: &EpubBook, changes: &mut Vec<BookChange>) {
+    let mut o = Vec::new();
+    flatten_nav(old.nav(), "", &mut o);
+    let mut n = Vec::new();
+    flatten_nav(new.nav(), "", &mut n);
+
+    let (_, (removed, added)) = lcs(&o, &n);
+    for index in removed {
+        let (path, file_name) = o[index].clone();
+        changes.push(BookChange::NavRemoved { path, file_name });
+    }
+    for index in added {
+        let (path, file_name) = n[index].clone();
+        changes.push(BookChange::NavAdded { path, file_name });
+    }
+}
+
+fn asset_hash(asset: &EpubAssets) -> Option<[u8; 20]> {
+    let mut data = Vec::new();
+    asset.reader().ok()?.read_to_end(&mut data).ok()?;
+    Some(crate::common::sha1(&data))
+}
+
+fn diff_assets(old: &EpubBook, new: &EpubBook, changes: &mut Vec<BookChange>) {
+    let assets = |book: &EpubBook| -> Vec<String> {
+        book.assets()
+            .chain(book.cover())
+            .map(|f| f.file_name().to_string())
+            .filter(|f| !is_nav_file(f))
+            .fold(Vec::new(), |mut v, f| {
+                if !v.contains(&f) {
+                    v.push(f);
+                }
+                v
+            })
+    };
+    let find = |book: &'_ EpubBook, file_name: &str| -> Option<[u8; 20]> {
+        book.get_assets(file_name)
+            .or_else(|| book.cover().filter(|f| f.file_name() == file_name))
+            .and_then(asset_hash)
+    };
+    let o = assets(old);
+    let n = assets(new);
+    for file_name in &o {
+        if !n.contains(file_name) {
+            changes.push(BookChange::AssetRemoved {
+                file_name: file_name.clone(),
+            });
+        }
+    }
+    for file_name in &n {
+        if !o.contains(file_name) {
+            changes.push(BookChange::AssetAdded {
+                file_name: file_name.clone(),
+            });
+        } else if find(old, file_name) != find(new, file_name) {
+            changes.push(BookChange::AssetModified {
+                file_name: file_name.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_book(paragraphs: &[&str], chapters: &[&str], image: &[u8]) -> EpubBook {
+        let mut builder = EpubBuilder::default()
+            .with_title("书名")
+            .with_creator("作者")
+            .add_assets("image/a.png", image.to_vec());
+        for (index, name) in chapters.iter().enumerate() {
+            let data = paragraphs
+                .iter()
+                .map(|f| format!("<p>{name}{f}</p>"))
+                .collect::<String>();
+            builder = builder.add_chapter(
+                EpubHtml::default()
+                    .with_file_name(format!("{name}.xhtml"))
+                    .with_title(format!("第{index}章"))
+                    .with_data(data.into_bytes()),
+            );
+        }
+        builder.book().unwrap()
+    }
+
+    fn write(book: &mut EpubBook) -> Vec<u8> {
+        EpubWriter::write_to_mem(book, false).unwrap()
+    }
+
+    #[test]
+    fn test_lcs() {
+        let (kept, (removed, added)) = lcs(&["a", "b", "c", "d"], &["a", "c", "e", "d"]);
+        assert_eq!(vec![0, 1, 3], kept);
+        assert_eq!(vec![1], removed);
+        assert_eq!(vec![2], added);
+
+        let (kept, (removed, added)) = lcs::<&str>(&[], &["a"]);
+        assert!(kept.is_empty());
+        assert!(removed.is_empty());
+        assert_eq!(vec![0], added);
+    }
+
+    #[test]
+    fn test_diff_book() {
+        let mut old = create_book(&["一", "二", "三"], &["a", "b", "c"], &[1, 2, 3]);
+        let mut old = read_from_vec(write(&mut old)).unwrap();
+        let mut new = create_book(&["一", "三", "四"], &["b", "a", "d"], &[1, 2]);
+        new.set_title("新书名");
+        new.add_nav(
+            EpubNav::default()
+                .with_title("第一卷")
+                .with_file_name("b.xhtml"),
+        );
+        let data = write(&mut new);
+        let mut new = read_from_vec(data.clone()).unwrap();
+
+        let changes = diff_book(&mut old, &mut new);
+        assert!(changes.contains(&BookChange::Metadata {
+            field: "title".to_string(),
+            old: Some("书名".to_string()),
+            new: Some("新书名".to_string()),
+        }));
+        assert!(changes.contains(&BookChange::ChapterRemoved {
+            file_name: "c.xhtml".to_string(),
+            title: "3. 第2章".to_string(),
+            index: 2,
+        }));
+        assert!(changes.contains(&BookChange::ChapterAdded {
+            file_name: "d.xhtml".to_string(),
+            title: "3. 第2章".to_string(),
+            index: 2,
+        }));
+        assert_eq!(
+            1,
+            changes
+                .iter()
+                .filter(|f| matches!(f, BookChange::ChapterMoved { .. }))
+                .count()
+        );
+        assert!(changes.contains(&BookChange::ParagraphRemoved {
+            file_name: "a.xhtml".to_string(),
+            index: 1,
+            text: "a二".to_string(),
+        }));
+        assert!(changes.contains(&BookChange::ParagraphAdded {
+            file_name: "b.xhtml".to_string(),
+            index: 2,
+            text: "b四".to_string(),
+        }));
+        assert!(changes.contains(&BookChange::NavAdded {
+            path: "第一卷".to_string(),
+            file_name: "b.xhtml".to_string(),
+        }));
+        assert!(changes.contains(&BookChange::AssetModified {
+            file_name: "image/a.png".to_string(),
+        }));
+        // 读取的章节比较后释放
+        assert!(new.chapters().all(|f| f.data().is_none()));
+
+        let json = to_json(&changes);
+        assert!(
+            json.starts_with(r#"[{"type":"metadata","field":"title","old":"书名","new":"新书名"}"#)
+        );
+        assert!(json.contains(
+            r#"{"type":"paragraph_added","file_name":"b.xhtml","text":"b四","index":2}"#
+        ));
+
+        assert!(diff_book(&mut read_from_vec(data).unwrap(), &mut new).is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! 字体混淆
+//!
+//! 支持 IDPF 和 Adobe 两种算法，对应 META-INF/encryption.xml 文件
+//!
+//! - IDPF: 密钥为 unique-identifier 去除空白字符后的 SHA-1，混淆前 1040 字节
+//! - Adobe: 密钥为 urn:uuid 标识的 16 字节，混淆前 1024 字节
+//!
+
+use std::collections::HashMap;
+
+use quick_xml::events::{BytesStart, Event};
+
+use crate::common::{sha1, IError, IResult};
+
+pub(crate) static ENCRYPTION: &str = "META-INF/encryption.xml";
+
+static IDPF_ALGORITHM: &str = "http://www.idpf.org/2008/embedding";
+static ADOBE_ALGORITHM: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+crate::cache_enum! {
+    ///
+    /// 字体混淆算法
+    ///
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum FontObfuscation {
+        /// http://www.idpf.org/2008/embedding
+        Idpf,
+        /// http://ns.adobe.com/pdf/enc#RC
+        Adobe,
+    }
+}
+
+impl FontObfuscation {
+    pub(crate) fn algorithm(&self) -> &'static str {
+        match self {
+            FontObfuscation::Idpf => IDPF_ALGORITHM,
+            FontObfuscation::Adobe => ADOBE_ALGORITHM,
+        }
+    }
+
+    fn from_algorithm(value: &str) -> Option<Self> {
+        if value == IDPF_ALGORITHM {
+            Some(FontObfuscation::Idpf)
+        } else if value == ADOBE_ALGORITHM {
+            Some(FontObfuscation::Adobe)
+        } else {
+            None
+        }
+    }
+
+    /// 被混淆的字节数
+    fn length(&self) -> usize {
+        match self {
+            FontObfuscation::Idpf => 1040,
+            FontObfuscation::Adobe => 1024,
+        }
+    }
+
+    ///
+    /// 生成密钥
+    ///
+    /// [identifier] IDPF 使用 unique-identifier，Adobe 使用 urn:uuid 标识
+    ///
+    pub(crate) fn key(&self, identifier: &str) -> Option<Vec<u8>> {
+        match self {
+            FontObfuscation::Idpf => {
+                let id: String = identifier
+                    .chars()
+                    .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+                    .collect();
+                Some(sha1(id.as_bytes()).to_vec())
+            }
+            FontObfuscation::Adobe => {
+                let id = identifier.trim();
+                let id = id
+                    .strip_prefix("urn:uuid:")
+                    .or_else(|| id.strip_prefix("uuid:"))
+                    .unwrap_or(id)
+                    .replace('-', "");
+                if id.len() != 32 {
+                    return None;
+                }
+                (0..16)
+                    .map(|i| u8::from_str_radix(&id[i * 2..i * 2 + 2], 16).ok())
+                    .collect()
+            }
+        }
+    }
+
+    ///
+    /// 混淆或者反混淆，两者是同一个操作
+    ///
+    /// [offset] data 在文件中的偏移，用于分段处理
+    ///
+    pub(crate) fn apply(&self, key: &[u8], data: &mut [u8], offset: usize) {
+        if key.is_empty() {
+            return;
+        }
+        let len = self.length();
+        for (index, byte) in data.iter_mut().enumerate() {
+            let pos = offset + index;
+            if pos >= len {
+                break;
+            }
+            *byte ^= key[pos % key.len()];
+        }
+    }
+}
+
+///
+/// 读取时混淆或者反混淆，用于流式处理
+///
+pub(crate) struct ObfuscationReader<R> {
+    inner: R,
+    obfuscation: FontObfuscation,
+    key: Vec<u8>,
+    offset: usize,
+}
+
+impl<R> ObfuscationReader<R> {
+    pub(crate) fn new(inner: R, obfuscation: FontObfuscation, key: Vec<u8>) -> Self {
+        ObfuscationReader {
+            inner,
+            obfuscation,
+            key,
+            offset: 0,
+        }
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for ObfuscationReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.obfuscation
+            .apply(&self.key, &mut buf[..len], self.offset);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+///
+/// 解析 encryption.xml
+///
+/// 返回 文件路径 -> 算法，不支持的算法会被忽略
+///
+pub(crate) fn read_encryption_xml(xml: &str) -> IResult<HashMap<String, FontObfuscation>> {
+    let mut reader = quick_xml::reader::Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut map = HashMap::new();
+    let mut algorithm = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(IError::Xml(e)),
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"EncryptedData" => algorithm = None,
+                b"EncryptionMethod" => {
+                    algorithm = get_attribute(&e, "Algorithm")
+                        .and_then(|f| FontObfuscation::from_algorithm(f.as_str()));
+                }
+                b"CipherReference" => {
+                    if let (Some(alg), Some(uri)) = (algorithm, get_attribute(&e, "URI")) {
+                        let uri = crate::common::urldecode_enhanced(uri.as_str()).unwrap_or(uri);
+                        map.insert(uri, alg);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    Ok(map)
+}
+
+fn get_attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+}
+
+///
+/// 生成 encryption.xml
+///
+/// [files] 文件路径（从epub根目录开始）以及对应算法
+///
+pub(crate) fn to_encryption_xml(files: &[(String, FontObfuscation)]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">"#,
+    );
+    for (file, alg) in files {
+        xml.push_str(
+            format!(
+                r#"<enc:EncryptedData><enc:EncryptionMethod Algorithm="{}"/><enc:CipherData><enc:CipherReference URI="{}"/></enc:CipherData></enc:EncryptedData>"#,
+                alg.algorithm(),
+                crate::common::escape_xml(file.as_str())
+            )
+            .as_str(),
+        );
+    }
+    xml.push_str("</encryption>");
+    xml
+}
+
+///
+/// 从opf中获取混淆所需的标识
+///
+/// 返回 (unique-identifier 对应的标识, urn:uuid 格式的标识)
+///
+pub(crate) fn get_identifiers(opf: &str) -> IResult<(Option<String>, Option<String>)> {
+    let mut reader = quick_xml::reader::Reader::from_str(opf);
+    reader.config_mut().trim_text(true);
+
+    let mut unique_id = None;
+    let mut identifiers: Vec<(Option<String>, String)> = Vec::new();
+    let mut current: Option<Option<String>> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(IError::Xml(e)),
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"package" => unique_id = get_attribute(&e, "unique-identifier"),
+                b"identifier" => {
+                    current = Some(get_attribute(&e, "id"));
+                    text.clear();
+                }
+                _ => {}
+            },
+            Ok(Event::Text(e)) if current.is_some() => {
+                text.push_str(&e.decode().map_err(IError::Encoding)?);
+            }
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"identifier" => {
+                    if let Some(id) = current.take() {
+                        identifiers.push((id, text.trim().to_string()));
+                    }
+                }
+                b"metadata" => break,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    let unique = identifiers
+        .iter()
+        .find(|(id, _)| id.is_some() && *id == unique_id)
+        .or_else(|| identifiers.first())
+        .map(|(_, v)| v.clone());
+    let uuid = identifiers
+        .iter()
+        .map(|(_, v)| v)
+        .find(|v| FontObfuscation::Adobe.key(v).is_some() && v.contains("uuid"))
+        .or(unique.as_ref())
+        .cloned();
+    Ok((unique, uuid))
+}
+
+/// 是否是字体文件
+pub(crate) fn is_font(file_name: &str, media_type: &str) -> bool {
+    let media_type = if media_type.is_empty() {
+        crate::common::get_media_type(file_name)
+    } else {
+        media_type.to_string()
+    };
+    media_type.starts_with("font/")
+        || media_type.starts_with("application/font")
+        || media_type.starts_with("application/x-font")
+        || media_type == "application/vnd.ms-opentype"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_identifiers, read_encryption_xml, to_encryption_xml, FontObfuscation};
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            Some(vec![
+                0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+                0xde, 0xf0
+            ]),
+            FontObfuscation::Adobe.key("urn:uuid:12345678-9abc-def0-1234-56789abcdef0")
+        );
+        assert_eq!(None, FontObfuscation::Adobe.key("isbn"));
+        assert_eq!(
+            FontObfuscation::Idpf.key("urn:uuid:1 2"),
+            FontObfuscation::Idpf.key("urn:uuid:12\n")
+        );
+
+        let key = FontObfuscation::Idpf.key("id").unwrap();
+        let origin: Vec<u8> = (0..2000).map(|f| (f % 256) as u8).collect();
+        let mut data = origin.clone();
+        FontObfuscation::Idpf.apply(&key, &mut data, 0);
+        assert_ne!(origin[..1040], data[..1040]);
+        assert_eq!(origin[1040..], data[1040..]);
+        // 分段处理
+        FontObfuscation::Idpf.apply(&key, &mut data[..500], 0);
+        FontObfuscation::Idpf.apply(&key, &mut data[500..], 500);
+        assert_eq!(origin, data);
+    }
+
+    #[test]
+    fn test_encryption_xml() {
+        let xml = to_encryption_xml(&[
+            ("OEBPS/fonts/a.ttf".to_string(), FontObfuscation::Idpf),
+            ("OEBPS/fonts/b.otf".to_string(), FontObfuscation::Adobe),
+        ]);
+        let map = read_encryption_xml(xml.as_str()).unwrap();
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&FontObfuscation::Idpf), map.get("OEBPS/fonts/a.ttf"));
+        assert_eq!(Some(&FontObfuscation::Adobe), map.get("OEBPS/fonts/b.otf"));
+
+        let map = read_encryption_xml(
+            r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><EncryptedData xmlns="http://www.w3.org/2001/04/xmlenc#"><EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/><CipherData><CipherReference URI="OEBPS/1.xhtml"/></CipherData></EncryptedData></encryption>"#,
+        )
+        .unwrap();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_get_identifiers() {
+        let opf = r#"<package unique-identifier="bookid" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:identifier id="isbn">9787000000000</dc:identifier><dc:identifier id="bookid">urn:uuid:12345678-9abc-def0-1234-56789abcdef0</dc:identifier></metadata></package>"#;
+        let (unique, uuid) = get_identifiers(opf).unwrap();
+        assert_eq!(
+            Some("urn:uuid:12345678-9abc-def0-1234-56789abcdef0"),
+            unique.as_deref()
+        );
+        assert_eq!(unique, uuid);
+    }
+}
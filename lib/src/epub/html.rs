@@ -5,7 +5,15 @@ use crate::{common::get_media_type, prelude::*};
 use quick_xml::events::Event;
 
 /// 生成html
-pub(crate) fn to_html(chap: &mut EpubHtml, append_title: bool, dir: &Option<Direction>) -> String {
+///
+/// [epub2] 严格的 epub2，使用 xhtml 1.1 的 doctype，不声明 epub 命名空间
+///
+pub(crate) fn to_html(
+    chap: &mut EpubHtml,
+    append_title: bool,
+    dir: &Option<Direction>,
+    epub2: bool,
+) -> String {
     let mut css = String::new();
     if let Some(links) = chap.links() {
         for ele in links {
@@ -30,13 +38,14 @@ pub(crate) fn to_html(chap: &mut EpubHtml, append_title: bool, dir: &Option<Dire
     if let Some(v) = cus_css {
         css.push_str(format!("\n<style type=\"text/css\">{}</style>", v).as_str());
     }
+    if let Some(v) = chap.head() {
+        css.push_str(format!("\n{v}").as_str());
+    }
     let mut body = String::new();
     {
         body.insert_str(
             0,
-            String::from_utf8(chap.data_mut().as_ref().unwrap().to_vec())
-                .unwrap()
-                .as_str(),
+            crate::common::decode_text(chap.data_mut().as_ref().unwrap()).as_str(),
         );
         // 正文
     }
@@ -46,12 +55,39 @@ pub(crate) fn to_html(chap: &mut EpubHtml, append_title: bool, dir: &Option<Dire
     } else if let Some(d) = dir {
         dir_s = format!(r#" dir="{d}""#);
     }
+    // 严格的 epub2 没有声明 epub 命名空间，需要移除 epub: 属性
+    let attribute = |f: &Vec<u8>| {
+        if epub2 {
+            String::from_utf8(remove_epub_attributes(f)).ok()
+        } else {
+            String::from_utf8(f.clone()).ok()
+        }
+    };
+    let html_attribute = chap.html_attribute.as_ref().and_then(attribute);
+    // 原有的 epub:prefix 保留，否则使用默认的
+    let mut prefix = format!(r#" epub:prefix="{DEFAULT_EPUB_PREFIX}""#);
+    if let Some(attr) = html_attribute {
+        if attr.contains("epub:prefix=") {
+            prefix.clear();
+        }
+        dir_s.push_str(attr.as_str());
+    }
     let lang = chap.lang.as_str();
     let title = escape_xml(chap.title());
+    let html = if epub2 {
+        format!(
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.1//EN" "http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="{lang}"{dir_s}>"#
+        )
+    } else {
+        format!(
+            r#"<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"{prefix} lang="{lang}" xml:lang="{lang}"{dir_s}>"#
+        )
+    };
     format!(
         r#"<?xml version='1.0' encoding='utf-8'?>
-<!DOCTYPE html>
-<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" epub:prefix="z3998: http://www.daisy.org/z3998/2012/vocab/structure/#" lang="{lang}" xml:lang="{lang}"{dir_s}>
+{html}
   <head>
     <title>{title}</title>
 {css}
@@ -63,7 +99,7 @@ pub(crate) fn to_html(chap: &mut EpubHtml, append_title: bool, dir: &Option<Dire
 </html>"#,
         chap.body_attribute
             .as_ref()
-            .and_then(|f| String::from_utf8(f.clone()).ok())
+            .and_then(attribute)
             .unwrap_or_default(),
         if append_title {
             format!(r#"<h1 style="text-align: center">{}</h1>"#, title)
@@ -107,12 +143,48 @@ fn to_nav_xml(nav: std::slice::Iter<EpubNav>) -> String {
 pub(crate) fn to_nav_html(
     book_title: &str,
     nav: std::slice::Iter<EpubNav>,
+    landmarks: &[EpubLandmark],
+    pages: &[EpubPage],
     lang: &str,
     dir: &Option<Direction>,
 ) -> String {
     let book_title = escape_xml(book_title);
+    let mut other = String::new();
+    if !landmarks.is_empty() {
+        other.push_str(r#"<nav epub:type="landmarks" id="landmarks" hidden=""><ol>"#);
+        for ele in landmarks {
+            other.push_str(
+                format!(
+                    r#"<li><a epub:type="{}" href="{}">{}</a></li>"#,
+                    escape_xml(ele.epub_type()),
+                    escape_xml(ele.href()),
+                    escape_xml(if ele.title().is_empty() {
+                        ele.epub_type()
+                    } else {
+                        ele.title()
+                    }),
+                )
+                .as_str(),
+            );
+        }
+        other.push_str("</ol></nav>");
+    }
+    if !pages.is_empty() {
+        other.push_str(r#"<nav epub:type="page-list" id="page-list" hidden=""><ol>"#);
+        for ele in pages {
+            other.push_str(
+                format!(
+                    r#"<li><a href="{}">{}</a></li>"#,
+                    escape_xml(ele.href()),
+                    escape_xml(ele.label()),
+                )
+                .as_str(),
+            );
+        }
+        other.push_str("</ol></nav>");
+    }
     format!(
-        r#"<?xml version='1.0' encoding='utf-8'?><!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}"{}><head><title>{book_title}</title></head><body><nav epub:type="toc" id="id" role="doc-toc"><h2>{book_title}</h2>{}</nav></body></html>"#,
+        r#"<?xml version='1.0' encoding='utf-8'?><!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}"{}><head><title>{book_title}</title></head><body><nav epub:type="toc" id="id" role="doc-toc"><h2>{book_title}</h2>{}</nav>{other}</body></html>"#,
         if let Some(d) = dir {
             format!(r#" dir="{d}""#)
         } else {
@@ -122,6 +194,26 @@ pub(crate) fn to_nav_html(
     )
 }
 
+///
+/// 需要写入的地标
+///
+/// 封面页由 cover_chapter 决定，其他的封面地标会被忽略
+///
+pub(crate) fn get_landmarks(book: &EpubBook) -> Vec<EpubLandmark> {
+    let mut landmarks = Vec::new();
+    if let Some(c) = book.cover_chapter() {
+        landmarks.push(EpubLandmark::new("cover", c.file_name()).with_title(c.title()));
+    }
+    let has_cover = !landmarks.is_empty();
+    landmarks.extend(
+        book.landmarks()
+            .iter()
+            .filter(|f| !has_cover || f.epub_type() != "cover")
+            .cloned(),
+    );
+    landmarks
+}
+
 fn to_toc_xml_point(nav: std::slice::Iter<EpubNav>, parent: usize) -> String {
     let mut xml = String::new();
     for (index, ele) in nav.enumerate() {
@@ -151,15 +243,143 @@ fn to_toc_xml_point(nav: std::slice::Iter<EpubNav>, parent: usize) -> String {
     xml
 }
 
+/// 生成toc.ncx中的pageList
+fn to_toc_page_list(pages: &[EpubPage]) -> String {
+    if pages.is_empty() {
+        return String::new();
+    }
+    let mut xml = String::from("<pageList>");
+    for (index, ele) in pages.iter().enumerate() {
+        // value 只能是数字，罗马数字等视为前言部分
+        let (page_type, value) = match ele.label().parse::<usize>() {
+            Ok(v) => ("normal", format!(r#" value="{v}""#)),
+            Err(_) => ("front", String::new()),
+        };
+        xml.push_str(
+            format!(
+                r#"<pageTarget id="page-{index}" type="{page_type}"{value}><navLabel><text>{}</text></navLabel><content src="{}"/></pageTarget>"#,
+                escape_xml(ele.label()),
+                escape_xml(ele.href())
+            )
+            .as_str(),
+        );
+    }
+    xml.push_str("</pageList>");
+    xml
+}
+
 /// 生成epub中的toc.ncx文件
-pub(crate) fn to_toc_xml(book_title: &str, nav: std::slice::Iter<EpubNav>) -> String {
+pub(crate) fn to_toc_xml(
+    book_title: &str,
+    nav: std::slice::Iter<EpubNav>,
+    pages: &[EpubPage],
+) -> String {
     let book_title = escape_xml(book_title);
     format!(
-        r#"<?xml version='1.0' encoding='utf-8'?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><head><meta content="1394" name="dtb:uid"/><meta content="0" name="dtb:depth"/><meta content="0" name="dtb:totalPageCount"/><meta content="0" name="dtb:maxPageNumber"/></head><docTitle><text>{book_title}</text></docTitle><navMap>{}</navMap></ncx>"#,
-        to_toc_xml_point(nav, 0)
+        r#"<?xml version='1.0' encoding='utf-8'?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><head><meta content="1394" name="dtb:uid"/><meta content="0" name="dtb:depth"/><meta content="0" name="dtb:totalPageCount"/><meta content="0" name="dtb:maxPageNumber"/></head><docTitle><text>{book_title}</text></docTitle><navMap>{}</navMap>{}</ncx>"#,
+        to_toc_xml_point(nav, 0),
+        to_toc_page_list(pages)
     )
 }
 
+/// 生成元数据的id，第一个不带序号
+fn gen_meta_id(prefix: &str, index: usize) -> String {
+    if index == 0 {
+        prefix.to_string()
+    } else {
+        format!("{prefix}{index}")
+    }
+}
+
+///
+/// 写入 epub3 的 refines 元数据
+///
+/// [lang] alternate-script 需要的语言
+///
+fn write_refines(
+    xml: &mut quick_xml::Writer<std::io::Cursor<Vec<u8>>>,
+    id: &str,
+    property: &str,
+    value: &str,
+    lang: Option<&str>,
+) -> IResult<()> {
+    let refines = format!("#{id}");
+    let mut ele = xml
+        .create_element("meta")
+        .with_attribute(("refines", refines.as_str()))
+        .with_attribute(("property", property));
+    if property == "role" {
+        ele = ele.with_attribute(("scheme", "marc:relators"));
+    }
+    if let Some(lang) = lang {
+        ele = ele.with_attribute(("xml:lang", lang));
+    }
+    ele.write_text_content(quick_xml::events::BytesText::new(value))?;
+    Ok(())
+}
+
+/// 写入作者、参与者
+fn write_person(
+    xml: &mut quick_xml::Writer<std::io::Cursor<Vec<u8>>>,
+    tag: &str,
+    id: &str,
+    person: &EpubPerson,
+    epub3: bool,
+) -> IResult<()> {
+    let mut ele = xml.create_element(tag).with_attribute(("id", id));
+    if !epub3 {
+        if let Some(role) = person.role() {
+            ele = ele.with_attribute(("opf:role", role));
+        }
+        if let Some(file_as) = person.file_as() {
+            ele = ele.with_attribute(("opf:file-as", file_as));
+        }
+    }
+    ele.write_text_content(quick_xml::events::BytesText::new(person.name()))?;
+    if epub3 {
+        if let Some(role) = person.role() {
+            write_refines(xml, id, "role", role, None)?;
+        }
+        if let Some(file_as) = person.file_as() {
+            write_refines(xml, id, "file-as", file_as, None)?;
+        }
+        for (lang, value) in person.alternate_script() {
+            write_refines(xml, id, "alternate-script", value, Some(lang))?;
+        }
+    }
+    Ok(())
+}
+
+/// 写入 spine 中的 itemref，保留 id、linear 和 properties
+fn write_itemref(
+    xml: &mut quick_xml::Writer<std::io::Cursor<Vec<u8>>>,
+    idref: &str,
+    chap: Option<&EpubHtml>,
+    epub2: bool,
+) -> IResult<()> {
+    let mut ele = xml
+        .create_element("itemref")
+        .with_attribute(("idref", idref));
+    if let Some(chap) = chap {
+        if let Some(id) = chap.spine_id() {
+            ele = ele.with_attribute(("id", id));
+        }
+        if !chap.linear() {
+            ele = ele.with_attribute(("linear", "no"));
+        }
+        let properties = chap
+            .spine_properties()
+            .map(|f| f.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if !properties.is_empty() && !epub2 {
+            ele = ele.with_attribute(("properties", properties.as_str()));
+        }
+    }
+    ele.write_empty()?;
+    Ok(())
+}
+
 fn write_metadata(
     book: &EpubBook,
     generator: &str,
@@ -180,9 +400,17 @@ fn write_metadata(
         String::from,
     );
 
-    xml.create_element("meta")
-        .with_attribute(("property", "dcterms:modified"))
-        .write_text_content(BytesText::new(now.as_str()))?;
+    // 严格的 epub2 不支持 property，只保留 opf 2.0 中的元素
+    let epub2 = book.is_strict_epub2();
+    if epub2 {
+        xml.create_element("dc:date")
+            .with_attribute(("opf:event", "modification"))
+            .write_text_content(BytesText::new(now.as_str()))?;
+    } else {
+        xml.create_element("meta")
+            .with_attribute(("property", "dcterms:modified"))
+            .write_text_content(BytesText::new(now.as_str()))?;
+    }
 
     if let Some(v) = book.date() {
         xml.create_element("dc:date")
@@ -196,26 +424,72 @@ fn write_metadata(
         .with_attribute(("content", generator))
         .write_empty()?;
 
-    xml.create_element("dc:identifier")
-        .with_attribute(("id", "id"))
-        .write_text_content(BytesText::new(book.identifier()))?;
-    xml.create_element("dc:title")
-        .write_text_content(BytesText::new(book.title()))?;
-    // xml
-    // .create_element("dc:lang")
-    // .write_text_content(BytesText::new(book.info.title.as_str()));
-    if let Some(creator) = book.creator() {
-        xml.create_element("dc:creator")
-            .with_attribute(("id", "creator"))
-            .write_text_content(BytesText::new(creator))?;
+    // epub2 不支持 refines，只能使用 opf: 属性
+    let epub3 = book.version().starts_with('3');
+
+    if book.identifiers().is_empty() {
+        xml.create_element("dc:identifier")
+            .with_attribute(("id", "id"))
+            .write_text_content(BytesText::new(""))?;
+    }
+    for (index, v) in book.identifiers().iter().enumerate() {
+        let id = gen_meta_id("id", index);
+        let mut ele = xml
+            .create_element("dc:identifier")
+            .with_attribute(("id", id.as_str()));
+        if let (false, Some(scheme)) = (epub3, v.scheme()) {
+            ele = ele.with_attribute(("opf:scheme", scheme));
+        }
+        ele.write_text_content(BytesText::new(v.value()))?;
+        if let (true, Some(scheme)) = (epub3, v.scheme()) {
+            write_refines(xml, &id, "identifier-type", scheme, None)?;
+        }
+    }
+
+    if book.titles().is_empty() {
+        xml.create_element("dc:title")
+            .write_text_content(BytesText::new(""))?;
+    }
+    for (index, v) in book.titles().iter().enumerate() {
+        let refines = epub3
+            && (v.title_type().is_some()
+                || v.file_as().is_some()
+                || !v.alternate_script().is_empty());
+        if !refines {
+            xml.create_element("dc:title")
+                .write_text_content(BytesText::new(v.title()))?;
+            continue;
+        }
+        let id = gen_meta_id("title", index);
+        xml.create_element("dc:title")
+            .with_attribute(("id", id.as_str()))
+            .write_text_content(BytesText::new(v.title()))?;
+        if let Some(t) = v.title_type() {
+            write_refines(xml, &id, "title-type", t.as_str(), None)?;
+        }
+        if let Some(f) = v.file_as() {
+            write_refines(xml, &id, "file-as", f, None)?;
+        }
+        for (lang, value) in v.alternate_script() {
+            write_refines(xml, &id, "alternate-script", value, Some(lang))?;
+        }
+    }
+    if let Some(lang) = book.language() {
+        xml.create_element("dc:language")
+            .write_text_content(BytesText::new(lang))?;
+    }
+    for (index, v) in book.creators().iter().enumerate() {
+        write_person(xml, "dc:creator", &gen_meta_id("creator", index), v, epub3)?;
     }
     if let Some(desc) = book.description() {
         xml.create_element("dc:description")
             .write_text_content(BytesText::new(desc))?;
 
-        xml.create_element("meta")
-            .with_attribute(("property", "desc"))
-            .write_text_content(BytesText::new(desc))?;
+        if !epub2 {
+            xml.create_element("meta")
+                .with_attribute(("property", "desc"))
+                .write_text_content(BytesText::new(desc))?;
+        }
     }
     if book.cover().is_some() {
         xml.create_element("meta")
@@ -234,19 +508,93 @@ fn write_metadata(
             .with_attribute(("id", "publisher"))
             .write_text_content(BytesText::new(v))?;
     }
-    if let Some(v) = book.subject() {
+    for (index, v) in book.subjects().iter().enumerate() {
         xml.create_element("dc:subject")
-            .with_attribute(("id", "subject"))
+            .with_attribute(("id", gen_meta_id("subject", index).as_str()))
             .write_text_content(BytesText::new(v))?;
     }
-    if let Some(v) = book.contributor() {
-        xml.create_element("dc:contributor")
-            .with_attribute(("id", "contributor"))
-            .write_text_content(BytesText::new(v))?;
+    for (index, v) in book.contributors().iter().enumerate() {
+        write_person(
+            xml,
+            "dc:contributor",
+            &gen_meta_id("contributor", index),
+            v,
+            epub3,
+        )?;
+    }
+
+    if let Some(series) = book.series() {
+        // epub3
+        if epub3 {
+            xml.create_element("meta")
+                .with_attribute(("property", "belongs-to-collection"))
+                .with_attribute(("id", "series"))
+                .write_text_content(BytesText::new(series))?;
+            write_refines(xml, "series", "collection-type", "series", None)?;
+            if let Some(index) = book.series_index() {
+                write_refines(xml, "series", "group-position", index, None)?;
+            }
+        }
+        // calibre
+        xml.create_element("meta")
+            .with_attribute(("name", "calibre:series"))
+            .with_attribute(("content", series))
+            .write_empty()?;
+        if let Some(index) = book.series_index() {
+            xml.create_element("meta")
+                .with_attribute(("name", "calibre:series_index"))
+                .with_attribute(("content", index))
+                .write_empty()?;
+        }
+    }
+
+    // 固定布局
+    for (property, value) in [
+        ("rendition:layout", book.layout().map(|f| f.as_str())),
+        (
+            "rendition:orientation",
+            book.orientation().map(|f| f.as_str()),
+        ),
+        ("rendition:spread", book.spread().map(|f| f.as_str())),
+    ] {
+        if let Some(value) = value.filter(|_| !epub2) {
+            xml.create_element("meta")
+                .with_attribute(("property", property))
+                .write_text_content(BytesText::new(value))?;
+        }
+    }
+
+    // 媒体覆盖
+    let mut total = None;
+    for (index, ele) in book.chapters().enumerate().filter(|_| !epub2) {
+        if let Some(overlay) = ele.media_overlay() {
+            let duration = overlay.duration();
+            xml.create_element("meta")
+                .with_attribute(("property", "media:duration"))
+                .with_attribute(("refines", format!("#smil_{index}").as_str()))
+                .write_text_content(BytesText::new(
+                    super::overlay::format_clock(duration).as_str(),
+                ))?;
+            total = Some(total.unwrap_or_default() + duration);
+        }
+    }
+    if let Some(total) = total {
+        xml.create_element("meta")
+            .with_attribute(("property", "media:duration"))
+            .write_text_content(BytesText::new(super::overlay::format_clock(total).as_str()))?;
+    }
+    if let Some(class) = book.media_active_class().filter(|_| !epub2) {
+        xml.create_element("meta")
+            .with_attribute(("property", "media:active-class"))
+            .write_text_content(BytesText::new(class))?;
     }
 
     // 自定义的meta
-    for ele in book.meta() {
+    for ele in book
+        .meta()
+        .iter()
+        .filter(|f| !epub2 || (f.get_attr("property").is_none() && f.get_attr("refines").is_none()))
+    {
         let mut x = xml.create_element("meta");
         for (key, value) in ele.attrs() {
             x = x.with_attribute((key.as_str(), value.as_str()));
@@ -263,6 +611,35 @@ fn write_metadata(
     Ok(())
 }
 
+///
+/// 章节在 manifest 中的 properties，只在 epub3 中写入
+///
+/// 没有读取内容的章节使用读取 opf 或者释放内容前记录的结果，
+/// 已读取内容的章节重新判断 svg、scripted
+///
+pub(crate) fn chapter_properties(ele: &EpubHtml) -> Vec<String> {
+    let loaded = ele.data().is_some();
+    let mut properties: Vec<String> = ele
+        .manifest_properties
+        .iter()
+        .flatten()
+        .filter(|f| !loaded || (f.as_str() != "svg" && f.as_str() != "scripted"))
+        .cloned()
+        .collect();
+    // 内嵌 svg 的页面，例如固定布局的图片页，epub3 要求声明 svg 属性
+    if ele.data().is_some_and(contains_svg) {
+        properties.push("svg".to_string());
+    }
+    // 包含脚本的页面需要声明 scripted 属性
+    if (ele.data().is_some_and(contains_script)
+        || ele.head().is_some_and(|f| contains_script(f.as_bytes())))
+        && !properties.iter().any(|f| f == "scripted")
+    {
+        properties.push("scripted".to_string());
+    }
+    properties
+}
+
 pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String> {
     let vue: Vec<u8> = Vec::new();
     let mut xml: quick_xml::Writer<std::io::Cursor<Vec<u8>>> =
@@ -275,7 +652,10 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
     html.push_attribute(("xmlns", "http://www.idpf.org/2007/opf"));
     html.push_attribute(("unique-identifier", "id"));
     html.push_attribute(("version", book.version()));
-    html.push_attribute(("prefix", "rendition: http://www.idpf.org/vocab/rendition/#"));
+    let epub2 = book.is_strict_epub2();
+    if !epub2 {
+        html.push_attribute(("prefix", "rendition: http://www.idpf.org/vocab/rendition/#"));
+    }
 
     xml.write_event(Event::Start(html.borrow()))?;
 
@@ -295,25 +675,33 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
         .with_attribute(("media-type", "application/x-dtbncx+xml"))
         .write_empty()?;
     // nav
-    xml.create_element("item")
-        .with_attribute(("href", common::NAV.replace(common::EPUB, "").as_str()))
-        .with_attribute(("id", "toc"))
-        .with_attribute(("media-type", "application/xhtml+xml"))
-        .with_attribute(("properties", "nav"))
-        .write_empty()?;
-    if let Some(cover) = book.cover() {
-        xml.create_element("item")
-            .with_attribute(("href", cover.file_name()))
-            .with_attribute(("id", "cover-img"))
-            .with_attribute(("media-type", get_media_type(cover.file_name()).as_str()))
-            .with_attribute(("properties", "cover-image"))
-            .write_empty()?;
+    if !epub2 {
         xml.create_element("item")
-            .with_attribute(("href", common::COVER.replace(common::EPUB, "").as_str()))
-            .with_attribute(("id", "cover"))
+            .with_attribute(("href", common::NAV.replace(common::EPUB, "").as_str()))
+            .with_attribute(("id", "toc"))
             .with_attribute(("media-type", "application/xhtml+xml"))
+            .with_attribute(("properties", "nav"))
             .write_empty()?;
     }
+    if let Some(cover) = book.cover() {
+        let mut item = xml
+            .create_element("item")
+            .with_attribute(("href", cover.file_name()))
+            .with_attribute(("id", "cover-img"))
+            .with_attribute(("media-type", get_media_type(cover.file_name()).as_str()));
+        if !epub2 {
+            item = item.with_attribute(("properties", "cover-image"));
+        }
+        item.write_empty()?;
+        // 有封面页时在后面写入，避免 id 重复
+        if book.cover_chapter().is_none() {
+            xml.create_element("item")
+                .with_attribute(("href", common::COVER.replace(common::EPUB, "").as_str()))
+                .with_attribute(("id", "cover"))
+                .with_attribute(("media-type", "application/xhtml+xml"))
+                .write_empty()?;
+        }
+    }
     for (index, ele) in book.assets().enumerate() {
         xml.create_element("item")
             .with_attribute((
@@ -329,8 +717,10 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
             .write_empty()?;
     }
 
+    let epub3 = book.version().starts_with('3');
     for (index, ele) in book.chapters().enumerate() {
-        xml.create_element("item")
+        let mut item = xml
+            .create_element("item")
             .with_attribute((
                 "href",
                 if ele.file_name().starts_with("/") {
@@ -340,8 +730,35 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
                 },
             ))
             .with_attribute(("id", format!("chap_{}", index).as_str()))
-            .with_attribute(("media-type", "application/xhtml+xml"))
-            .write_empty()?;
+            .with_attribute(("media-type", "application/xhtml+xml"));
+        let properties = if epub3 {
+            chapter_properties(ele)
+        } else {
+            Vec::new()
+        };
+        if !properties.is_empty() {
+            item = item.with_attribute(("properties", properties.join(" ").as_str()));
+        }
+        if ele.media_overlay().is_some() && !epub2 {
+            item = item.with_attribute(("media-overlay", format!("smil_{index}").as_str()));
+        }
+        item.write_empty()?;
+    }
+
+    // 媒体覆盖
+    for (index, ele) in book.chapters().enumerate().filter(|_| !epub2) {
+        if let Some(overlay) = ele.media_overlay() {
+            xml.create_element("item")
+                .with_attribute((
+                    "href",
+                    overlay
+                        .resolve_file_name(ele.file_name())
+                        .trim_start_matches('/'),
+                ))
+                .with_attribute(("id", format!("smil_{index}").as_str()))
+                .with_attribute(("media-type", "application/smil+xml"))
+                .write_empty()?;
+        }
     }
 
     if let Some(cover) = book.cover_chapter() {
@@ -363,35 +780,39 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
 
     let mut spine = BytesStart::new("spine");
     spine.push_attribute(("toc", "ncx"));
-    if let Some(dir) = &book.direction {
+    if let Some(dir) = book.direction.as_ref().filter(|_| !epub2) {
         spine.push_attribute(("page-progression-direction", format!("{dir}").as_str()));
     }
     xml.write_event(Event::Start(spine.borrow()))?;
     // 把封面放第一个 nav，导航第二个
     if let Some(co) = book.cover_chapter() {
-        xml.create_element("itemref")
-            .with_attribute(("idref", "cover"))
-            .write_empty()?;
+        write_itemref(&mut xml, "cover", Some(co), epub2)?;
+    }
+    if !epub2 {
+        write_itemref(&mut xml, "toc", None, epub2)?;
     }
-    xml.create_element("itemref")
-        .with_attribute(("idref", "toc"))
-        .write_empty()?;
     // spine 内的 itemref
-    for (index, _ele) in book.chapters().enumerate() {
-        xml.create_element("itemref")
-            .with_attribute(("idref", format!("chap_{}", index).as_str()))
-            .write_empty()?;
+    for (index, ele) in book.chapters().enumerate() {
+        write_itemref(
+            &mut xml,
+            format!("chap_{}", index).as_str(),
+            Some(ele),
+            epub2,
+        )?;
     }
     xml.write_event(Event::End(spine.to_end()))?;
 
-    if let Some(c) = book.cover_chapter() {
+    let landmarks = get_landmarks(book);
+    if !landmarks.is_empty() {
         let guide = BytesStart::new("guide");
         xml.write_event(Event::Start(guide.borrow()))?;
-        xml.create_element("reference")
-            .with_attribute(("href", c.file_name()))
-            .with_attribute(("title", c.title()))
-            .with_attribute(("type", "cover"))
-            .write_empty()?;
+        for ele in &landmarks {
+            xml.create_element("reference")
+                .with_attribute(("href", ele.href()))
+                .with_attribute(("title", ele.title()))
+                .with_attribute(("type", ele.guide_type()))
+                .write_empty()?;
+        }
         xml.write_event(Event::End(guide.to_end()))?;
     }
 
@@ -403,6 +824,14 @@ pub(crate) fn do_to_opf(book: &mut EpubBook, generator: &str) -> IResult<String>
     }
 }
 
+fn contains_svg(data: &[u8]) -> bool {
+    data.windows(4).any(|f| f == b"<svg")
+}
+
+fn contains_script(data: &[u8]) -> bool {
+    data.windows(7).any(|f| f == b"<script")
+}
+
 /// 生成OPF
 pub(crate) fn to_opf(book: &mut EpubBook, generator: &str) -> String {
     do_to_opf(book, generator).unwrap_or_default()
@@ -416,8 +845,39 @@ pub(crate) struct HtmlInfo {
     pub(crate) link: Vec<EpubLink>,
     pub(crate) style: Option<String>,
     pub(crate) body_attribute: Option<Vec<u8>>,
+    pub(crate) html_attribute: Option<Vec<u8>>,
+    pub(crate) head: Option<String>,
 }
 
+///
+/// 移除 epub: 命名空间下的属性，严格的 epub2 不声明该命名空间
+///
+/// [attributes] html 或者 body 标签中的属性
+///
+pub(crate) fn remove_epub_attributes(attributes: &[u8]) -> Vec<u8> {
+    let tag = quick_xml::events::BytesStart::from_content(String::from_utf8_lossy(attributes), 0);
+    let mut out = Vec::new();
+    for attr in tag.attributes().with_checks(false).flatten() {
+        let key = attr.key.as_ref();
+        if key.starts_with(b"epub:") || key == b"xmlns:epub" {
+            continue;
+        }
+        let value = attr
+            .unescape_value()
+            .map_or_else(|_| String::new(), |v| v.to_string());
+        out.push(b' ');
+        out.extend_from_slice(key);
+        out.extend_from_slice(format!("=\"{}\"", escape_xml(value)).as_bytes());
+    }
+    out
+}
+
+/// to_html 默认生成的 epub:prefix
+const DEFAULT_EPUB_PREFIX: &str = "z3998: http://www.daisy.org/z3998/2012/vocab/structure/#";
+
+/// to_html 会重新生成的 html 标签属性，读取时不再保留
+const HTML_GEN_ATTRIBUTES: [&[u8]; 5] = [b"xmlns", b"xmlns:epub", b"lang", b"xml:lang", b"dir"];
+
 ///
 /// 解析html获取相关数据
 ///
@@ -437,6 +897,8 @@ pub(crate) fn get_html_info(html: &str, id: Option<&str>) -> IResult<HtmlInfo> {
     let mut parent: Vec<&str> = Vec::new();
     let mut body_data: Option<Vec<u8>> = None;
     let mut body_attribute: Option<Vec<u8>> = None;
+    let mut html_attribute = Vec::new();
+    let mut head: Vec<String> = Vec::new();
     let mut style = String::new();
     loop {
         match reader.read_event_into(&mut buf) {
@@ -474,6 +936,22 @@ pub(crate) fn get_html_info(html: &str, id: Option<&str>) -> IResult<HtmlInfo> {
                             direction = Some(Direction::from(h))
                         }
                     }
+                    // 其他属性，例如 class、epub:type
+                    for attr in body.attributes().flatten() {
+                        if HTML_GEN_ATTRIBUTES.contains(&attr.key.as_ref()) {
+                            continue;
+                        }
+                        let value = attr
+                            .unescape_value()
+                            .map_or_else(|_| String::new(), |v| v.to_string());
+                        if attr.key.as_ref() == b"epub:prefix" && value == DEFAULT_EPUB_PREFIX {
+                            continue;
+                        }
+                        html_attribute.push(b' ');
+                        html_attribute.extend_from_slice(attr.key.as_ref());
+                        html_attribute
+                            .extend_from_slice(format!("=\"{}\"", escape_xml(value)).as_bytes());
+                    }
                 }
                 b"head" => {
                     if parent.len() != 1 || parent[0] != "html" {
@@ -535,7 +1013,23 @@ pub(crate) fn get_html_info(html: &str, id: Option<&str>) -> IResult<HtmlInfo> {
                         break;
                     }
                 }
-                _ => {}
+                _ => {
+                    if parent.last().map(|f| f == &"head").unwrap_or(false) {
+                        // 其他 head 内容，例如 meta、script，原样保留
+                        let name = String::from_utf8_lossy(body.name().as_ref()).to_string();
+                        let attr = String::from_utf8_lossy(body.attributes_raw())
+                            .trim_end()
+                            .to_string();
+                        let inner = reader
+                            .read_text(body.to_end().to_owned().name())
+                            .map_err(IError::Xml)?;
+                        if inner.is_empty() && name != "script" {
+                            head.push(format!("<{name}{attr}/>"));
+                        } else {
+                            head.push(format!("<{name}{attr}>{inner}</{name}>"));
+                        }
+                    }
+                }
             },
             Ok(Event::End(e)) => match e.name().as_ref() {
                 b"title" => {
@@ -616,9 +1110,78 @@ pub(crate) fn get_html_info(html: &str, id: Option<&str>) -> IResult<HtmlInfo> {
             Some(style.trim().to_string())
         },
         body_attribute,
+        html_attribute: if html_attribute.is_empty() {
+            None
+        } else {
+            Some(html_attribute)
+        },
+        head: if head.is_empty() {
+            None
+        } else {
+            Some(head.join("\n"))
+        },
     })
 }
 
+///
+/// 宽松解析html，用于格式错误无法按照xml解析的文件
+///
+/// 只按照字符串查找 title 和 body
+///
+pub(crate) fn get_html_info_lenient(html: &str) -> HtmlInfo {
+    let lower = html.to_ascii_lowercase();
+    let find_tag = |tag: &str, from: usize| {
+        lower[from..]
+            .match_indices(tag)
+            .map(|(i, _)| i + from)
+            // 排除类似 <bodyx> 的标签
+            .find(|i| {
+                lower[i + tag.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+            })
+    };
+    let title = find_tag("<title", 0)
+        .and_then(|start| lower[start..].find('>').map(|f| start + f + 1))
+        .and_then(|start| {
+            lower[start..]
+                .find("</title")
+                .map(|end| &html[start..start + end])
+        })
+        .map(|f| f.trim().to_string())
+        .unwrap_or_default();
+
+    let mut body_attribute = None;
+    let content = match find_tag("<body", 0) {
+        Some(start) => {
+            let open_end = lower[start..].find('>').map_or(html.len(), |f| start + f);
+            let attr = html[start + "<body".len()..open_end].trim_end_matches('/');
+            if !attr.trim().is_empty() {
+                body_attribute = Some(attr.as_bytes().to_vec());
+            }
+            let content_start = (open_end + 1).min(html.len());
+            let content_end = lower
+                .rfind("</body")
+                .filter(|f| *f >= content_start)
+                .unwrap_or(html.len());
+            &html[content_start..content_end]
+        }
+        None => html,
+    };
+    HtmlInfo {
+        title,
+        content: content.as_bytes().to_vec(),
+        language: None,
+        direction: None,
+        link: Vec::new(),
+        style: None,
+        body_attribute,
+        html_attribute: None,
+        head: None,
+    }
+}
+
 /// epub3 将所有正文放到一个文件里，不同的section代表不同的章节
 fn get_section_from_html(body: &str, id: &str) -> IResult<Vec<u8>> {
     use quick_xml::reader::Reader;
@@ -705,7 +1268,7 @@ mod test {
         };
 
         t.add_link(link);
-        let html = to_html(&mut t, true, &None);
+        let html = to_html(&mut t, true, &None, false);
 
         println!("{}", html);
 
@@ -728,7 +1291,7 @@ ok
 
         // test dir
         t.set_direction(Direction::RTL);
-        let html = to_html(&mut t, true, &None);
+        let html = to_html(&mut t, true, &None, false);
 
         println!("{}", html);
 
@@ -751,7 +1314,7 @@ ok
 
         // 测试优先级
         t.set_direction(Direction::LTR);
-        let html = to_html(&mut t, true, &Some(Direction::RTL));
+        let html = to_html(&mut t, true, &Some(Direction::RTL), false);
 
         assert_eq!(
             html,
@@ -776,7 +1339,7 @@ ok
             file_type: "()".to_string(),
             href: "1.css".to_string(),
         });
-        let html = to_html(&mut t, true, &Some(Direction::RTL));
+        let html = to_html(&mut t, true, &Some(Direction::RTL), false);
 
         assert_eq!(
             html,
@@ -795,7 +1358,7 @@ ok
 </html>"###
         );
         t.body_attribute = Some(" class=\"ok\"".as_bytes().to_vec());
-        let html = to_html(&mut t, true, &Some(Direction::RTL));
+        let html = to_html(&mut t, true, &Some(Direction::RTL), false);
 
         assert_eq!(
             html,
@@ -829,7 +1392,7 @@ ok
         };
 
         t.add_link(link);
-        let html = to_html(&mut t, true, &None);
+        let html = to_html(&mut t, true, &None, false);
 
         println!("{}", html);
 
@@ -871,7 +1434,7 @@ ok
 
         let nav = vec![n, n1];
 
-        let html = to_nav_html("book_title", nav.iter(), "zh", &None);
+        let html = to_nav_html("book_title", nav.iter(), &[], &[], "zh", &None);
 
         println!("{}", html);
 
@@ -880,7 +1443,14 @@ ok
             html
         );
 
-        let html = to_nav_html("book_title", nav.iter(), "en", &Some(Direction::RTL));
+        let html = to_nav_html(
+            "book_title",
+            nav.iter(),
+            &[],
+            &[],
+            "en",
+            &Some(Direction::RTL),
+        );
 
         println!("{}", html);
 
@@ -913,6 +1483,8 @@ ok
         let html = to_nav_html(
             "Test Story Title `~!@#$%^&*()_+ and []\\{}| and ;':\" and ,./<>?",
             nav.iter(),
+            &[],
+            &[],
             "zh",
             &None,
         );
@@ -945,7 +1517,7 @@ ok
 
         let nav = vec![n, n1];
 
-        let html = to_toc_xml("book_title", nav.iter());
+        let html = to_toc_xml("book_title", nav.iter(), &[]);
 
         println!("{}", html);
 
@@ -978,6 +1550,7 @@ ok
         let html = to_toc_xml(
             "Test Story Title `~!@#$%^&*()_+ and []\\{}| and ;':\" and ,./<>?",
             nav.iter(),
+            &[],
         );
 
         println!("{}", html);
@@ -1059,7 +1632,7 @@ ok
         );
         let res = to_opf(&mut epub, "epub-rs");
 
-        let ass: &str = r###"<?xml version="1.0" encoding="utf-8"?><package xmlns="http://www.idpf.org/2007/opf" unique-identifier="id" version="3.0" prefix="rendition: http://www.idpf.org/vocab/rendition/#"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf"><meta property="dcterms:modified">2024-06-28T03:07:07UTC</meta><dc:date id="date" opf:event="publication">2024-06-28T08:07:07UTC</dc:date><meta name="generator" content="epub-rs"/><dc:identifier id="id">identifier</dc:identifier><dc:title>中文</dc:title><dc:creator id="creator">作者</dc:creator><dc:description>description</dc:description><meta property="desc">description</meta><meta name="cover" content="cover-img"/><dc:format id="format">format</dc:format><dc:publisher id="publisher">publisher</dc:publisher><dc:subject id="subject">subject</dc:subject><dc:contributor id="contributor">contributor</dc:contributor><meta ok="ov">new</meta></metadata><manifest><item href="toc.ncx" id="ncx" media-type="application/x-dtbncx+xml"/><item href="nav.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/><item href="" id="cover-img" media-type="" properties="cover-image"/><item href="cover.xhtml" id="cover" media-type="application/xhtml+xml"/><item href="1.png" id="assets_0" media-type="image/png"/><item href="2.png" id="assets_1" media-type="image/png"/><item href="" id="chap_0" media-type="application/xhtml+xml"/><item href="1.xhtml" id="cover" media-type="application/xhtml+xml"/></manifest><spine toc="ncx" page-progression-direction="rtl"><itemref idref="cover"/><itemref idref="toc"/><itemref idref="chap_0"/></spine><guide><reference href="1.xhtml" title="封面" type="cover"/></guide></package>"###;
+        let ass: &str = r###"<?xml version="1.0" encoding="utf-8"?><package xmlns="http://www.idpf.org/2007/opf" unique-identifier="id" version="3.0" prefix="rendition: http://www.idpf.org/vocab/rendition/#"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf"><meta property="dcterms:modified">2024-06-28T03:07:07UTC</meta><dc:date id="date" opf:event="publication">2024-06-28T08:07:07UTC</dc:date><meta name="generator" content="epub-rs"/><dc:identifier id="id">identifier</dc:identifier><dc:title>中文</dc:title><dc:creator id="creator">作者</dc:creator><dc:description>description</dc:description><meta property="desc">description</meta><meta name="cover" content="cover-img"/><dc:format id="format">format</dc:format><dc:publisher id="publisher">publisher</dc:publisher><dc:subject id="subject">subject</dc:subject><dc:contributor id="contributor">contributor</dc:contributor><meta ok="ov">new</meta></metadata><manifest><item href="toc.ncx" id="ncx" media-type="application/x-dtbncx+xml"/><item href="nav.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/><item href="" id="cover-img" media-type="" properties="cover-image"/><item href="1.png" id="assets_0" media-type="image/png"/><item href="2.png" id="assets_1" media-type="image/png"/><item href="" id="chap_0" media-type="application/xhtml+xml"/><item href="1.xhtml" id="cover" media-type="application/xhtml+xml"/></manifest><spine toc="ncx" page-progression-direction="rtl"><itemref idref="cover"/><itemref idref="toc"/><itemref idref="chap_0"/></spine><guide><reference href="1.xhtml" title="封面" type="cover"/></guide></package>"###;
         assert_eq!(ass, res.as_str());
     }
 
@@ -1264,4 +1837,79 @@ ok
         assert!(info.body_attribute.is_some());
         assert_eq!(b" class=\"b\"", info.body_attribute.unwrap().as_slice());
     }
+
+    #[test]
+    fn test_keep_head() {
+        let info = get_html_info(
+            r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" class="vrtl" epub:type="bodymatter" lang="ja">
+<head>
+<title>t</title>
+<meta name="viewport" content="width=1200, height=1600" />
+<style>p{margin:0}</style>
+<script type="text/javascript" src="a.js"></script>
+</head>
+<body epub:type="chapter"><p>正文</p></body>
+</html>"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            b" class=\"vrtl\" epub:type=\"bodymatter\"",
+            info.html_attribute.as_deref().unwrap()
+        );
+        assert_eq!(
+            r#"<meta name="viewport" content="width=1200, height=1600"/>
+<script type="text/javascript" src="a.js"></script>"#,
+            info.head.as_deref().unwrap()
+        );
+
+        let mut t = EpubHtml::default()
+            .with_title("t")
+            .with_language("ja")
+            .with_data(info.content)
+            .with_head(info.head.unwrap());
+        t.html_attribute = info.html_attribute;
+        t.body_attribute = info.body_attribute;
+        let html = to_html(&mut t, false, &None, false);
+        assert!(html.contains(r#"lang="ja" xml:lang="ja" class="vrtl" epub:type="bodymatter">"#));
+        assert!(html.contains(r#"<meta name="viewport" content="width=1200, height=1600"/>"#));
+        assert!(html.contains(r#"<script type="text/javascript" src="a.js"></script>"#));
+        assert!(html.contains(r#"<body epub:type="chapter">"#));
+
+        // 再次解析结果不变
+        let again = get_html_info(&html, None).unwrap();
+        assert_eq!(t.head(), again.head.as_deref());
+        assert_eq!(t.html_attribute, again.html_attribute);
+
+        // 严格的 epub2 移除 epub: 属性
+        let html = to_html(&mut t, false, &None, true);
+        assert!(!html.contains("epub:"));
+        assert!(html.contains(r#"xml:lang="ja" class="vrtl">"#));
+        assert!(html.contains("<body>"));
+    }
+
+    #[test]
+    fn test_keep_epub_prefix() {
+        let info = get_html_info(
+            r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" epub:prefix="rendition: http://www.idpf.org/vocab/rendition/#" lang="zh">
+<head><title>t</title></head>
+<body><p>正文</p></body>
+</html>"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            br#" epub:prefix="rendition: http://www.idpf.org/vocab/rendition/#""#,
+            info.html_attribute.as_deref().unwrap()
+        );
+
+        let mut t = EpubHtml::default().with_title("t").with_data(info.content);
+        t.html_attribute = info.html_attribute;
+        let html = to_html(&mut t, false, &None, false);
+        assert_eq!(1, html.matches("epub:prefix=").count());
+        assert!(html.contains(r#"epub:prefix="rendition: http://www.idpf.org/vocab/rendition/#""#));
+
+        let html = to_html(&mut t, false, &None, true);
+        assert!(!html.contains("epub:prefix"));
+    }
 }
@@ -1,7 +1,13 @@
 pub(crate) mod appender;
 pub(crate) mod builder;
+pub(crate) mod cfi;
 pub(crate) mod common;
+pub(crate) mod convert;
 pub(crate) mod core;
+pub(crate) mod diff;
+pub(crate) mod encryption;
 mod html;
+pub(crate) mod overlay;
 pub(crate) mod reader;
+pub(crate) mod validator;
 pub(crate) mod writer;
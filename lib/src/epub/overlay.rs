@@ -0,0 +1,396 @@
+//!
+//! epub3 媒体覆盖（Media Overlays）
+//!
+//! 通过 smil 文件把章节中的元素和音频片段关联起来，用于有声绘本等跟读场景
+//!
+use std::time::Duration;
+
+use quick_xml::events::{BytesDecl, BytesStart, Event};
+
+use crate::cache_struct;
+use crate::common::{IError, IResult};
+
+cache_struct! {
+///
+/// 一段音频，对应 smil 中的 par
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubClip {
+    /// 章节中元素的 id，不带 #
+    id: String,
+    /// 音频文件，相对于opf文件所在目录
+    audio: String,
+    /// 开始时间，对应 clipBegin
+    begin: Duration,
+    /// 结束时间，对应 clipEnd，为空时播放到文件末尾
+    end: Option<Duration>,
+}
+}
+
+impl EpubClip {
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use iepub::prelude::*;
+    /// use std::time::Duration;
+    /// EpubClip::new("p1", "audio/1.mp3", Duration::ZERO, Duration::from_millis(2500));
+    /// // id 和音频路径可以是不同的类型
+    /// EpubClip::new(format!("p{}", 2), "audio/1.mp3", Duration::ZERO, Duration::from_secs(3));
+    /// ```
+    ///
+    pub fn new<T: Into<String>, A: Into<String>>(
+        id: T,
+        audio: A,
+        begin: Duration,
+        end: Duration,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            audio: audio.into(),
+            begin,
+            end: Some(end),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn audio(&self) -> &str {
+        &self.audio
+    }
+
+    pub fn begin(&self) -> Duration {
+        self.begin
+    }
+
+    pub fn end(&self) -> Option<Duration> {
+        self.end
+    }
+
+    ///
+    /// 片段时长，没有结束时间时无法计算
+    ///
+    pub fn duration(&self) -> Option<Duration> {
+        self.end.map(|f| f.saturating_sub(self.begin))
+    }
+}
+
+cache_struct! {
+///
+/// 章节的媒体覆盖，对应一个 smil 文件
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EpubMediaOverlay {
+    /// smil 文件路径，相对于opf文件所在目录，为空时根据章节文件名生成
+    file_name: String,
+    /// 总时长，对应 media:duration，为空时根据片段计算
+    duration: Option<Duration>,
+    /// 按播放顺序排列的片段
+    clips: Vec<EpubClip>,
+}
+}
+
+impl EpubMediaOverlay {
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn set_file_name<T: Into<String>>(&mut self, file_name: T) {
+        self.file_name = file_name.into();
+    }
+
+    pub fn with_file_name<T: Into<String>>(mut self, file_name: T) -> Self {
+        self.set_file_name(file_name);
+        self
+    }
+
+    ///
+    /// 总时长，没有设置时为所有片段时长之和
+    ///
+    pub fn duration(&self) -> Duration {
+        self.duration.unwrap_or_else(|| {
+            self.clips
+                .iter()
+                .filter_map(|f| f.duration())
+                .sum::<Duration>()
+        })
+    }
+
+    pub fn set_duration(&mut self, duration: Option<Duration>) {
+        self.duration = duration;
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.set_duration(Some(duration));
+        self
+    }
+
+    pub fn clips(&self) -> &[EpubClip] {
+        &self.clips
+    }
+
+    pub fn add_clip(&mut self, clip: EpubClip) {
+        self.clips.push(clip);
+    }
+
+    pub fn with_clip(mut self, clip: EpubClip) -> Self {
+        self.add_clip(clip);
+        self
+    }
+
+    ///
+    /// smil 文件路径，没有设置时和章节同目录同名
+    ///
+    pub(crate) fn resolve_file_name(&self, chapter: &str) -> String {
+        if !self.file_name.is_empty() {
+            return self.file_name.clone();
+        }
+        let chapter = chapter.split('#').next().unwrap_or_default();
+        let name_start = chapter.rfind('/').map_or(0, |f| f + 1);
+        match chapter[name_start..].rfind('.') {
+            Some(i) => format!("{}.smil", &chapter[..name_start + i]),
+            None => format!("{chapter}.smil"),
+        }
+    }
+}
+
+///
+/// 解析时间，支持 01:02:03.5、02:03.5、3.5s、500ms、1.5min、1h 以及不带单位的秒数
+///
+pub(crate) fn parse_clock(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.contains(':') {
+        let mut seconds = 0f64;
+        for ele in value.split(':') {
+            seconds = seconds * 60.0 + ele.trim().parse::<f64>().ok()?;
+        }
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => (&value[..i], &value[i..]),
+        None => (value, "s"),
+    };
+    let number = number.trim().parse::<f64>().ok()?;
+    let seconds = match unit {
+        "h" => number * 3600.0,
+        "min" => number * 60.0,
+        "s" => number,
+        "ms" => number / 1000.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+///
+/// 格式化时间，例如 0:01:02.500
+///
+pub(crate) fn format_clock(value: Duration) -> String {
+    let millis = value.as_millis();
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+///
+/// 从 dir 目录出发指向 target 的路径，两者都相对于opf文件所在目录
+///
+fn relative(dir: &str, target: &str) -> String {
+    let dir: Vec<&str> = dir.split('/').filter(|f| !f.is_empty()).collect();
+    let target: Vec<&str> = target.split('/').collect();
+    let common = dir
+        .iter()
+        .zip(&target[..target.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut out = vec![".."; dir.len() - common];
+    out.extend_from_slice(&target[common..]);
+    out.join("/")
+}
+
+fn parent(file_name: &str) -> &str {
+    &file_name[..file_name.rfind('/').unwrap_or(0)]
+}
+
+///
+/// 生成 smil 文件
+///
+/// [chapter] 章节文件路径
+///
+pub(crate) fn to_smil(overlay: &EpubMediaOverlay, chapter: &str) -> IResult<String> {
+    let smil = overlay.resolve_file_name(chapter);
+    let dir = parent(smil.as_str());
+    let text = relative(dir, chapter);
+
+    let mut xml = quick_xml::Writer::new(std::io::Cursor::new(Vec::new()));
+    xml.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+    let mut root = BytesStart::new("smil");
+    root.push_attribute(("xmlns", "http://www.w3.org/ns/SMIL"));
+    root.push_attribute(("xmlns:epub", "http://www.idpf.org/2007/ops"));
+    root.push_attribute(("version", "3.0"));
+    xml.write_event(Event::Start(root.borrow()))?;
+    let body = BytesStart::new("body");
+    xml.write_event(Event::Start(body.borrow()))?;
+    let mut seq = BytesStart::new("seq");
+    seq.push_attribute(("id", "seq1"));
+    seq.push_attribute(("epub:textref", text.as_str()));
+    xml.write_event(Event::Start(seq.borrow()))?;
+
+    for (index, clip) in overlay.clips().iter().enumerate() {
+        let mut par = BytesStart::new("par");
+        par.push_attribute(("id", format!("par{}", index + 1).as_str()));
+        xml.write_event(Event::Start(par.borrow()))?;
+        xml.create_element("text")
+            .with_attribute(("src", format!("{text}#{}", clip.id()).as_str()))
+            .write_empty()?;
+        let begin = format_clock(clip.begin());
+        let mut audio = xml
+            .create_element("audio")
+            .with_attribute(("src", relative(dir, clip.audio()).as_str()))
+            .with_attribute(("clipBegin", begin.as_str()));
+        if let Some(end) = clip.end() {
+            audio = audio.with_attribute(("clipEnd", format_clock(end).as_str()));
+        }
+        audio.write_empty()?;
+        xml.write_event(Event::End(par.to_end()))?;
+    }
+
+    xml.write_event(Event::End(seq.to_end()))?;
+    xml.write_event(Event::End(body.to_end()))?;
+    xml.write_event(Event::End(root.to_end()))?;
+    String::from_utf8(xml.into_inner().into_inner()).map_err(IError::Utf8)
+}
+
+///
+/// 解析 smil 文件，嵌套的 seq 会按顺序展开
+///
+/// [file_name] smil 文件路径，相对于opf文件所在目录
+///
+pub(crate) fn read_smil(xml: &str, file_name: &str) -> IResult<EpubMediaOverlay> {
+    let dir = crate::path::Path::system(parent(file_name));
+    let resolve = |src: &str| {
+        if parent(file_name).is_empty() {
+            src.to_string()
+        } else {
+            dir.join(src).to_str()
+        }
+    };
+    let mut overlay = EpubMediaOverlay::default().with_file_name(file_name);
+
+    let mut reader = quick_xml::reader::Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut clip: Option<EpubClip> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Eof => break,
+            Event::Start(e) | Event::Empty(e) => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                match e.local_name().as_ref() {
+                    b"par" => clip = Some(EpubClip::default()),
+                    b"text" => {
+                        if let (Some(clip), Some(src)) = (clip.as_mut(), attr("src")) {
+                            clip.id = src.split_once('#').map_or("", |f| f.1).to_string();
+                        }
+                    }
+                    b"audio" => {
+                        if let (Some(clip), Some(src)) = (clip.as_mut(), attr("src")) {
+                            clip.audio = resolve(src.as_str());
+                            clip.begin = attr("clipBegin")
+                                .and_then(|f| parse_clock(f.as_str()))
+                                .unwrap_or_default();
+                            clip.end = attr("clipEnd").and_then(|f| parse_clock(f.as_str()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"par" => {
+                if let Some(clip) = clip.take() {
+                    overlay.add_clip(clip);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(overlay)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(
+            Some(Duration::from_millis(3_723_500)),
+            parse_clock("1:02:03.5")
+        );
+        assert_eq!(Some(Duration::from_millis(123_500)), parse_clock("02:03.5"));
+        assert_eq!(Some(Duration::from_millis(3500)), parse_clock("3.5s"));
+        assert_eq!(Some(Duration::from_millis(500)), parse_clock("500ms"));
+        assert_eq!(Some(Duration::from_secs(90)), parse_clock("1.5min"));
+        assert_eq!(Some(Duration::from_secs(3600)), parse_clock("1h"));
+        assert_eq!(Some(Duration::from_secs(12)), parse_clock("12"));
+        assert_eq!(None, parse_clock("abc"));
+        assert_eq!(
+            "1:02:03.500",
+            format_clock(Duration::from_millis(3_723_500))
+        );
+        assert_eq!("0:00:00.000", format_clock(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_smil() {
+        let overlay = EpubMediaOverlay::default()
+            .with_clip(EpubClip::new(
+                "p1",
+                "audio/1.mp3",
+                Duration::ZERO,
+                Duration::from_millis(2500),
+            ))
+            .with_clip(EpubClip::new(
+                "p2",
+                "audio/1.mp3",
+                Duration::from_millis(2500),
+                Duration::from_secs(4),
+            ));
+        assert_eq!(Duration::from_secs(4), overlay.duration());
+        assert_eq!("text/1.smil", overlay.resolve_file_name("text/1.xhtml"));
+
+        let smil = to_smil(&overlay, "text/1.xhtml").unwrap();
+        assert!(smil.contains(r#"<text src="1.xhtml#p1"/>"#));
+        assert!(smil.contains(
+            r#"<audio src="../audio/1.mp3" clipBegin="0:00:02.500" clipEnd="0:00:04.000"/>"#
+        ));
+
+        let read = read_smil(smil.as_str(), "text/1.smil").unwrap();
+        assert_eq!(overlay.clips(), read.clips());
+        assert_eq!("text/1.smil", read.file_name());
+
+        // 嵌套的 seq
+        let read = read_smil(
+            r#"<smil xmlns="http://www.w3.org/ns/SMIL" version="3.0"><body>
+            <seq epub:textref="c.xhtml"><par><text src="c.xhtml#a"/><audio src="a.mp3" clipBegin="1s" clipEnd="2s"/></par>
+            <seq><par><text src="c.xhtml#b"/><audio src="a.mp3" clipBegin="2s"/></par></seq></seq>
+            </body></smil>"#,
+            "c.smil",
+        )
+        .unwrap();
+        assert_eq!(2, read.clips().len());
+        assert_eq!("b", read.clips()[1].id());
+        assert_eq!("a.mp3", read.clips()[1].audio());
+        assert_eq!(None, read.clips()[1].end());
+    }
+}
@@ -10,7 +10,9 @@ use std::{
 };
 
 use super::core::EpubReaderTrait;
+use super::encryption;
 use crate::prelude::*;
+use std::collections::HashMap;
 macro_rules! invalid {
     ($x:tt) => {
         Err(IError::InvalidArchive(Cow::from($x)))
@@ -23,14 +25,14 @@ macro_rules! invalid {
 macro_rules! read_from_zip {
     ($m:ident,$x:expr) => {{
         // 读取 container.xml
-        let mut file = invalid!($m.by_name($x), format!("{} not exist", $x));
-        let mut content = String::new();
-        invalid!(file.read_to_string(&mut content), "read err");
-        content
+        let mut file = invalid!($m.open($x), format!("{} not exist", $x));
+        let mut content = Vec::new();
+        invalid!(file.read_to_end(&mut content), "read err");
+        crate::common::decode_text(&content)
     }};
 }
 
-fn get_opf_location(xml: &str) -> IResult<String> {
+pub(crate) fn get_opf_location(xml: &str) -> IResult<String> {
     use quick_xml::events::Event;
     use quick_xml::reader::Reader;
 
@@ -77,9 +79,260 @@ fn create_meta(xml: &BytesStart) -> IResult<EpubMetaData> {
     Ok(meta)
 }
 
+/// dc 元素的属性，key 去掉了命名空间前缀
+fn get_dc_attrs(xml: &BytesStart) -> HashMap<String, String> {
+    xml.attributes()
+        .flatten()
+        .map(|a| {
+            let key = String::from_utf8_lossy(a.key.local_name().as_ref()).to_string();
+            let value = a
+                .unescape_value()
+                .map_or_else(|_| String::new(), |v| v.to_string());
+            (key, value)
+        })
+        .collect()
+}
+
+/// refines 指向的元素
+#[derive(Clone, Copy)]
+enum MetaRefines {
+    Title(usize),
+    Identifier(usize),
+    Creator(usize),
+    Contributor(usize),
+}
+
+/// 多值的元数据，读取完成后再统一处理 refines
+#[derive(Default)]
+struct MetaBuf {
+    titles: Vec<EpubTitle>,
+    identifiers: Vec<EpubIdentifier>,
+    creators: Vec<EpubPerson>,
+    contributors: Vec<EpubPerson>,
+    /// id -> 元素
+    ids: HashMap<String, MetaRefines>,
+}
+
+impl MetaBuf {
+    fn person(attrs: &HashMap<String, String>, name: &str) -> EpubPerson {
+        let mut person = EpubPerson::new(name);
+        if let Some(v) = attrs.get("role") {
+            person.set_role(v.as_str());
+        }
+        if let Some(v) = attrs.get("file-as") {
+            person.set_file_as(v.as_str());
+        }
+        person
+    }
+
+    fn push(&mut self, name: &str, attrs: &HashMap<String, String>, text: &str) {
+        let target = match name {
+            "dc:title" => {
+                self.titles.push(EpubTitle::new(text));
+                MetaRefines::Title(self.titles.len() - 1)
+            }
+            "dc:identifier" => {
+                let mut v = EpubIdentifier::new(text);
+                if let Some(scheme) = attrs.get("scheme") {
+                    v.set_scheme(scheme.as_str());
+                }
+                self.identifiers.push(v);
+                MetaRefines::Identifier(self.identifiers.len() - 1)
+            }
+            "dc:creator" => {
+                self.creators.push(Self::person(attrs, text));
+                MetaRefines::Creator(self.creators.len() - 1)
+            }
+            "dc:contributor" => {
+                self.contributors.push(Self::person(attrs, text));
+                MetaRefines::Contributor(self.contributors.len() - 1)
+            }
+            _ => return,
+        };
+        if let Some(id) = attrs.get("id") {
+            self.ids.insert(id.clone(), target);
+        }
+    }
+
+    ///
+    /// 应用 refines
+    ///
+    /// 返回 false 表示不支持该属性，需要作为自定义元数据保留
+    ///
+    fn refine(&mut self, meta: &EpubMetaData) -> bool {
+        let Some(target) = meta
+            .get_attr("refines")
+            .and_then(|f| self.ids.get(f.trim().trim_start_matches('#')))
+            .copied()
+        else {
+            return false;
+        };
+        let Some(property) = meta.get_attr("property") else {
+            return false;
+        };
+        let value = meta.text().unwrap_or("").trim();
+        let lang = meta.get_attr("xml:lang").map_or("", |f| f.as_str());
+
+        match target {
+            MetaRefines::Title(i) => {
+                let title = &mut self.titles[i];
+                match property.as_str() {
+                    "title-type" => match TitleType::from_str(value) {
+                        Some(t) => title.set_title_type(Some(t)),
+                        None => return false,
+                    },
+                    "file-as" => title.set_file_as(value),
+                    "alternate-script" => title.add_alternate_script(lang, value),
+                    _ => return false,
+                }
+            }
+            MetaRefines::Identifier(i) => match property.as_str() {
+                "identifier-type" => self.identifiers[i].set_scheme(value),
+                _ => return false,
+            },
+            MetaRefines::Creator(i) | MetaRefines::Contributor(i) => {
+                let person = if let MetaRefines::Creator(_) = target {
+                    &mut self.creators[i]
+                } else {
+                    &mut self.contributors[i]
+                };
+                match property.as_str() {
+                    "role" => person.set_role(value),
+                    "file-as" => person.set_file_as(value),
+                    "alternate-script" => person.add_alternate_script(lang, value),
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+}
+
+///
+/// 从自定义元数据中读取系列
+///
+/// 支持 epub3 的 belongs-to-collection 以及 calibre:series，优先使用前者
+///
+fn read_series(book: &mut EpubBook) {
+    let attr = |meta: &EpubMetaData, key: &str, value: &str| {
+        meta.get_attr(key).is_some_and(|f| f == value)
+    };
+    let text = |meta: &EpubMetaData| meta.text().map(|f| f.trim().to_string());
+    // calibre 的属性值没有经过反转义
+    let content = |meta: &EpubMetaData| {
+        meta.get_attr("content").map(|f| {
+            quick_xml::escape::unescape(f.as_str())
+                .map_or_else(|_| f.to_string(), |v| v.to_string())
+        })
+    };
+
+    let mut series = None;
+    let mut index = None;
+    let mut consumed = Vec::new();
+
+    for (i, meta) in book.meta().iter().enumerate() {
+        if !attr(meta, "property", "belongs-to-collection") {
+            continue;
+        }
+        let refines: Vec<(usize, &EpubMetaData)> = match meta.get_attr("id") {
+            Some(id) => book
+                .meta()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| {
+                    f.get_attr("refines")
+                        .is_some_and(|r| r.trim().trim_start_matches('#') == id)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // 只处理系列，其他合集保留
+        if refines.iter().any(|(_, f)| {
+            attr(f, "property", "collection-type") && text(f).is_some_and(|t| t != "series")
+        }) {
+            continue;
+        }
+        series = text(meta);
+        consumed.push(i);
+        for (r, f) in refines {
+            if attr(f, "property", "group-position") {
+                index = text(f);
+                consumed.push(r);
+            } else if attr(f, "property", "collection-type") {
+                consumed.push(r);
+            }
+        }
+        break;
+    }
+
+    for (i, meta) in book.meta().iter().enumerate() {
+        if attr(meta, "name", "calibre:series") {
+            if series.is_none() {
+                series = content(meta);
+            }
+            consumed.push(i);
+        } else if attr(meta, "name", "calibre:series_index") {
+            if index.is_none() {
+                index = content(meta);
+            }
+            consumed.push(i);
+        }
+    }
+
+    consumed.sort_unstable();
+    for i in consumed.into_iter().rev() {
+        book.remove_meta(i);
+    }
+    if let Some(v) = series.filter(|f| !f.is_empty()) {
+        book.set_series(v);
+        if let Some(v) = index.filter(|f| !f.is_empty()) {
+            book.set_series_index(v);
+        }
+    }
+}
+
+///
+/// 从自定义元数据中读取固定布局相关的 rendition:layout、rendition:orientation、rendition:spread
+///
+/// 无法识别的值保留为自定义元数据
+///
+fn read_rendition(book: &mut EpubBook) {
+    let mut index = 0;
+    while let Some(meta) = book.get_meta(index) {
+        let property = match meta.get_attr("refines") {
+            None => meta.get_attr("property").cloned().unwrap_or_default(),
+            Some(_) => String::new(),
+        };
+        let value = meta.text().unwrap_or_default().to_string();
+        let consumed = match property.as_str() {
+            "rendition:layout" => RenditionLayout::from_str(&value)
+                .map(|v| book.set_layout(Some(v)))
+                .is_some(),
+            "rendition:orientation" => RenditionOrientation::from_str(&value)
+                .map(|v| book.set_orientation(Some(v)))
+                .is_some(),
+            "rendition:spread" => RenditionSpread::from_str(&value)
+                .map(|v| book.set_spread(Some(v)))
+                .is_some(),
+            _ => false,
+        };
+        if consumed {
+            book.remove_meta(index);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+///
+/// 读取 metadata
+///
+/// [unique_id] package 上的 unique-identifier，对应的标识会放在第一个
+///
 fn read_meta_xml(
     reader: &mut quick_xml::reader::Reader<&[u8]>,
     book: &mut EpubBook,
+    unique_id: Option<&str>,
 ) -> IResult<()> {
     use quick_xml::events::Event;
 
@@ -91,6 +344,9 @@ fn read_meta_xml(
 
     // 可能有多个时间，所以需要缓存，然后再分别处理
     let mut date_buf: Vec<(Option<String>, Option<String>)> = Vec::new();
+    // 作者等可能有多个，且需要通过 refines 补充信息
+    let mut meta_buf = MetaBuf::default();
+    let mut dc_attrs = HashMap::new();
 
     // 模拟 栈，记录当前的层级
     let mut parent: Vec<String> = vec!["package".to_string(), "metadata".to_string()];
@@ -105,6 +361,9 @@ fn read_meta_xml(
             }
             Ok(Event::Start(e)) => {
                 let name = String::from_utf8(e.name().as_ref().to_vec()).map_err(IError::Utf8)?;
+                if name.starts_with("dc:") {
+                    dc_attrs = get_dc_attrs(&e);
+                }
 
                 if name == "meta" {
                     if parent.len() != 2 || parent[1] != "metadata" {
@@ -184,14 +443,8 @@ fn read_meta_xml(
                             m.set_text(text.trim());
                         }
                     }
-                    "dc:identifier" => {
-                        book.set_identifier(text.trim());
-                    }
-                    "dc:title" => {
-                        book.set_title(text.trim());
-                    }
-                    "dc:creator" => {
-                        book.set_creator(text.trim());
+                    "dc:identifier" | "dc:title" | "dc:creator" | "dc:contributor" => {
+                        meta_buf.push(name.as_str(), &dc_attrs, text.trim());
                     }
                     "dc:description" => {
                         book.set_description(text.trim());
@@ -202,11 +455,11 @@ fn read_meta_xml(
                     "dc:publisher" => {
                         book.set_publisher(text.trim());
                     }
-                    "dc:subject" => {
-                        book.set_subject(text.trim());
+                    "dc:language" => {
+                        book.set_language(text.trim());
                     }
-                    "dc:contributor" => {
-                        book.set_contributor(text.trim());
+                    "dc:subject" => {
+                        book.add_subject(text.trim());
                     }
                     "dc:date" => {
                         if let Some(last_mut) = date_buf.last_mut() {
@@ -231,6 +484,40 @@ fn read_meta_xml(
         }
     }
 
+    // 处理 refines，已经识别的不再作为自定义元数据
+    let mut index = 0;
+    while let Some(meta) = book.get_meta(index) {
+        if meta_buf.refine(meta) {
+            book.remove_meta(index);
+        } else {
+            index += 1;
+        }
+    }
+    read_series(book);
+    read_rendition(book);
+    if let Some(index) = unique_id
+        .and_then(|id| meta_buf.ids.get(id))
+        .and_then(|f| match f {
+            MetaRefines::Identifier(i) => Some(*i),
+            _ => None,
+        })
+    {
+        let v = meta_buf.identifiers.remove(index);
+        meta_buf.identifiers.insert(0, v);
+    }
+    for v in meta_buf.titles {
+        book.add_title(v);
+    }
+    for v in meta_buf.identifiers {
+        book.add_identifier(v);
+    }
+    for v in meta_buf.creators {
+        book.add_creator(v);
+    }
+    for v in meta_buf.contributors {
+        book.add_contributor(v);
+    }
+
     // 处理时间
     for (event, value) in &date_buf {
         if let Some(event) = event {
@@ -254,91 +541,55 @@ fn read_meta_xml(
     Ok(())
 }
 
-/// 从xml中获取封面页
+/// 从guide中获取封面页以及其他地标
 fn read_guide_xml(
     reader: &mut quick_xml::reader::Reader<&[u8]>,
     book: &mut EpubBook,
 ) -> IResult<()> {
     use quick_xml::events::Event;
-    // 模拟 栈，记录当前的层级
-    let _parent: Vec<String> = vec!["package".to_string(), "metadata".to_string()];
+    let attr = |e: &BytesStart, key: &str| {
+        e.try_get_attribute(key)
+            .ok()
+            .and_then(|f| f)
+            .map(|f| {
+                f.unescape_value()
+                    .map_or_else(|_| String::new(), |v| v.to_string())
+            })
+            .unwrap_or_default()
+    };
+    let mut has_cover = false;
     let mut buf = Vec::new();
     loop {
         match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                if e.name().as_ref() == b"reference" {
-                    // <reference href="cover.xhtml" title="cover" type="cover"/>
-                    // 读取封面页的时候，不一定已经获取到了章节
-                    if let Some(_t) = e
-                        .try_get_attribute("type")
-                        .ok()
-                        .and_then(|f| f)
-                        .map(|f| {
-                            f.unescape_value()
-                                .map_or_else(|_| String::new(), |v| v.to_string())
-                        })
-                        .filter(|f| f == "cover")
-                    {
-                        if let Some(href) =
-                            e.try_get_attribute("href").ok().and_then(|f| f).map(|f| {
-                                f.unescape_value()
-                                    .map_or_else(|_| String::new(), |v| v.to_string())
-                            })
-                        {
-                            book.cover_chapter = Some(EpubHtml::default().with_file_name(href));
-                        }
-                        break;
-                    }
-                }
-            }
-            Ok(Event::Empty(e)) => {
-                match e.name().as_ref() {
-                    b"reference" => {
-                        // <reference href="cover.xhtml" title="cover" type="cover"/>
-                        // 读取封面页的时候，不一定已经获取到了章节
-
-                        if let Some(t) = e
-                            .try_get_attribute("type")
-                            .ok()
-                            .and_then(|f| f)
-                            .map(|f| {
-                                f.unescape_value()
-                                    .map_or_else(|_| String::new(), |v| v.to_string())
-                            })
-                            .filter(|f| f == "cover")
-                        {
-                            if let Some(href) =
-                                e.try_get_attribute("href").ok().and_then(|f| f).map(|f| {
-                                    f.unescape_value()
-                                        .map_or_else(|_| String::new(), |v| v.to_string())
-                                })
-                            {
-                                book.cover_chapter = Some(EpubHtml::default().with_file_name(href));
-                            }
-                            break;
-                        }
-                    }
-                    _ => {
-                        // invalid!("item err")
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"reference" => {
+                // <reference href="cover.xhtml" title="cover" type="cover"/>
+                // 读取封面页的时候，不一定已经获取到了章节
+                let href = attr(&e, "href");
+                let guide_type = attr(&e, "type");
+                if !href.is_empty() && !guide_type.is_empty() {
+                    if guide_type == "cover" && !has_cover {
+                        has_cover = true;
+                        book.cover_chapter = Some(EpubHtml::default().with_file_name(&href));
                     }
+                    book.add_landmark(EpubLandmark::from_guide(
+                        &guide_type,
+                        &href,
+                        &attr(&e, "title"),
+                    ));
                 }
             }
-            Ok(Event::End(e)) => {
-                if e.name().as_ref() == b"guide" {
-                    break;
-                }
+            Ok(Event::End(e)) if e.name().as_ref() == b"guide" => {
+                break;
             }
-
             Ok(Event::Eof) => {
                 break;
             }
             Err(_e) => {
                 return invalid!("err");
             }
-            _ => {
-                break;
-            }
+            _ => {}
         }
+        buf.clear();
     }
     Ok(())
 }
@@ -353,6 +604,8 @@ fn read_spine_xml(
     // 模拟 栈，记录当前的层级
     let _parent: Vec<String> = vec!["package".to_string(), "metadata".to_string()];
     let mut buf = Vec::new();
+    // itemref 在 spine 中的位置
+    let mut spine_index = 0;
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::End(e)) => {
@@ -368,32 +621,39 @@ fn read_spine_xml(
                 }
             }
 
-            Ok(Event::Empty(e)) => {
-                if e.name().as_ref() == b"itemref" {
-                    if let Ok(href) = e.try_get_attribute("idref") {
-                        if let Some(h) = href.map(|f| {
-                            f.unescape_value()
-                                .map_or_else(|_| String::new(), |v| v.to_string())
-                        }) {
-                            let xhtml = assets
-                                .iter()
-                                .enumerate()
-                                .find(|(_index, s)| s.id() == h.as_str());
-                            if let Some((index, xh)) = xhtml {
-                                book.add_chapter(
-                                    EpubHtml::default().with_file_name(xh.file_name()),
-                                );
-                                if !xh.id().eq_ignore_ascii_case("toc")
-                                    && xh.file_name().contains(".xhtml")
-                                {
-                                    assets.remove(index);
-                                }
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"itemref" => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                if let Some(h) = attr("idref") {
+                    let xhtml = assets
+                        .iter()
+                        .enumerate()
+                        .find(|(_index, s)| s.id() == h.as_str());
+                    if let Some((index, xh)) = xhtml {
+                        let mut chap = EpubHtml::default().with_file_name(xh.file_name());
+                        chap.spine_index = Some(spine_index);
+                        if let Some(id) = attr("id") {
+                            chap.set_spine_id(id);
+                        }
+                        chap.set_linear(attr("linear").is_none_or(|f| f.trim() != "no"));
+                        if let Some(properties) = attr("properties") {
+                            for ele in properties.split_whitespace() {
+                                chap.add_spine_property(ele);
                             }
                         }
+                        book.add_chapter(chap);
+                        if !xh.id().eq_ignore_ascii_case("toc") && xh.file_name().contains(".xhtml")
+                        {
+                            assets.remove(index);
+                        }
                     }
                 }
+                spine_index += 1;
             }
-
             _ => {
                 break;
             }
@@ -484,6 +744,7 @@ fn read_opf_xml(xml: &str, book: &mut EpubBook) -> IResult<()> {
     // 模拟 栈，记录当前的层级
     let mut parent: Vec<String> = Vec::new();
     let mut assets: Vec<EpubAssets> = Vec::new();
+    let mut unique_id: Option<String> = None;
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Eof) => {
@@ -496,6 +757,9 @@ fn read_opf_xml(xml: &str, book: &mut EpubBook) -> IResult<()> {
                 b"package" => {
                     parent.push("package".to_string());
                     for attr in e.attributes().flatten() {
+                        if attr.key.as_ref() == b"unique-identifier" {
+                            unique_id = Some(attr.unescape_value()?.trim().to_string());
+                        }
                         if attr.key.as_ref() == b"version" {
                             let ver = attr.unescape_value()?.trim().to_string();
                             if !ver.is_empty() {
@@ -512,7 +776,7 @@ fn read_opf_xml(xml: &str, book: &mut EpubBook) -> IResult<()> {
                     } else {
                         parent.push("metadata".to_string());
                     }
-                    read_meta_xml(&mut reader, book)?;
+                    read_meta_xml(&mut reader, book, unique_id.as_deref())?;
                 }
                 b"manifest" => {
                     read_manifest_xml(&mut reader, book, &mut assets)?;
@@ -864,90 +1128,989 @@ fn read_nav_xhtml(xhtml: &str, root_path: String, book: &mut EpubBook) -> IResul
     Ok(())
 }
 
-fn has_epub_type(e: &BytesStart, value: &str) -> bool {
-    e.attributes().any(|a| {
-        if let Ok(attr) = a {
-            attr.key.0 == b"epub:type" && &*attr.value == value.as_bytes()
-        } else {
-            false
-        }
-    })
-}
-
-#[derive(Debug, Clone)]
-struct EpubReader<T: Read + Seek> {
-    inner: zip::ZipArchive<T>,
-}
+///
+/// 读取导航文件中的 landmarks 和 page-list
+///
+/// 有 landmarks 时会替换掉从 guide 中读取的地标
+///
+fn read_nav_landmarks(xhtml: &str, root_path: &str, book: &mut EpubBook) -> IResult<()> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
 
-impl<T: Read + Seek> Drop for EpubReader<T> {
-    fn drop(&mut self) {}
-}
+    let mut reader = Reader::from_str(xhtml);
+    reader.config_mut().trim_text(true);
 
-impl<T: Read + Seek> EpubReader<T> {
-    pub fn new(value: T) -> IResult<Self> {
-        let r = zip::ZipArchive::new(value)?;
-        Ok(EpubReader { inner: r })
+    let mut landmarks = Vec::new();
+    let mut pages = Vec::new();
+    // 当前所在的nav
+    let mut current: Option<&str> = None;
+    // (epub:type, href, 文本)
+    let mut link: Option<(String, String, String)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"nav" if has_epub_type(&e, "landmarks") => current = Some("landmarks"),
+                b"nav" if has_epub_type(&e, "page-list") => current = Some("page-list"),
+                b"a" if current.is_some() => {
+                    let attr = |key: &str| {
+                        e.try_get_attribute(key)
+                            .ok()
+                            .flatten()
+                            .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                            .unwrap_or_default()
+                    };
+                    let mut href = attr("href");
+                    if !href.starts_with(root_path) {
+                        href = format!("{}{}", root_path, href);
+                    }
+                    link = Some((attr("epub:type"), href, String::new()));
+                }
+                _ => (),
+            },
+            Event::Text(e) => {
+                if let Some((_, _, text)) = &mut link {
+                    text.push_str(&e.decode().map_err(IError::Encoding)?);
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Some((_, _, text)) = &mut link {
+                    let r = e.decode().map_err(IError::Encoding)?;
+                    if let Ok(v) = quick_xml::escape::unescape(format!("&{r};").as_str()) {
+                        text.push_str(&v);
+                    }
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"nav" => current = None,
+                b"a" => {
+                    if let Some((epub_type, href, text)) = link.take() {
+                        match current {
+                            Some("landmarks") if !epub_type.is_empty() => landmarks
+                                .push(EpubLandmark::new(epub_type, href).with_title(text.trim())),
+                            Some("page-list") => pages.push(EpubPage::new(text.trim(), &href)),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
     }
+    if !landmarks.is_empty() {
+        book.set_landmarks(landmarks);
+    }
+    for page in pages {
+        book.add_page(page);
+    }
+    Ok(())
 }
 
-impl<T: Read + Seek + Sync + Send> EpubReaderTrait for EpubReader<T> {
-    fn read(&mut self, book: &mut EpubBook) -> IResult<()> {
-        let reader = &mut self.inner;
+///
+/// 读取 toc.ncx 中的 pageList
+///
+/// [root_path] toc.ncx 相对于opf的目录
+///
+fn read_ncx_page_list(xml: &str, root_path: &str, book: &mut EpubBook) -> IResult<()> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
 
-        {
-            // 判断文件格式
-            let content = read_from_zip!(reader, "mimetype");
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
 
-            if content != "application/epub+zip" {
-                return invalid!("not a epub file");
+    let mut in_page_list = false;
+    let mut in_text = false;
+    // (页码, 链接)
+    let mut page: Option<(String, String)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                b"pageList" => in_page_list = true,
+                b"pageTarget" if in_page_list => page = Some((String::new(), String::new())),
+                b"text" if page.is_some() => in_text = true,
+                b"content" => {
+                    if let Some((_, href)) = &mut page {
+                        if let Some(src) = e
+                            .try_get_attribute("src")
+                            .ok()
+                            .flatten()
+                            .and_then(|f| f.unescape_value().ok())
+                        {
+                            *href = format!("{}{}", root_path, src);
+                        }
+                    }
+                }
+                _ => (),
+            },
+            Event::Text(e) => {
+                if let (true, Some((label, _))) = (in_text, &mut page) {
+                    label.push_str(&e.decode().map_err(IError::Encoding)?);
+                }
             }
+            Event::End(e) => match e.name().as_ref() {
+                b"pageList" => break,
+                b"text" => in_text = false,
+                b"pageTarget" => {
+                    if let Some((label, href)) = page.take() {
+                        book.add_page(EpubPage::new(label.trim(), &href));
+                    }
+                }
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
         }
+    }
+    Ok(())
+}
 
-        {
-            let content = read_from_zip!(reader, "META-INF/container.xml");
+///
+/// 从opf中获取epub3导航文件的位置
+///
+/// 返回相对于opf的路径
+///
+fn get_nav_location(opf: &str) -> Option<String> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
 
-            let opf_path = get_opf_location(content.as_str());
-            if let Ok(path) = opf_path {
-                let pp = crate::path::Path::system(path.as_str());
-                if pp.level_count() != 1 {
-                    book.prefix.push_str(pp.pop().to_str().as_str());
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                if attr("properties").is_some_and(|f| f.split_whitespace().any(|p| p == "nav")) {
+                    return attr("href");
                 }
-                let opf = read_from_zip!(reader, path.as_str());
-                read_opf_xml(opf.as_str(), book)?;
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
 
-                {
-                    // 读取导航
-                    if let Some(toc) = book.toc() {
-                        let t = crate::path::Path::system(path.as_str())
-                            .pop()
-                            .join(toc.file_name())
-                            .to_str();
-
-                        if reader.by_name(t.as_str()).is_ok() {
-                            let content = read_from_zip!(reader, t.as_str());
-                            if toc.file_name().contains(".xhtml") {
-                                let root = toc
-                                    .file_name()
-                                    .rfind("/")
-                                    .map(|f| {
-                                        toc.file_name()
-                                            .get(..(f + 1))
-                                            .unwrap_or_default()
-                                            .to_string()
-                                    })
-                                    .unwrap_or_default();
-                                read_nav_xhtml(content.as_str(), root, book)?;
-                            } else {
-                                read_nav_xml(content.as_str(), book)?;
-                            }
-                            book.update_chapter();
+///
+/// 从opf中获取 spine@toc 指向的 ncx 文件位置
+///
+/// 返回相对于opf的路径
+///
+pub(crate) fn get_ncx_location(opf: &str) -> Option<String> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
+
+    let mut reader = Reader::from_str(opf);
+    let mut items = HashMap::new();
+    let mut toc = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                match e.name().as_ref() {
+                    b"item" => {
+                        if let (Some(id), Some(href)) = (attr("id"), attr("href")) {
+                            items.insert(id, href);
                         }
                     }
+                    b"spine" => toc = attr("toc"),
+                    _ => {}
                 }
             }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
         }
-        // 从封面页获取封面
-        {
+    }
+    items.remove(toc?.as_str())
+}
+
+///
+/// 获取 manifest 中的 properties
+///
+/// 返回 href 对应的 properties，不包括写入时会重新生成的 nav 和 cover-image
+///
+fn get_manifest_properties(opf: &str) -> HashMap<String, Vec<String>> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
+
+    let mut reader = Reader::from_str(opf);
+    let mut properties = HashMap::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                if let (Some(href), Some(value)) = (attr("href"), attr("properties")) {
+                    let value: Vec<String> = value
+                        .split_whitespace()
+                        .filter(|f| *f != "nav" && *f != "cover-image")
+                        .map(|f| f.to_string())
+                        .collect();
+                    if !value.is_empty() {
+                        properties.insert(href, value);
+                    }
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    properties
+}
+
+///
+/// 获取 manifest 中的媒体覆盖
+///
+/// 返回 (章节路径, smil 的 id, smil 路径)
+///
+fn get_media_overlays(opf: &str) -> Vec<(String, String, String)> {
+    use quick_xml::events::Event;
+    use quick_xml::reader::Reader;
+
+    let mut reader = Reader::from_str(opf);
+    // id -> href
+    let mut items = HashMap::new();
+    let mut chapters = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
+                let attr = |key: &str| {
+                    e.try_get_attribute(key)
+                        .ok()
+                        .flatten()
+                        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+                };
+                if let (Some(id), Some(href)) = (attr("id"), attr("href")) {
+                    if let Some(overlay) = attr("media-overlay") {
+                        chapters.push((href.clone(), overlay));
+                    }
+                    items.insert(id, href);
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    chapters
+        .into_iter()
+        .filter_map(|(href, id)| items.get(&id).map(|smil| (href, id, smil.clone())))
+        .collect()
+}
+
+///
+/// 从自定义元数据中读取媒体覆盖相关的 media:duration、media:active-class
+///
+/// 返回 smil 的 id 对应的时长，总时长在写入时重新计算
+///
+fn read_media_meta(book: &mut EpubBook) -> HashMap<String, std::time::Duration> {
+    let mut durations = HashMap::new();
+    let mut index = 0;
+    while let Some(meta) = book.get_meta(index) {
+        let value = meta.text().unwrap_or_default().to_string();
+        let refines = meta
+            .get_attr("refines")
+            .map(|f| f.trim().trim_start_matches('#').to_string());
+        match meta.get_attr("property").map(|f| f.as_str()) {
+            Some("media:duration") => {
+                if let (Some(id), Some(v)) = (refines, super::overlay::parse_clock(&value)) {
+                    durations.insert(id, v);
+                }
+            }
+            Some("media:active-class") if refines.is_none() => {
+                book.set_media_active_class(value);
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        }
+        book.remove_meta(index);
+    }
+    durations
+}
+
+fn has_epub_type(e: &BytesStart, value: &str) -> bool {
+    e.attributes().any(|a| {
+        if let Ok(attr) = a {
+            attr.key.0 == b"epub:type" && &*attr.value == value.as_bytes()
+        } else {
+            false
+        }
+    })
+}
+
+///
+/// 宽松模式下读取时做出的修复
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpubWarning {
+    /// 缺少 mimetype 文件
+    MimetypeMissing,
+    /// mimetype 内容不正确，例如末尾有空白字符
+    MimetypeInvalid(String),
+    /// container.xml 中的 opf 路径不可用，通过扫描找到了 opf 文件
+    OpfGuessed {
+        /// container.xml 中声明的路径
+        declared: Option<String>,
+        /// 实际使用的路径
+        found: String,
+    },
+    /// 文件名大小写或编码和实际文件不一致
+    HrefMismatch {
+        /// 声明的路径
+        href: String,
+        /// 实际的路径
+        actual: String,
+    },
+    /// 文件不存在，已跳过
+    MissingFile(String),
+    /// xhtml 格式错误，已使用宽松方式解析
+    MalformedXhtml { file: String, error: String },
+}
+
+impl std::fmt::Display for EpubWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpubWarning::MimetypeMissing => write!(f, "mimetype 不存在"),
+            EpubWarning::MimetypeInvalid(v) => write!(f, "mimetype 内容不正确: {:?}", v),
+            EpubWarning::OpfGuessed { declared, found } => {
+                write!(f, "opf 路径 {:?} 不可用，使用 {}", declared, found)
+            }
+            EpubWarning::HrefMismatch { href, actual } => {
+                write!(f, "文件 {} 不存在，使用 {}", href, actual)
+            }
+            EpubWarning::MissingFile(v) => write!(f, "文件 {} 不存在，已跳过", v),
+            EpubWarning::MalformedXhtml { file, error } => {
+                write!(f, "文件 {} 格式错误: {}", file, error)
+            }
+        }
+    }
+}
+
+///
+/// 可以重新打开的数据源
+///
+/// 流式读取时每个文件都需要独立的读取位置，不能和 zip 共用同一个 reader
+///
+#[derive(Debug, Clone)]
+enum EpubSource {
+    File(std::path::PathBuf),
+    Memory(SharedBytes),
+}
+
+impl EpubSource {
+    /// 打开 [start, start + len) 范围内的数据
+    fn open(&self, start: u64, len: u64) -> IResult<Box<dyn Read + Send>> {
+        Ok(match self {
+            EpubSource::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(std::io::SeekFrom::Start(start))?;
+                Box::new(std::io::BufReader::new(file).take(len))
+            }
+            EpubSource::Memory(data) => {
+                let mut cursor = std::io::Cursor::new(data.clone());
+                cursor.set_position(start);
+                Box::new(cursor.take(len))
+            }
+        })
+    }
+}
+
+///
+/// 读取完毕时校验 crc32
+///
+/// 直接按偏移读取 zip 中的数据时不经过 [zip::read::ZipFile]，需要自行校验
+///
+struct CrcReader<R> {
+    inner: R,
+    crc: flate2::Crc,
+    expected: u32,
+}
+
+impl<R> CrcReader<R> {
+    fn new(inner: R, expected: u32) -> Self {
+        CrcReader {
+            inner,
+            crc: flate2::Crc::new(),
+            expected,
+        }
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.crc.update(&buf[..len]);
+        if len == 0 && !buf.is_empty() && self.crc.sum() != self.expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid checksum",
+            ));
+        }
+        Ok(len)
+    }
+}
+
+/// 共享的内存数据，避免流式读取和多线程读取时复制整个epub
+#[derive(Debug, Clone)]
+enum SharedBytes {
+    Vec(std::sync::Arc<Vec<u8>>),
+    Slice(std::sync::Arc<[u8]>),
+}
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            SharedBytes::Vec(v) => v.as_slice(),
+            SharedBytes::Slice(v) => v,
+        }
+    }
+}
+
+///
+/// 解压后的epub目录
+///
+#[derive(Debug, Clone)]
+pub(crate) struct DirArchive {
+    root: std::path::PathBuf,
+    /// 目录下所有文件，使用 / 分隔的相对路径
+    files: Vec<String>,
+}
+
+impl DirArchive {
+    pub(crate) fn new<P: AsRef<Path>>(root: P) -> IResult<Self> {
+        let root = root.as_ref().to_path_buf();
+        if !root.is_dir() {
+            return invalid!("not a directory");
+        }
+        let mut files = Vec::new();
+        let mut dirs = vec![(root.clone(), String::new())];
+        while let Some((dir, prefix)) = dirs.pop() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                // 跳过 .git .DS_Store 等隐藏文件
+                if name.starts_with('.') {
+                    continue;
+                }
+                let name = format!("{prefix}{name}");
+                if entry.file_type()?.is_dir() {
+                    dirs.push((entry.path(), format!("{name}/")));
+                } else {
+                    files.push(name);
+                }
+            }
+        }
+        files.sort();
+        Ok(DirArchive { root, files })
+    }
+
+    /// 文件的实际路径，不在目录中的文件返回 None
+    fn path(&self, name: &str) -> Option<std::path::PathBuf> {
+        self.files
+            .iter()
+            .any(|f| f == name)
+            .then(|| self.root.join(name))
+    }
+}
+
+///
+/// epub 的存储方式
+///
+#[derive(Debug, Clone)]
+pub(crate) enum EpubArchive<T: Read + Seek> {
+    Zip(zip::ZipArchive<T>),
+    Dir(DirArchive),
+}
+
+impl<T: Read + Seek> EpubArchive<T> {
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        match self {
+            EpubArchive::Zip(zip) => zip.index_for_name(name).is_some(),
+            EpubArchive::Dir(dir) => dir.path(name).is_some(),
+        }
+    }
+
+    pub(crate) fn file_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self {
+            EpubArchive::Zip(zip) => Box::new(zip.file_names()),
+            EpubArchive::Dir(dir) => Box::new(dir.files.iter().map(|f| f.as_str())),
+        }
+    }
+
+    /// 打开文件，不存在时返回 [IError::FileNotFound]
+    pub(crate) fn open(&mut self, name: &str) -> IResult<Box<dyn Read + '_>> {
+        Ok(match self {
+            EpubArchive::Zip(zip) => Box::new(zip.by_name(name).or(Err(IError::FileNotFound))?),
+            EpubArchive::Dir(dir) => {
+                Box::new(File::open(dir.path(name).ok_or(IError::FileNotFound)?)?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EpubReader<T: Read + Seek> {
+    inner: EpubArchive<T>,
+    /// 被混淆的字体，文件路径 -> (算法, 密钥)
+    obfuscation: HashMap<String, (FontObfuscation, Vec<u8>)>,
+    /// 宽松模式
+    lenient: bool,
+    /// 宽松模式下做出的修复
+    warnings: Vec<EpubWarning>,
+    /// 数据源，没有时流式读取会退化为读取整个文件
+    source: Option<EpubSource>,
+    /// opf 所在的目录，以 / 结尾
+    root: String,
+}
+
+impl<T: Read + Seek> Drop for EpubReader<T> {
+    fn drop(&mut self) {}
+}
+
+impl EpubReader<File> {
+    fn from_dir(dir: DirArchive) -> Self {
+        EpubReader {
+            inner: EpubArchive::Dir(dir),
+            obfuscation: HashMap::new(),
+            lenient: false,
+            warnings: Vec::new(),
+            source: None,
+            root: String::new(),
+        }
+    }
+}
+
+impl<T: Read + Seek> EpubReader<T> {
+    pub fn new(value: T) -> IResult<Self> {
+        let r = zip::ZipArchive::new(value)?;
+        Ok(EpubReader {
+            inner: EpubArchive::Zip(r),
+            obfuscation: HashMap::new(),
+            lenient: false,
+            warnings: Vec::new(),
+            source: None,
+            root: String::new(),
+        })
+    }
+
+    fn with_source(mut self, source: EpubSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// 新打开的句柄沿用已经读取到的配置
+    #[cfg(feature = "parallel")]
+    fn inherit_config<R: Read + Seek + Sync + Send + 'static>(
+        &self,
+        mut reader: EpubReader<R>,
+    ) -> Box<dyn EpubReaderTrait + Send + Sync> {
+        reader.lenient = self.lenient;
+        reader.obfuscation = self.obfuscation.clone();
+        reader.root = self.root.clone();
+        Box::new(reader)
+    }
+
+    ///
+    /// 文件在 zip 中的位置
+    ///
+    /// 返回 (压缩方式, 数据起始位置, 压缩后大小)，加密的文件返回 None
+    ///
+    fn raw_location(&mut self, name: &str) -> Option<(zip::CompressionMethod, u64, u64, u32)> {
+        let EpubArchive::Zip(zip) = &mut self.inner else {
+            return None;
+        };
+        let index = zip.index_for_name(name)?;
+        let file = zip.by_index_raw(index).ok()?;
+        if file.encrypted() {
+            return None;
+        }
+        Some((
+            file.compression(),
+            file.data_start()?,
+            file.compressed_size(),
+            file.crc32(),
+        ))
+    }
+
+    ///
+    /// 宽松模式，尽量修复错误而不是直接返回
+    ///
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    ///
+    /// 查找zip中实际的文件名
+    ///
+    /// 宽松模式下忽略大小写以及url编码
+    ///
+    fn resolve_name(&self, file_name: &str) -> Option<String> {
+        if self.inner.contains(file_name) {
+            return Some(file_name.to_string());
+        }
+        // 相对于 opf 所在目录的路径
+        let full = format!("{}{}", self.root, file_name);
+        if !self.root.is_empty() && self.inner.contains(full.as_str()) {
+            return Some(full);
+        }
+        if !self.lenient {
+            return None;
+        }
+        let decoded = crate::common::urldecode_enhanced(file_name).ok();
+        self.inner
+            .file_names()
+            .find(|f| {
+                f.eq_ignore_ascii_case(file_name)
+                    || decoded
+                        .as_deref()
+                        .is_some_and(|d| f.eq_ignore_ascii_case(d))
+            })
+            .map(|f| f.to_string())
+    }
+
+    /// 读取文件，不存在时返回 None
+    fn read_optional(&mut self, file_name: &str) -> IResult<Option<String>> {
+        match self.resolve_name(file_name) {
+            Some(name) => {
+                let reader = &mut self.inner;
+                Ok(Some(read_from_zip!(reader, name.as_str())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 宽松模式下忽略解析错误，记录为警告
+    fn tolerate(&mut self, result: IResult<()>, file_name: &str) -> IResult<()> {
+        match result {
+            Err(e) if self.lenient => {
+                self.warnings.push(EpubWarning::MalformedXhtml {
+                    file: file_name.to_string(),
+                    error: e.to_string(),
+                });
+                Ok(())
+            }
+            _ => result,
+        }
+    }
+
+    /// 判断文件格式
+    fn read_mimetype(&mut self) -> IResult<()> {
+        if self.lenient && !self.inner.contains("mimetype") {
+            self.warnings.push(EpubWarning::MimetypeMissing);
+            return Ok(());
+        }
+        let reader = &mut self.inner;
+        let content = read_from_zip!(reader, "mimetype");
+        if content != "application/epub+zip" {
+            if !self.lenient {
+                return invalid!("not a epub file");
+            }
+            self.warnings.push(EpubWarning::MimetypeInvalid(content));
+        }
+        Ok(())
+    }
+
+    ///
+    /// 获取 opf 文件路径
+    ///
+    /// 宽松模式下，如果 container.xml 中的路径不可用，将查找第一个 .opf 文件
+    ///
+    fn read_opf_location(&mut self) -> IResult<Option<String>> {
+        if !self.lenient {
+            let reader = &mut self.inner;
+            let content = read_from_zip!(reader, "META-INF/container.xml");
+            return Ok(get_opf_location(content.as_str()).ok());
+        }
+        let declared = self
+            .read_optional("META-INF/container.xml")?
+            .and_then(|f| get_opf_location(f.as_str()).ok())
+            .filter(|f| !f.is_empty());
+        if let Some(path) = declared.as_ref().filter(|f| self.inner.contains(f)) {
+            return Ok(Some(path.clone()));
+        }
+        let found = declared
+            .as_deref()
+            .and_then(|f| self.resolve_name(f))
+            .or_else(|| {
+                self.inner
+                    .file_names()
+                    .find(|f| f.to_lowercase().ends_with(".opf"))
+                    .map(|f| f.to_string())
+            });
+        match found {
+            Some(found) => {
+                self.warnings.push(EpubWarning::OpfGuessed {
+                    declared,
+                    found: found.clone(),
+                });
+                Ok(Some(found))
+            }
+            None => invalid!("has no opf"),
+        }
+    }
+
+    ///
+    /// 宽松模式下检查 opf 中声明的文件
+    ///
+    /// 文件名不一致的记录警告，读取时自动使用实际的文件；不存在的文件直接跳过；
+    /// 格式错误的章节记录警告，读取时使用宽松方式解析
+    ///
+    fn repair_items(&mut self, book: &mut EpubBook, opf_path: &str) {
+        let root = crate::path::Path::system(opf_path).pop();
+        let check = |reader: &mut Self, file_name: &str| -> Option<String> {
+            let href = root
+                .join(file_name.split('#').next().unwrap_or_default())
+                .to_str();
+            match reader.resolve_name(href.as_str()) {
+                Some(actual) => {
+                    if actual != href {
+                        reader.warnings.push(EpubWarning::HrefMismatch {
+                            href,
+                            actual: actual.clone(),
+                        });
+                    }
+                    Some(actual)
+                }
+                None => {
+                    reader.warnings.push(EpubWarning::MissingFile(href));
+                    None
+                }
+            }
+        };
+
+        let mut index = 0;
+        while let Some(file_name) = book.assets().nth(index).map(|f| f.file_name().to_string()) {
+            if check(self, file_name.as_str()).is_some() {
+                index += 1;
+            } else {
+                book.remove_assets(index);
+            }
+        }
+
+        let mut index = 0;
+        while let Some(file_name) = book
+            .chapters()
+            .nth(index)
+            .map(|f| f.file_name().to_string())
+        {
+            match check(self, file_name.as_str()) {
+                Some(actual) => {
+                    if let Ok(Some(html)) = self.read_optional(actual.as_str()) {
+                        if let Err(e) = super::html::get_html_info(html.as_str(), None) {
+                            self.warnings.push(EpubWarning::MalformedXhtml {
+                                file: actual,
+                                error: e.to_string(),
+                            });
+                        }
+                    }
+                    index += 1;
+                }
+                None => {
+                    book.remove_chapter(index);
+                }
+            }
+        }
+    }
+
+    ///
+    /// 读取 encryption.xml，记录被混淆的字体
+    ///
+    /// [opf_path] opf文件路径
+    /// [opf] opf文件内容
+    ///
+    fn read_encryption(&mut self, book: &mut EpubBook, opf_path: &str, opf: &str) -> IResult<()> {
+        if !self.inner.contains(encryption::ENCRYPTION) {
+            return Ok(());
+        }
+        let reader = &mut self.inner;
+        let content = read_from_zip!(reader, encryption::ENCRYPTION);
+        let files = encryption::read_encryption_xml(content.as_str())?;
+        if files.is_empty() {
+            return Ok(());
+        }
+        let (unique, uuid) = encryption::get_identifiers(opf)?;
+        for (file, alg) in files {
+            let key = match alg {
+                FontObfuscation::Idpf => unique.as_deref().and_then(|f| alg.key(f)),
+                FontObfuscation::Adobe => uuid.as_deref().and_then(|f| alg.key(f)),
+            };
+            if let Some(key) = key {
+                self.obfuscation.insert(file, (alg, key));
+            }
+        }
+        let root = crate::path::Path::system(opf_path).pop();
+        for ele in book.assets_mut() {
+            let f = root.join(ele.file_name()).to_str();
+            if let Some((alg, _)) = self.obfuscation.get(f.as_str()) {
+                ele.set_obfuscation(Some(*alg));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// 读取媒体覆盖，smil 文件不再作为资源文件
+    ///
+    fn read_media_overlays(
+        &mut self,
+        book: &mut EpubBook,
+        root: &crate::path::Path,
+        opf: &str,
+    ) -> IResult<()> {
+        let durations = read_media_meta(book);
+        for (chapter, id, smil) in get_media_overlays(opf) {
+            let t = root.join(smil.as_str()).to_str();
+            let Some(content) = self.read_optional(t.as_str())? else {
+                continue;
+            };
+            let result =
+                super::overlay::read_smil(content.as_str(), smil.as_str()).map(|mut overlay| {
+                    overlay.set_duration(durations.get(&id).copied());
+                    if let Some(chap) = book.get_chapter_mut(chapter.as_str()) {
+                        chap.set_media_overlay(Some(overlay));
+                    }
+                });
+            self.tolerate(result, t.as_str())?;
+            if let Some(index) = book.assets().position(|f| f.file_name() == smil) {
+                book.remove_assets(index);
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// 记录未在 opf 中声明的其他文件，写入时原样保留
+    ///
+    /// [opf_path] opf文件路径
+    ///
+    fn read_extra_files(&mut self, book: &mut EpubBook, opf_path: &str) {
+        let root = crate::path::Path::system(opf_path).pop();
+        let mut known: Vec<String> = vec![
+            "mimetype".to_string(),
+            "META-INF/container.xml".to_string(),
+            encryption::ENCRYPTION.to_string(),
+            opf_path.to_string(),
+        ];
+        let files = book
+            .assets()
+            .map(|f| f.file_name())
+            .chain(book.toc().map(|f| f.file_name()))
+            .chain(book.cover().map(|f| f.file_name()))
+            .chain(book.chapters().map(|f| f.file_name()))
+            .chain(
+                book.chapters()
+                    .filter_map(|f| f.media_overlay())
+                    .map(|f| f.file_name()),
+            )
+            .chain(book.cover_chapter().map(|f| f.file_name()));
+        for ele in files {
+            // 去掉锚点
+            let ele = ele.split('#').next().unwrap_or_default();
+            known.push(root.join(ele).to_str());
+            if let Ok(v) = crate::common::urldecode_enhanced(ele) {
+                known.push(root.join(v.as_str()).to_str());
+            }
+        }
+
+        let extra: Vec<String> = self
+            .inner
+            .file_names()
+            .filter(|f| {
+                !f.ends_with('/')
+                    && !known
+                        .iter()
+                        .any(|k| k == f || (self.lenient && k.eq_ignore_ascii_case(f)))
+            })
+            .map(|f| f.to_string())
+            .collect();
+        for ele in extra {
+            let mut file = EpubAssets::default();
+            file.media_type = crate::common::get_media_type(ele.as_str());
+            file.set_file_name(ele);
+            book.add_extra_file(file);
+        }
+    }
+}
+
+impl<T: Read + Seek + Sync + Send> EpubReaderTrait for EpubReader<T> {
+    fn read(&mut self, book: &mut EpubBook) -> IResult<()> {
+        self.read_mimetype()?;
+
+        let mut opf_file = None;
+        if let Some(path) = self.read_opf_location()? {
+            opf_file = Some(path.clone());
+            let pp = crate::path::Path::system(path.as_str());
+            book.set_opf_path(Some(path.clone()));
+            self.root = path[..path.rfind('/').map_or(0, |f| f + 1)].to_string();
+            let reader = &mut self.inner;
+            let opf = read_from_zip!(reader, path.as_str());
+            read_opf_xml(opf.as_str(), book)?;
+            // 没有读取内容的章节重新写入时保留原有的 properties
+            let properties = get_manifest_properties(opf.as_str());
+            for chap in book.chapters_mut() {
+                chap.manifest_properties = properties.get(chap.file_name()).cloned();
+            }
+            self.read_encryption(book, path.as_str(), opf.as_str())?;
+            if self.lenient {
+                self.repair_items(book, path.as_str());
+            }
+            let root = pp.pop();
+
+            // 读取导航
+            if let Some(toc) = book.toc().map(|f| f.file_name().to_string()) {
+                let t = root.join(toc.as_str()).to_str();
+                if let Some(content) = self.read_optional(t.as_str())? {
+                    let result = if toc.contains(".xhtml") {
+                        let root = toc[..toc.rfind('/').map_or(0, |f| f + 1)].to_string();
+                        read_nav_xhtml(content.as_str(), root, book)
+                    } else {
+                        read_nav_xml(content.as_str(), book)
+                    };
+                    self.tolerate(result, t.as_str())?;
+                    book.update_chapter();
+                }
+            }
+            // 读取 landmarks 和 page-list
+            if let Some(nav) = get_nav_location(opf.as_str()) {
+                let t = root.join(nav.as_str()).to_str();
+                if let Some(content) = self.read_optional(t.as_str())? {
+                    let nav_root = nav[..nav.rfind('/').map_or(0, |f| f + 1)].to_string();
+                    let result = read_nav_landmarks(content.as_str(), nav_root.as_str(), book);
+                    self.tolerate(result, t.as_str())?;
+                }
+            }
+            self.read_media_overlays(book, &root, opf.as_str())?;
+            // epub2 的页码在 toc.ncx 中
+            if let Some(toc) = book
+                .toc()
+                .filter(|_| book.page_list().is_empty())
+                .map(|f| f.file_name().to_string())
+                .filter(|f| !f.contains(".xhtml"))
+            {
+                let t = root.join(toc.as_str()).to_str();
+                if let Some(content) = self.read_optional(t.as_str())? {
+                    let toc_root = toc[..toc.rfind('/').map_or(0, |f| f + 1)].to_string();
+                    let result = read_ncx_page_list(content.as_str(), toc_root.as_str(), book);
+                    self.tolerate(result, t.as_str())?;
+                }
+            }
+        }
+        // 从封面页获取封面
+        {
             if let Some(co) = &mut book.cover_chapter {
                 co.read_data(self);
                 // let _ = co.data_mut().unwrap(); // 死锁，因为是不可重入锁
@@ -971,6 +2134,9 @@ impl<T: Read + Seek + Sync + Send> EpubReaderTrait for EpubReader<T> {
                 }
             }
         }
+        if let Some(path) = opf_file {
+            self.read_extra_files(book, path.as_str());
+        }
         book.update_chapter();
         book.update_assets();
 
@@ -978,38 +2144,93 @@ impl<T: Read + Seek + Sync + Send> EpubReaderTrait for EpubReader<T> {
     }
 
     fn read_file(&mut self, file_name: &str) -> IResult<Vec<u8>> {
-        let mut file = self
-            .inner
-            .by_name(file_name)
-            .or(Err(IError::FileNotFound))?;
+        let name = self.resolve_name(file_name).ok_or(IError::FileNotFound)?;
+        let mut file = self.inner.open(name.as_str())?;
         let mut content = Vec::new();
         invalid!(file.read_to_end(&mut content), "read err");
+        if let Some((alg, key)) = self.obfuscation.get(name.as_str()) {
+            alg.apply(key, &mut content, 0);
+        }
         Ok(content)
     }
 
     fn read_string(&mut self, file_name: &str) -> IResult<String> {
-        let mut file = self
-            .inner
-            .by_name(file_name)
-            .or(Err(IError::FileNotFound))?;
-        let mut content = String::new();
-        invalid!(file.read_to_string(&mut content), "read err");
-        Ok(content)
+        let name = self.resolve_name(file_name).ok_or(IError::FileNotFound)?;
+        let mut file = self.inner.open(name.as_str())?;
+        let mut content = Vec::new();
+        invalid!(file.read_to_end(&mut content), "read err");
+        Ok(crate::common::decode_text(&content))
+    }
+
+    fn open_stream(&mut self, file_name: &str) -> IResult<Box<dyn Read + Send>> {
+        let name = self.resolve_name(file_name).ok_or(IError::FileNotFound)?;
+        let location = self
+            .source
+            .is_some()
+            .then(|| self.raw_location(name.as_str()))
+            .flatten();
+        let stream: Box<dyn Read + Send> = match (&self.inner, &self.source, location) {
+            (EpubArchive::Dir(dir), _, _) => Box::new(std::io::BufReader::new(File::open(
+                dir.path(name.as_str()).ok_or(IError::FileNotFound)?,
+            )?)),
+            (_, Some(source), Some((zip::CompressionMethod::Stored, start, size, crc))) => {
+                Box::new(CrcReader::new(source.open(start, size)?, crc))
+            }
+            (_, Some(source), Some((zip::CompressionMethod::Deflated, start, size, crc))) => {
+                Box::new(CrcReader::new(
+                    flate2::read::DeflateDecoder::new(source.open(start, size)?),
+                    crc,
+                ))
+            }
+            // 其他压缩方式或者无法重新打开的数据源，只能完整读取
+            _ => return Ok(Box::new(std::io::Cursor::new(self.read_file(file_name)?))),
+        };
+        Ok(match self.obfuscation.get(name.as_str()) {
+            Some((alg, key)) => Box::new(encryption::ObfuscationReader::new(
+                stream,
+                *alg,
+                key.clone(),
+            )),
+            None => stream,
+        })
+    }
+
+    #[cfg(feature = "parallel")]
+    fn fork(&self) -> Option<Box<dyn EpubReaderTrait + Send + Sync>> {
+        if let EpubArchive::Dir(dir) = &self.inner {
+            return Some(self.inherit_config(EpubReader::<File>::from_dir(dir.clone())));
+        }
+        Some(match self.source.as_ref()? {
+            EpubSource::File(path) => self.inherit_config(file_reader(path).ok()?),
+            EpubSource::Memory(data) => self.inherit_config(memory_reader(data.clone()).ok()?),
+        })
+    }
+
+    fn lenient(&self) -> bool {
+        self.lenient
+    }
+
+    fn take_warnings(&mut self) -> Vec<EpubWarning> {
+        std::mem::take(&mut self.warnings)
     }
 
     fn read_to_path(&mut self, file_name: &str, file_path: &str) -> IResult<()> {
-        let mut file = self
-            .inner
-            .by_name(file_name)
-            .or(Err(IError::FileNotFound))?;
+        let name = self.resolve_name(file_name).ok_or(IError::FileNotFound)?;
+        let mut file = self.inner.open(name.as_str())?;
         let output_file = File::create(file_path)?;
         let mut writer = BufWriter::new(output_file);
         let mut buffer = [0u8; 16384];
+        let obfuscation = self.obfuscation.get(name.as_str());
+        let mut offset = 0;
         loop {
             let bytes_read = file.read(&mut buffer)?;
             if bytes_read == 0 {
                 break;
             }
+            if let Some((alg, key)) = obfuscation {
+                alg.apply(key, &mut buffer[..bytes_read], offset);
+            }
+            offset += bytes_read;
             writer.write_all(&buffer[..bytes_read])?;
         }
         Ok(())
@@ -1049,28 +2270,100 @@ pub(crate) fn get_img_src(html: &[u8], tag: &str, attribute: &str) -> Option<Vec
 /// 从内存读取epub
 ///
 pub fn read_from_vec(data: Vec<u8>) -> IResult<EpubBook> {
-    read_from_reader(std::io::Cursor::new(data))
+    read_book(memory_reader(SharedBytes::Vec(std::sync::Arc::new(data)))?).map(|f| f.0)
+}
+
+///
+/// 从共享的内存读取epub
+///
+/// 多本书或者多个线程可以共用同一份数据，不会复制
+///
+pub fn read_from_shared(data: std::sync::Arc<[u8]>) -> IResult<EpubBook> {
+    read_book(memory_reader(SharedBytes::Slice(data))?).map(|f| f.0)
 }
 
 ///
 /// 从文件读取epub
 ///
 pub fn read_from_file<P: AsRef<Path>>(file: P) -> IResult<EpubBook> {
-    read_from_reader(std::fs::File::open(file)?)
+    read_book(file_reader(file)?).map(|f| f.0)
+}
+
+///
+/// 从任意reader读取epub
+///
+/// 无法流式读取其中的文件，[EpubAssets::reader] 会读取整个文件
+///
+pub fn read_from_reader<T: Read + Seek + Sync + Send + 'static>(value: T) -> IResult<EpubBook> {
+    read_book(EpubReader::new(value)?).map(|f| f.0)
+}
+
+///
+/// 宽松模式从内存读取epub，同时返回读取时做出的修复
+///
+pub fn read_from_vec_lenient(data: Vec<u8>) -> IResult<(EpubBook, Vec<EpubWarning>)> {
+    read_book(memory_reader(SharedBytes::Vec(std::sync::Arc::new(data)))?.with_lenient(true))
+}
+
+///
+/// 宽松模式从文件读取epub，同时返回读取时做出的修复
+///
+/// 适用于 mimetype 缺失、container.xml 错误、文件名大小写不一致、文件缺失、xhtml 格式错误等情况
+///
+pub fn read_from_file_lenient<P: AsRef<Path>>(file: P) -> IResult<(EpubBook, Vec<EpubWarning>)> {
+    read_book(file_reader(file)?.with_lenient(true))
+}
+
+///
+/// 从解压后的目录读取epub
+///
+/// 目录结构与epub文件相同，即包含 mimetype 和 META-INF/container.xml，隐藏文件会被忽略
+///
+pub fn read_from_dir<P: AsRef<Path>>(dir: P) -> IResult<EpubBook> {
+    read_book(dir_reader(dir)?).map(|f| f.0)
+}
+
+///
+/// 宽松模式从解压后的目录读取epub，同时返回读取时做出的修复
+///
+pub fn read_from_dir_lenient<P: AsRef<Path>>(dir: P) -> IResult<(EpubBook, Vec<EpubWarning>)> {
+    read_book(dir_reader(dir)?.with_lenient(true))
 }
 
 ///
-/// 从任意reader读取epub
+/// 宽松模式从任意reader读取epub，同时返回读取时做出的修复
 ///
-pub fn read_from_reader<T: Read + Seek + Sync + Send + 'static>(value: T) -> IResult<EpubBook> {
-    let reader = EpubReader::new(value)?;
+pub fn read_from_reader_lenient<T: Read + Seek + Sync + Send + 'static>(
+    value: T,
+) -> IResult<(EpubBook, Vec<EpubWarning>)> {
+    read_book(EpubReader::new(value)?.with_lenient(true))
+}
+
+fn dir_reader<P: AsRef<Path>>(dir: P) -> IResult<EpubReader<File>> {
+    Ok(EpubReader::from_dir(DirArchive::new(dir)?))
+}
+
+fn memory_reader(data: SharedBytes) -> IResult<EpubReader<std::io::Cursor<SharedBytes>>> {
+    Ok(EpubReader::new(std::io::Cursor::new(data.clone()))?.with_source(EpubSource::Memory(data)))
+}
+
+fn file_reader<P: AsRef<Path>>(file: P) -> IResult<EpubReader<File>> {
+    Ok(EpubReader::new(File::open(file.as_ref())?)?
+        .with_source(EpubSource::File(file.as_ref().to_path_buf())))
+}
+
+fn read_book<T: Read + Seek + Sync + Send + 'static>(
+    reader: EpubReader<T>,
+) -> IResult<(EpubBook, Vec<EpubWarning>)> {
     let mut book = EpubBook::default();
     let re: std::sync::Arc<std::sync::Mutex<Box<dyn EpubReaderTrait + Sync + Send>>> =
         std::sync::Arc::new(std::sync::Mutex::new(Box::new(reader)));
     book.set_reader(std::sync::Arc::clone(&re));
 
-    re.lock().unwrap().read(&mut book)?;
-    Ok(book)
+    let mut r = re.lock().unwrap();
+    r.read(&mut book)?;
+    let warnings = r.take_warnings();
+    Ok((book, warnings))
 }
 
 /// 判断是否是epub文件
@@ -1090,7 +2383,7 @@ mod tests {
     };
     use std::fs;
 
-    use super::{is_epub, read_nav_xml};
+    use super::{is_epub, read_guide_xml, read_nav_xml, read_ncx_page_list};
 
     #[test]
     fn test_is_epub() {
@@ -1376,7 +2669,7 @@ html
         let mut book = EpubBook::default();
         let mut reader = quick_xml::Reader::from_str(xml);
         reader.config_mut().trim_text(true);
-        read_meta_xml(&mut reader, &mut book).unwrap();
+        read_meta_xml(&mut reader, &mut book, None).unwrap();
         assert_eq!(
             book.title(),
             r#"Test Story `~!@#$%^&*()_+ and []\{}| and ;':" and ,./<>?"#
@@ -1395,6 +2688,648 @@ html
         );
     }
 
+    #[test]
+    fn test_read_meta_refines() {
+        let xml = r##"<dc:identifier id="isbn">9787000000000</dc:identifier>
+        <meta refines="#isbn" property="identifier-type" scheme="onix:codelist5">ISBN</meta>
+        <dc:identifier id="uid">urn:uuid:1</dc:identifier>
+        <dc:title id="t2">副标题</dc:title>
+        <dc:title id="t1">书名</dc:title>
+        <meta refines="#t1" property="title-type">main</meta>
+        <meta refines="#t1" property="file-as">shu ming</meta>
+        <meta refines="#t2" property="title-type">subtitle</meta>
+        <meta refines="#t2" property="display-seq">2</meta>
+        <dc:creator id="c1">作者</dc:creator>
+        <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+        <meta refines="#c1" property="alternate-script" xml:lang="en">Author</meta>
+        <dc:creator opf:role="ill" opf:file-as="Hua, Jia">画家</dc:creator>
+        <dc:contributor id="c2">译者</dc:contributor>
+        <meta refines="#c2" property="role" scheme="marc:relators">trl</meta>
+        <dc:subject>科幻</dc:subject>
+        <dc:subject>小说</dc:subject>"##;
+
+        let mut book = EpubBook::default();
+        let mut reader = quick_xml::Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+        read_meta_xml(&mut reader, &mut book, Some("uid")).unwrap();
+
+        assert_eq!("urn:uuid:1", book.identifier());
+        assert_eq!(2, book.identifiers().len());
+        assert_eq!(Some("ISBN"), book.identifiers()[1].scheme());
+
+        assert_eq!("书名", book.title());
+        assert_eq!(2, book.titles().len());
+        assert_eq!(Some(TitleType::Subtitle), book.titles()[0].title_type());
+        assert_eq!(Some("shu ming"), book.titles()[1].file_as());
+
+        assert_eq!(Some("作者"), book.creator());
+        assert_eq!(2, book.creators().len());
+        assert_eq!(Some("aut"), book.creators()[0].role());
+        assert_eq!(
+            &[("en".to_string(), "Author".to_string())],
+            book.creators()[0].alternate_script()
+        );
+        assert_eq!(Some("ill"), book.creators()[1].role());
+        assert_eq!(Some("Hua, Jia"), book.creators()[1].file_as());
+        assert_eq!(Some("trl"), book.contributors()[0].role());
+        assert_eq!(&["科幻".to_string(), "小说".to_string()], book.subjects());
+
+        // 不支持的 refines 保留为自定义元数据
+        assert_eq!(1, book.meta_len());
+        assert_eq!(
+            Some(&"display-seq".to_string()),
+            book.get_meta(0).unwrap().get_attr("property")
+        );
+    }
+
+    #[test]
+    fn test_write_meta_refines() {
+        for version in ["3.0", "2.0"] {
+            let data = EpubBuilder::default()
+                .with_version(version)
+                .with_title("书名")
+                .add_title(EpubTitle::new("副标题").with_title_type(TitleType::Subtitle))
+                .with_identifier("urn:uuid:1")
+                .add_identifier(EpubIdentifier::new("9787000000000").with_scheme("ISBN"))
+                .add_creator(
+                    EpubPerson::new("作者")
+                        .with_role("aut")
+                        .with_file_as("Zuo, Zhe")
+                        .with_alternate_script("en", "Author"),
+                )
+                .add_creator(EpubPerson::new("画家").with_role("ill"))
+                .add_contributor(EpubPerson::new("译者").with_role("trl"))
+                .add_subject("科幻")
+                .add_subject("小说")
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_file_name("0.xhtml")
+                        .with_data("<p>0</p>".as_bytes().to_vec()),
+                )
+                .mem()
+                .unwrap();
+            let book = read_from_vec(data).unwrap();
+
+            assert_eq!("书名", book.title());
+            assert_eq!("urn:uuid:1", book.identifier());
+            assert_eq!(Some("ISBN"), book.identifiers()[1].scheme());
+            assert_eq!(2, book.creators().len());
+            assert_eq!(Some("aut"), book.creators()[0].role());
+            assert_eq!(Some("Zuo, Zhe"), book.creators()[0].file_as());
+            assert_eq!(Some("ill"), book.creators()[1].role());
+            assert_eq!(Some("trl"), book.contributors()[0].role());
+            assert_eq!(2, book.subjects().len());
+            assert!(book.meta().iter().all(|f| f.get_attr("refines").is_none()));
+            if version == "3.0" {
+                assert_eq!(Some(TitleType::Subtitle), book.titles()[1].title_type());
+                assert_eq!(1, book.creators()[0].alternate_script().len());
+            } else {
+                // epub2 不支持 refines
+                assert_eq!(None, book.titles()[1].title_type());
+                assert!(book.creators()[0].alternate_script().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_series() {
+        use std::io::Read;
+        let read = |xml: &str| {
+            let mut book = EpubBook::default();
+            let mut reader = quick_xml::Reader::from_str(xml);
+            reader.config_mut().trim_text(true);
+            read_meta_xml(&mut reader, &mut book, None).unwrap();
+            book
+        };
+
+        let book = read(
+            r##"<meta name="calibre:series" content="A &amp; B"/>
+        <meta name="calibre:series_index" content="2.0"/>"##,
+        );
+        assert_eq!(Some("A & B"), book.series());
+        assert_eq!(Some("2.0"), book.series_index());
+        assert_eq!(0, book.meta_len());
+
+        // epub3 优先，其他类型的合集保留
+        let book = read(
+            r##"<meta property="belongs-to-collection" id="c1">合集</meta>
+        <meta refines="#c1" property="collection-type">set</meta>
+        <meta property="belongs-to-collection" id="c2">系列</meta>
+        <meta refines="#c2" property="collection-type">series</meta>
+        <meta refines="#c2" property="group-position">3</meta>
+        <meta name="calibre:series" content="旧系列"/>"##,
+        );
+        assert_eq!(Some("系列"), book.series());
+        assert_eq!(Some("3"), book.series_index());
+        assert_eq!(2, book.meta_len());
+
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_series("系列")
+            .with_series_index("1.5")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_data("<p>0</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        let book = read_from_vec(data).unwrap();
+        assert_eq!(Some("系列"), book.series());
+        assert_eq!(Some("1.5"), book.series_index());
+        assert!(book.meta().iter().all(|f| f
+            .get_attr("name")
+            .is_none_or(|v| !v.starts_with("calibre:series"))));
+
+        // epub2 没有 refines，只写 calibre 的 meta
+        let data = EpubBuilder::default()
+            .with_version("2.0")
+            .with_title("书名")
+            .with_series("系列")
+            .with_series_index("2")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_data("<p>0</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(!opf.contains("belongs-to-collection"), "{}", opf);
+        assert!(!opf.contains("refines"), "{}", opf);
+        assert!(opf.contains(r#"<meta name="calibre:series" content="系列"/>"#));
+        let book = read_from_vec(data).unwrap();
+        assert_eq!(Some("系列"), book.series());
+        assert_eq!(Some("2"), book.series_index());
+    }
+
+    #[test]
+    fn test_read_guide_and_page_list() {
+        let xml = r#"<guide><reference href="Text/cover.xhtml" title="封面" type="cover"/><reference href="Text/1.xhtml#start" title="正文" type="text"/><reference href="Text/toc.xhtml" type="toc"/></guide>"#;
+        let mut book = EpubBook::default();
+        let mut reader = quick_xml::Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+        reader.read_event().unwrap();
+        read_guide_xml(&mut reader, &mut book).unwrap();
+
+        assert_eq!(
+            "Text/cover.xhtml",
+            book.cover_chapter().unwrap().file_name()
+        );
+        assert_eq!(3, book.landmarks().len());
+        assert_eq!("bodymatter", book.landmarks()[1].epub_type());
+        assert_eq!("Text/1.xhtml#start", book.landmarks()[1].href());
+        assert_eq!("text", book.landmarks()[1].guide_type());
+
+        let ncx = r#"<ncx><navMap><navPoint id="1"><navLabel><text>第一章</text></navLabel><content src="1.xhtml"/></navPoint></navMap><pageList><navLabel><text>Pages</text></navLabel><pageTarget id="p1" type="front"><navLabel><text>i</text></navLabel><content src="1.xhtml#pi"/></pageTarget><pageTarget id="p2" type="normal" value="1"><navLabel><text>1</text></navLabel><content src="1.xhtml#p1"/></pageTarget></pageList></ncx>"#;
+        read_ncx_page_list(ncx, "Text/", &mut book).unwrap();
+        assert_eq!(
+            &[
+                EpubPage::new("i", "Text/1.xhtml#pi"),
+                EpubPage::new("1", "Text/1.xhtml#p1")
+            ],
+            book.page_list()
+        );
+    }
+
+    #[test]
+    fn test_write_landmarks() {
+        for version in ["3.0", "2.0"] {
+            let data = EpubBuilder::default()
+                .with_version(version)
+                .with_title("书名")
+                .cover("cover.png", vec![1, 2, 3])
+                .add_landmark(EpubLandmark::new("bodymatter", "0.xhtml").with_title("正文"))
+                .add_landmark(EpubLandmark::new("cover", "old_cover.xhtml"))
+                .add_page(EpubPage::new("1", "0.xhtml#p1"))
+                .add_page(EpubPage::new("2", "0.xhtml#p2"))
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_file_name("0.xhtml")
+                        .with_data(r#"<p id="p1">1</p><p id="p2">2</p>"#.as_bytes().to_vec()),
+                )
+                .mem()
+                .unwrap();
+
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+            let read = |zip: &mut zip::ZipArchive<_>, name: &str| {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut zip.by_name(name).unwrap(), &mut content)
+                    .unwrap();
+                content
+            };
+            let opf = read(&mut zip, "OEBPS/content.opf");
+            assert!(opf.contains(r#"<reference href="0.xhtml" title="正文" type="text"/>"#));
+            assert!(!opf.contains("old_cover.xhtml"));
+            assert!(read(&mut zip, "OEBPS/toc.ncx").contains("<pageList>"));
+
+            let book = read_from_vec(data).unwrap();
+            let landmarks: Vec<&str> = book.landmarks().iter().map(|f| f.epub_type()).collect();
+            assert_eq!(vec!["cover", "bodymatter"], landmarks);
+            assert_eq!("0.xhtml", book.landmarks()[1].href());
+            assert_eq!(2, book.page_list().len());
+            assert_eq!("0.xhtml#p2", book.page_list()[1].href());
+        }
+    }
+
+    #[test]
+    fn test_spine_itemref() {
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_data(vec![1]),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_data(vec![1])
+                    .with_spine_id("p1"),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("2.xhtml")
+                    .with_data(vec![1]),
+            )
+            .with_chapter_linear("0.xhtml", false)
+            .with_chapter_page_spread("1.xhtml", PageSpread::Left)
+            .add_chapter_spine_property("1.xhtml", "rendition:layout-pre-paginated")
+            .with_chapter_page_spread("2.xhtml", PageSpread::Left)
+            .with_chapter_page_spread("2.xhtml", PageSpread::Center)
+            .mem()
+            .unwrap();
+
+        let mut book = read_from_vec(data).unwrap();
+        let c0 = book.get_chapter("0.xhtml").unwrap();
+        assert!(!c0.linear());
+        assert_eq!(None, c0.page_spread());
+
+        let c1 = book.get_chapter("1.xhtml").unwrap();
+        assert!(c1.linear());
+        assert_eq!(Some("p1"), c1.spine_id());
+        assert_eq!(Some(PageSpread::Left), c1.page_spread());
+        assert_eq!(
+            vec!["page-spread-left", "rendition:layout-pre-paginated"],
+            c1.spine_properties().collect::<Vec<&String>>()
+        );
+        assert_eq!(
+            Some(PageSpread::Center),
+            book.get_chapter("2.xhtml").unwrap().page_spread()
+        );
+
+        // 再次写入后保持不变
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let book = read_from_vec(data).unwrap();
+        assert!(!book.get_chapter("0.xhtml").unwrap().linear());
+        assert_eq!(
+            Some(PageSpread::Left),
+            book.get_chapter("1.xhtml").unwrap().page_spread()
+        );
+    }
+
+    #[test]
+    fn test_media_overlay() {
+        use std::io::Read;
+        use std::time::Duration;
+
+        let overlay = EpubMediaOverlay::default()
+            .with_clip(EpubClip::new(
+                "p1",
+                "audio/1.mp3",
+                Duration::ZERO,
+                Duration::from_millis(1500),
+            ))
+            .with_clip(EpubClip::new(
+                "p2",
+                "audio/1.mp3",
+                Duration::from_millis(1500),
+                Duration::from_secs(4),
+            ));
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("text/1.xhtml")
+                    .with_data(r#"<p id="p1">一</p><p id="p2">二</p>"#.as_bytes().to_vec()),
+            )
+            .add_assets("audio/1.mp3", vec![1, 2, 3])
+            .add_media_overlay("text/1.xhtml", overlay.clone())
+            .with_media_active_class("-epub-media-overlay-active")
+            .mem()
+            .unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf
+            .contains(r##"<meta property="media:duration" refines="#smil_0">0:00:04.000</meta>"##));
+        assert!(opf.contains(r#"<meta property="media:duration">0:00:04.000</meta>"#));
+        assert!(opf.contains(r#"media-overlay="smil_0""#));
+        assert!(opf.contains(
+            r#"<item href="text/1.smil" id="smil_0" media-type="application/smil+xml"/>"#
+        ));
+
+        let mut book = read_from_vec(data).unwrap();
+        assert_eq!(
+            Some("-epub-media-overlay-active"),
+            book.media_active_class()
+        );
+        assert!(book.meta().iter().all(|f| f
+            .get_attr("property")
+            .is_none_or(|v| !v.starts_with("media:"))));
+        assert!(book.assets().all(|f| !f.file_name().ends_with(".smil")));
+        assert!(book
+            .extra_files()
+            .all(|f| !f.file_name().ends_with(".smil")));
+        let read = book
+            .get_chapter("text/1.xhtml")
+            .and_then(|f| f.media_overlay())
+            .unwrap();
+        assert_eq!("text/1.smil", read.file_name());
+        assert_eq!(overlay.clips(), read.clips());
+        assert_eq!(Duration::from_secs(4), read.duration());
+
+        // 再次写入后保持不变
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let book = read_from_vec(data).unwrap();
+        assert_eq!(
+            overlay.clips(),
+            book.get_chapter("text/1.xhtml")
+                .and_then(|f| f.media_overlay())
+                .unwrap()
+                .clips()
+        );
+    }
+
+    #[test]
+    fn test_extra_files() {
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .add_assets("style.css", b"p{}".to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_data(vec![1]),
+            )
+            .mem()
+            .unwrap();
+        // 添加 opf 中没有声明的文件
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for i in 0..zip.len() {
+            out.raw_copy_file(zip.by_index(i).unwrap()).unwrap();
+        }
+        let options = zip::write::SimpleFileOptions::default();
+        out.start_file("META-INF/com.apple.ibooks.display-options.xml", options)
+            .unwrap();
+        std::io::Write::write_all(&mut out, b"<display_options/>").unwrap();
+        out.start_file("rights.xml", options).unwrap();
+        std::io::Write::write_all(&mut out, b"rights").unwrap();
+        let data = out.finish().unwrap().into_inner();
+
+        let mut book = read_from_vec(data).unwrap();
+        let extra: Vec<&str> = book.extra_files().map(|f| f.file_name()).collect();
+        assert_eq!(
+            vec![
+                "META-INF/com.apple.ibooks.display-options.xml",
+                "rights.xml"
+            ],
+            extra
+        );
+
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut content = String::new();
+        std::io::Read::read_to_string(
+            &mut zip
+                .by_name("META-INF/com.apple.ibooks.display-options.xml")
+                .unwrap(),
+            &mut content,
+        )
+        .unwrap();
+        assert_eq!("<display_options/>", content);
+        assert!(zip.index_for_name("rights.xml").is_some());
+
+        book.remove_extra_file(1);
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        assert!(zip.index_for_name("rights.xml").is_none());
+        assert!(zip
+            .index_for_name("META-INF/com.apple.ibooks.display-options.xml")
+            .is_some());
+    }
+
+    #[test]
+    fn test_read_lenient() {
+        let data = EpubBuilder::default()
+            .append_title(false)
+            .with_title("书名")
+            .add_assets("style.css", b"p{}".to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("0.xhtml")
+                    .with_data(b"<p>0</p>".to_vec()),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第二章")
+                    .with_file_name("1.xhtml")
+                    .with_data(b"<p>1</p>".to_vec()),
+            )
+            .mem()
+            .unwrap();
+        // 制造各种错误
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut content).unwrap();
+            let (name, content) = match file.name() {
+                "mimetype" => ("mimetype", b"application/epub+zip\n".to_vec()),
+                "META-INF/container.xml" => (
+                    "META-INF/container.xml",
+                    String::from_utf8(content)
+                        .unwrap()
+                        .replace("OEBPS/content.opf", "wrong/content.opf")
+                        .into_bytes(),
+                ),
+                "OEBPS/0.xhtml" => ("OEBPS/0.XHTML", content),
+                "OEBPS/1.xhtml" => (
+                    "OEBPS/1.xhtml",
+                    b"<html><head><title>t</title><!-- </head><body class=\"c\"><p>1</p></body></html>"
+                        .to_vec(),
+                ),
+                "OEBPS/style.css" => continue,
+                v => (v, content),
+            };
+            out.start_file(name, options).unwrap();
+            std::io::Write::write_all(&mut out, &content).unwrap();
+        }
+        let data = out.finish().unwrap().into_inner();
+
+        assert!(read_from_vec(data.clone()).is_err());
+
+        let (mut book, warnings) = read_from_vec_lenient(data).unwrap();
+        assert_eq!(
+            EpubWarning::MimetypeInvalid("application/epub+zip\n".to_string()),
+            warnings[0]
+        );
+        assert_eq!(
+            EpubWarning::OpfGuessed {
+                declared: Some("wrong/content.opf".to_string()),
+                found: "OEBPS/content.opf".to_string()
+            },
+            warnings[1]
+        );
+        assert!(warnings.contains(&EpubWarning::MissingFile("OEBPS/style.css".to_string())));
+        assert!(warnings.contains(&EpubWarning::HrefMismatch {
+            href: "OEBPS/0.xhtml".to_string(),
+            actual: "OEBPS/0.XHTML".to_string()
+        }));
+        assert!(warnings.iter().any(|f| matches!(
+            f,
+            EpubWarning::MalformedXhtml { file, .. } if file == "OEBPS/1.xhtml"
+        )));
+
+        assert!(book.get_assets("style.css").is_none());
+        assert_eq!(
+            Some(b"<p>0</p>".as_slice()),
+            book.get_chapter_mut("0.xhtml")
+                .unwrap()
+                .data_mut()
+                .map(|f| f.trim_ascii())
+        );
+        assert_eq!(
+            Some(b"<p>1</p>".as_slice()),
+            book.get_chapter_mut("1.xhtml").unwrap().data_mut()
+        );
+    }
+
+    #[test]
+    fn test_read_legacy_encoding() {
+        let data = EpubBuilder::default()
+            .append_title(false)
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("0.xhtml")
+                    .with_data(b"<p>0</p>".to_vec()),
+            )
+            .mem()
+            .unwrap();
+        // 把章节改为 gbk 编码
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut content).unwrap();
+            if file.name() == "OEBPS/0.xhtml" {
+                content = encoding_rs::GBK
+                    .encode(
+                        r#"<?xml version="1.0" encoding="gbk"?><html><head><title>第一章</title><meta http-equiv="Content-Type" content="text/html; charset=gbk"/></head><body><p>天下大势，分久必合</p></body></html>"#,
+                    )
+                    .0
+                    .to_vec();
+            }
+            out.start_file(file.name(), options).unwrap();
+            std::io::Write::write_all(&mut out, &content).unwrap();
+        }
+        let data = out.finish().unwrap().into_inner();
+
+        let mut book = read_from_vec(data).unwrap();
+        let chap = book.get_chapter_mut("0.xhtml").unwrap();
+        assert_eq!("<p>天下大势，分久必合</p>", chap.string_data());
+        assert_eq!(
+            Some(r#"<meta http-equiv="Content-Type" content="text/html; charset=utf-8"/>"#),
+            chap.head()
+        );
+
+        // 写入后是 utf-8
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut html = String::new();
+        std::io::Read::read_to_string(&mut zip.by_name("OEBPS/0.xhtml").unwrap(), &mut html)
+            .unwrap();
+        assert!(html.contains("<p>天下大势，分久必合</p>"));
+        assert!(!html.contains("gbk"));
+    }
+
+    #[test]
+    fn test_keep_layout() {
+        let mut book = EpubBuilder::default()
+            .append_title(false)
+            .with_title("书名")
+            .add_assets("Images/1.png", vec![1, 2, 3])
+            .add_assets("Styles/style.css", b"p{}".to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("Text/0.xhtml")
+                    .with_data(b"<p>0</p>".to_vec()),
+            )
+            .book()
+            .unwrap();
+        let mut v = std::io::Cursor::new(Vec::new());
+        EpubWriter::new(&mut v)
+            .with_append_title(false)
+            .with_options(EpubWriteOptions::default().with_opf_path("book/package.opf"))
+            .write(&mut book)
+            .unwrap();
+        let data = v.into_inner();
+
+        let names = |data: &[u8]| -> Vec<String> {
+            zip::ZipArchive::new(std::io::Cursor::new(data))
+                .unwrap()
+                .file_names()
+                .map(|f| f.to_string())
+                .collect()
+        };
+        let files = names(&data);
+        for ele in [
+            "book/package.opf",
+            "book/toc.ncx",
+            "book/Text/0.xhtml",
+            "book/Images/1.png",
+            "book/Styles/style.css",
+        ] {
+            assert!(files.iter().any(|f| f == ele), "{ele}");
+        }
+        assert!(!files.iter().any(|f| f.starts_with("OEBPS/")));
+
+        // 读取后再写入，目录结构保持不变
+        let mut book = read_from_vec(data).unwrap();
+        assert_eq!(Some("book/package.opf"), book.opf_path());
+        assert_eq!(
+            Some(b"<p>0</p>".as_slice()),
+            book.get_chapter_mut("Text/0.xhtml")
+                .unwrap()
+                .data_mut()
+                .map(|f| f.trim_ascii())
+        );
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let files = names(&data);
+        assert!(files.iter().any(|f| f == "book/package.opf"));
+        assert!(files.iter().any(|f| f == "book/Images/1.png"));
+        assert!(!files.iter().any(|f| f.starts_with("OEBPS/")));
+        assert!(read_from_vec(data).is_ok());
+    }
+
     #[test]
     fn test_get_img_src() {
         let html = r#"<div class="center" style="margin: 3.5em 0 0 0;">
@@ -1453,4 +3388,302 @@ html
         assert!(!book.assets().any(|f| f.id() == "toc" || f.id() == "ncx"));
         assert_eq!(10, book.nav().len());
     }
+
+    #[test]
+    fn test_read_obfuscated_font() {
+        use std::io::Read;
+
+        let font: Vec<u8> = (0..3000).map(|f| (f % 251) as u8).collect();
+        for obfuscation in [FontObfuscation::Idpf, FontObfuscation::Adobe] {
+            let data = EpubBuilder::default()
+                .with_title("书名")
+                .with_identifier("urn:uuid:12345678-9abc-def0-1234-56789abcdef0")
+                .with_write_options(EpubWriteOptions::default().with_font_obfuscation(obfuscation))
+                .add_assets("fonts/1.ttf", font.clone())
+                .add_assets("1.css", "p{color:red}".as_bytes().to_vec())
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_file_name("0.xhtml")
+                        .with_title("第一章")
+                        .with_data("<p>锻炼</p>".as_bytes().to_vec()),
+                )
+                .mem()
+                .unwrap();
+
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+            let mut raw = Vec::new();
+            zip.by_name("OEBPS/fonts/1.ttf")
+                .unwrap()
+                .read_to_end(&mut raw)
+                .unwrap();
+            assert_ne!(font, raw);
+            assert!(zip.by_name("META-INF/encryption.xml").is_ok());
+
+            let mut book = read_from_vec(data).unwrap();
+            let f = book.get_assets_mut("fonts/1.ttf").unwrap();
+            assert_eq!(Some(obfuscation), f.obfuscation());
+            assert_eq!(font, f.data_mut().unwrap());
+            assert_eq!(None, book.get_assets("1.css").unwrap().obfuscation());
+        }
+    }
+
+    #[test]
+    fn test_stream_assets() {
+        use std::io::Read;
+
+        let font: Vec<u8> = (0..3000).map(|f| (f % 251) as u8).collect();
+        let text = "p{color:red}".repeat(1000);
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_identifier("urn:uuid:12345678-9abc-def0-1234-56789abcdef0")
+            .with_write_options(
+                EpubWriteOptions::default()
+                    .with_compression(EpubCompression::Deflate(None))
+                    .with_font_obfuscation(FontObfuscation::Idpf),
+            )
+            .add_assets("fonts/1.ttf", font.clone())
+            .add_assets("1.css", text.as_bytes().to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>锻炼</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+
+        let file = std::env::temp_dir().join("iepub_test_stream_assets.epub");
+        std::fs::write(&file, &data).unwrap();
+        for mut book in [read_from_vec(data).unwrap(), read_from_file(&file).unwrap()] {
+            for (name, expect) in [("fonts/1.ttf", font.as_slice()), ("1.css", text.as_bytes())] {
+                let asset = book.get_assets(name).unwrap();
+                let mut v = Vec::new();
+                asset.reader().unwrap().read_to_end(&mut v).unwrap();
+                assert_eq!(expect, v.as_slice());
+                // 不会缓存到内存中
+                assert!(asset.data().is_none());
+            }
+            let mut html = String::new();
+            book.get_chapter("0.xhtml")
+                .unwrap()
+                .reader()
+                .unwrap()
+                .read_to_string(&mut html)
+                .unwrap();
+            assert!(html.contains("<p>锻炼</p>"));
+
+            // 写入时直接复制
+            let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+            assert!(book.assets().all(|f| f.data().is_none()));
+            let mut book = read_from_vec(data).unwrap();
+            assert_eq!(
+                font.as_slice(),
+                book.get_assets_mut("fonts/1.ttf")
+                    .unwrap()
+                    .data_mut()
+                    .unwrap()
+            );
+        }
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_stream_checksum() {
+        use std::io::Read;
+
+        let text = "p{color:red}".repeat(100);
+        let mut data = EpubBuilder::default()
+            .with_title("书名")
+            .with_write_options(
+                EpubWriteOptions::default().with_compression(EpubCompression::Stored),
+            )
+            .add_assets("1.css", text.as_bytes().to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_data("<p>0</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        // 损坏 1.css 中的数据
+        let start = data
+            .windows(text.len())
+            .position(|f| f == text.as_bytes())
+            .unwrap();
+        data[start + 10] = b'X';
+
+        let book = read_from_vec(data).unwrap();
+        let mut v = Vec::new();
+        let err = book
+            .get_assets("1.css")
+            .unwrap()
+            .reader()
+            .unwrap()
+            .read_to_end(&mut v)
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_read_from_dir() {
+        let font: Vec<u8> = (0..3000).map(|f| (f % 251) as u8).collect();
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_identifier("urn:uuid:12345678-9abc-def0-1234-56789abcdef0")
+            .with_write_options(
+                EpubWriteOptions::default().with_font_obfuscation(FontObfuscation::Idpf),
+            )
+            .add_assets("fonts/1.ttf", font.clone())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("text/0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>锻炼</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+
+        let dir = std::env::temp_dir().join("iepub_test_read_from_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut book = read_from_vec(data).unwrap();
+        EpubWriter::write_to_dir(&dir, &mut book, false).unwrap();
+        assert_eq!(
+            "application/epub+zip",
+            std::fs::read_to_string(dir.join("mimetype")).unwrap()
+        );
+        assert!(dir.join("OEBPS/text/0.xhtml").is_file());
+        assert!(dir.join("META-INF/encryption.xml").is_file());
+        // 版本控制的文件会被忽略
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+
+        let mut book = read_from_dir(&dir).unwrap();
+        assert_eq!("书名", book.title());
+        assert!(book.extra_files().all(|f| !f.file_name().contains(".git")));
+        // 写回同一个目录
+        EpubWriter::write_to_dir(&dir, &mut book, false).unwrap();
+
+        let mut book = read_from_dir(&dir).unwrap();
+        assert_eq!(
+            font.as_slice(),
+            book.get_assets_mut("fonts/1.ttf")
+                .unwrap()
+                .data_mut()
+                .unwrap()
+        );
+        assert!(book
+            .get_chapter_mut("text/0.xhtml")
+            .unwrap()
+            .string_data()
+            .contains("<p>锻炼</p>"));
+        assert!(read_from_dir(dir.join("mimetype")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_and_pack() {
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_version("3.0")
+            .cover("cover.jpg", vec![1, 2, 3])
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("text/0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>锻炼</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        let origin = crate::prelude::validate_vec(data.clone()).unwrap();
+
+        let dir = std::env::temp_dir().join("iepub_test_unpack_and_pack");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut book = read_from_vec(data).unwrap();
+        EpubWriter::write_to_dir(&dir, &mut book, false).unwrap();
+        assert!(dir.join("OEBPS/cover.jpg").is_file());
+        assert!(dir.join("OEBPS/cover.xhtml").is_file());
+
+        let mut book = read_from_dir(&dir).unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        // 重新打包后不会出现新的问题
+        assert_eq!(
+            origin
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>(),
+            crate::prelude::validate_vec(data.clone())
+                .unwrap()
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+        );
+        let mut book = read_from_vec(data).unwrap();
+        assert_eq!(
+            Some([1u8, 2, 3].as_slice()),
+            book.cover_mut().unwrap().data_mut()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn many_chapters() -> Vec<u8> {
+        let mut builder = EpubBuilder::default()
+            .with_title("书名")
+            .append_title(false);
+        for i in 0..32 {
+            builder = builder.add_chapter(
+                EpubHtml::default()
+                    .with_file_name(format!("{i}.xhtml"))
+                    .with_title(format!("第{i}章"))
+                    .with_data(format!("<p>{i}</p>").into_bytes()),
+            );
+        }
+        builder.mem().unwrap()
+    }
+
+    #[test]
+    fn test_read_from_shared() {
+        let data: std::sync::Arc<[u8]> = std::sync::Arc::from(many_chapters());
+        let mut book = read_from_shared(std::sync::Arc::clone(&data)).unwrap();
+        let mut other = read_from_shared(data).unwrap();
+        assert_eq!(
+            "<p>31</p>",
+            book.get_chapter_mut("31.xhtml")
+                .unwrap()
+                .string_data()
+                .trim()
+        );
+        assert_eq!(
+            "<p>0</p>",
+            other
+                .get_chapter_mut("0.xhtml")
+                .unwrap()
+                .string_data()
+                .trim()
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_chapters() {
+        use rayon::iter::ParallelIterator;
+
+        let data = many_chapters();
+        let file = std::env::temp_dir().join("iepub_test_par_chapters.epub");
+        std::fs::write(&file, &data).unwrap();
+        for mut book in [
+            read_from_vec(data.clone()).unwrap(),
+            read_from_file(&file).unwrap(),
+            read_from_reader(std::io::Cursor::new(data)).unwrap(),
+        ] {
+            let v: Vec<String> = book
+                .par_chapters_mut()
+                .map(|f| String::from_utf8(f.data().unwrap().to_vec()).unwrap())
+                .collect();
+            let mut serial = read_from_vec(many_chapters()).unwrap();
+            let expect: Vec<String> = serial.chapters_mut().map(|f| f.string_data()).collect();
+            assert_eq!(expect, v);
+            assert!(v.iter().any(|f| f.trim() == "<p>31</p>"));
+        }
+        std::fs::remove_file(file).unwrap();
+    }
 }
@@ -0,0 +1,1085 @@
+//! epub 结构检查
+//!
+//! 参考 epubcheck，检查以下内容：
+//!
+//! - mimetype 是否是第一个文件并且没有压缩
+//! - manifest 和 spine 是否一致，id 是否重复
+//! - 文件是否缺失或者未声明，media-type 是否和后缀一致
+//! - 内部链接以及锚点是否有效
+//! - nav 和 ncx 的目录是否一致
+//! - 必需的元数据，包括 identifier、title、language，epub3 还需要 dcterms:modified
+//! - xhtml 是否是格式正确的xml
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+
+use super::reader::{DirArchive, EpubArchive};
+use crate::prelude::*;
+
+///
+/// 问题等级
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// 不影响阅读，但是不符合规范或者可能有问题
+    Warning,
+    /// 违反规范
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("WARNING"),
+            Severity::Error => f.write_str("ERROR"),
+        }
+    }
+}
+
+///
+/// 检查发现的问题
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpubDiagnostic {
+    severity: Severity,
+    /// zip 中的完整路径
+    file: Option<String>,
+    /// 行号，从 1 开始
+    line: Option<usize>,
+    message: String,
+}
+
+impl EpubDiagnostic {
+    fn new<T: Into<String>>(
+        severity: Severity,
+        file: Option<&str>,
+        line: Option<usize>,
+        message: T,
+    ) -> Self {
+        EpubDiagnostic {
+            severity,
+            file: file.map(|f| f.to_string()),
+            line,
+            message: message.into(),
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for EpubDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.severity)?;
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "({file}:{line})")?,
+            (Some(file), None) => write!(f, "({file})")?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+///
+/// 检查epub文件
+///
+/// 只有文件不是zip时返回错误，其他问题都会记录在返回值中
+///
+pub fn validate_file<P: AsRef<Path>>(file: P) -> IResult<Vec<EpubDiagnostic>> {
+    validate_reader(std::fs::File::open(file)?)
+}
+
+///
+/// 检查内存中的epub
+///
+pub fn validate_vec(data: Vec<u8>) -> IResult<Vec<EpubDiagnostic>> {
+    validate_reader(std::io::Cursor::new(data))
+}
+
+///
+/// 检查任意reader中的epub
+///
+pub fn validate_reader<T: Read + Seek>(value: T) -> IResult<Vec<EpubDiagnostic>> {
+    validate_archive(EpubArchive::Zip(zip::ZipArchive::new(value)?))
+}
+
+///
+/// 检查解压后的目录
+///
+/// 目录没有文件顺序和压缩方式，不检查 mimetype 的位置和压缩
+///
+pub fn validate_dir<P: AsRef<Path>>(dir: P) -> IResult<Vec<EpubDiagnostic>> {
+    validate_archive(EpubArchive::<std::fs::File>::Dir(DirArchive::new(dir)?))
+}
+
+fn validate_archive<T: Read + Seek>(archive: EpubArchive<T>) -> IResult<Vec<EpubDiagnostic>> {
+    let mut v = Validator {
+        archive,
+        diagnostics: Vec::new(),
+    };
+    v.validate();
+    Ok(v.diagnostics)
+}
+
+/// manifest 中的文件
+struct Item {
+    id: String,
+    /// zip 中的完整路径
+    href: String,
+    media_type: String,
+    properties: String,
+    line: usize,
+}
+
+/// opf 解析结果
+#[derive(Default)]
+struct Package {
+    version: String,
+    unique_identifier: Option<String>,
+    items: Vec<Item>,
+    /// (idref, 行号)
+    spine: Vec<(String, usize)>,
+    spine_toc: Option<String>,
+    /// (id, 值)
+    identifiers: Vec<(Option<String>, String)>,
+    titles: usize,
+    languages: usize,
+    modified: bool,
+    /// 所有元素的 (id, 行号)
+    ids: Vec<(String, usize)>,
+}
+
+/// xhtml 和 ncx 的解析结果
+#[derive(Default)]
+struct Document {
+    ids: HashSet<String>,
+    /// (链接, 行号)
+    links: Vec<(String, usize)>,
+    /// nav 中 toc 的链接
+    toc: Vec<String>,
+}
+
+struct Validator<T: Read + Seek> {
+    archive: EpubArchive<T>,
+    diagnostics: Vec<EpubDiagnostic>,
+}
+
+impl<T: Read + Seek> Validator<T> {
+    fn error<S: Into<String>>(&mut self, file: Option<&str>, line: Option<usize>, message: S) {
+        self.diagnostics
+            .push(EpubDiagnostic::new(Severity::Error, file, line, message));
+    }
+
+    fn warning<S: Into<String>>(&mut self, file: Option<&str>, line: Option<usize>, message: S) {
+        self.diagnostics
+            .push(EpubDiagnostic::new(Severity::Warning, file, line, message));
+    }
+
+    fn read_string(&mut self, file: &str) -> Option<String> {
+        let mut f = self.archive.open(file).ok()?;
+        let mut content = String::new();
+        f.read_to_string(&mut content).ok()?;
+        Some(content)
+    }
+
+    /// 查找zip中的文件，链接可能经过了url编码
+    fn find_file(&self, file: &str) -> Option<String> {
+        if self.archive.contains(file) {
+            return Some(file.to_string());
+        }
+        crate::common::urldecode_enhanced(file)
+            .ok()
+            .filter(|f| self.archive.contains(f))
+    }
+
+    fn validate(&mut self) {
+        self.check_mimetype();
+        let Some(opf_path) = self.check_container() else {
+            return;
+        };
+        let Some(package) = self.check_opf(opf_path.as_str()) else {
+            return;
+        };
+        self.check_metadata(opf_path.as_str(), &package);
+        self.check_manifest(opf_path.as_str(), &package);
+        self.check_spine(opf_path.as_str(), &package);
+
+        // 解析所有的 xhtml 和 ncx
+        let mut docs: HashMap<String, Document> = HashMap::new();
+        for item in &package.items {
+            let is_ncx = item.media_type == "application/x-dtbncx+xml";
+            if item.media_type != "application/xhtml+xml" && !is_ncx {
+                continue;
+            }
+            if let Some(content) = self
+                .find_file(&item.href)
+                .and_then(|f| self.read_string(&f))
+            {
+                if let Some(doc) = self.parse_document(&item.href, content.as_str(), is_ncx) {
+                    docs.insert(item.href.clone(), doc);
+                }
+            }
+        }
+        self.check_links(&docs);
+        self.check_toc(&package, &docs);
+    }
+
+    fn check_mimetype(&mut self) {
+        if !self.archive.contains("mimetype") {
+            self.error(None, None, "缺少 mimetype");
+            return;
+        }
+        if let EpubArchive::Zip(zip) = &mut self.archive {
+            let first = zip
+                .by_index(0)
+                .ok()
+                .map(|f| (f.name().to_string(), f.compression()));
+            match first {
+                Some((name, compression)) if name == "mimetype" => {
+                    if compression != zip::CompressionMethod::Stored {
+                        self.error(Some("mimetype"), None, "mimetype 不能压缩");
+                    }
+                }
+                _ => self.error(Some("mimetype"), None, "mimetype 必须是第一个文件"),
+            }
+        }
+        if let Some(content) = self.read_string("mimetype") {
+            if content != "application/epub+zip" {
+                self.error(
+                    Some("mimetype"),
+                    None,
+                    format!(
+                        "mimetype 内容必须是 application/epub+zip，实际为 {:?}",
+                        content
+                    ),
+                );
+            }
+        }
+    }
+
+    /// 返回 opf 路径
+    fn check_container(&mut self) -> Option<String> {
+        let file = "META-INF/container.xml";
+        let Some(content) = self.read_string(file) else {
+            self.error(None, None, "缺少 META-INF/container.xml");
+            return None;
+        };
+        match super::reader::get_opf_location(content.as_str()) {
+            Ok(path) if !path.is_empty() => {
+                if self.archive.contains(&path) {
+                    Some(path)
+                } else {
+                    self.error(Some(file), None, format!("opf 文件 {} 不存在", path));
+                    None
+                }
+            }
+            Ok(_) => {
+                self.error(Some(file), None, "没有声明 opf 文件");
+                None
+            }
+            Err(e) => {
+                self.error(Some(file), None, format!("xml 格式错误: {}", e));
+                None
+            }
+        }
+    }
+
+    fn check_opf(&mut self, opf_path: &str) -> Option<Package> {
+        let content = self.read_string(opf_path)?;
+        let root = parent_dir(opf_path);
+        let mut package = Package::default();
+
+        let mut reader = quick_xml::reader::Reader::from_str(content.as_str());
+        let mut lines = LineCounter::new(content.as_str());
+        let mut parent: Vec<Vec<u8>> = Vec::new();
+        let mut text = String::new();
+        loop {
+            let line = lines.line(reader.buffer_position() as usize);
+            let event = reader.read_event();
+            let (e, is_empty) = match event {
+                Ok(Event::Eof) => break,
+                Err(e) => {
+                    let line = lines.line(reader.error_position() as usize);
+                    self.error(Some(opf_path), Some(line), format!("xml 格式错误: {}", e));
+                    return None;
+                }
+                Ok(Event::Start(e)) => (e, false),
+                Ok(Event::Empty(e)) => (e, true),
+                Ok(Event::Text(t)) => {
+                    text.push_str(&t.decode().unwrap_or_default());
+                    continue;
+                }
+                Ok(Event::End(_)) => {
+                    let name = parent.pop().unwrap_or_default();
+                    self.end_opf_element(&mut package, &parent, name.as_slice(), &text);
+                    text.clear();
+                    continue;
+                }
+                _ => continue,
+            };
+            text.clear();
+            if let Some(id) = attr(&e, "id") {
+                package.ids.push((id, line));
+            }
+            match e.local_name().as_ref() {
+                b"package" => {
+                    package.version = attr(&e, "version").unwrap_or_default();
+                    package.unique_identifier = attr(&e, "unique-identifier");
+                }
+                b"item" if parent.last().is_some_and(|f| f == b"manifest") => {
+                    let href = attr(&e, "href").unwrap_or_default();
+                    let id = attr(&e, "id").unwrap_or_default();
+                    let media_type = attr(&e, "media-type").unwrap_or_default();
+                    if href.is_empty() || id.is_empty() || media_type.is_empty() {
+                        self.error(
+                            Some(opf_path),
+                            Some(line),
+                            "item 缺少 id、href 或 media-type",
+                        );
+                    }
+                    package.items.push(Item {
+                        id,
+                        href: resolve(root, href.split('#').next().unwrap_or_default()),
+                        media_type,
+                        properties: attr(&e, "properties").unwrap_or_default(),
+                        line,
+                    });
+                }
+                b"itemref" => {
+                    package
+                        .spine
+                        .push((attr(&e, "idref").unwrap_or_default(), line));
+                }
+                b"spine" => {
+                    package.spine_toc = attr(&e, "toc");
+                }
+                b"meta" if attr(&e, "property").as_deref() == Some("dcterms:modified") => {
+                    package.modified = true;
+                }
+                b"identifier" => {
+                    package.identifiers.push((attr(&e, "id"), String::new()));
+                }
+                _ => {}
+            }
+            if is_empty {
+                self.end_opf_element(&mut package, &parent, e.local_name().as_ref(), "");
+            } else {
+                parent.push(e.local_name().as_ref().to_vec());
+            }
+        }
+        Some(package)
+    }
+
+    /// 元素结束，记录元数据的内容，name 和 parent 都是不带前缀的名称
+    fn end_opf_element(
+        &mut self,
+        package: &mut Package,
+        parent: &[Vec<u8>],
+        name: &[u8],
+        text: &str,
+    ) {
+        if parent.last().is_none_or(|f| f != b"metadata") {
+            return;
+        }
+        let text = text.trim();
+        match name {
+            b"identifier" => {
+                if let Some(last) = package.identifiers.last_mut() {
+                    last.1 = text.to_string();
+                }
+            }
+            b"title" if !text.is_empty() => package.titles += 1,
+            b"language" if !text.is_empty() => package.languages += 1,
+            _ => {}
+        }
+    }
+
+    fn check_metadata(&mut self, opf_path: &str, package: &Package) {
+        let file = Some(opf_path);
+        if package.identifiers.iter().all(|f| f.1.is_empty()) {
+            self.error(file, None, "缺少 dc:identifier");
+        } else if let Some(unique) = &package.unique_identifier {
+            if !package
+                .identifiers
+                .iter()
+                .any(|f| f.0.as_deref() == Some(unique.as_str()))
+            {
+                self.error(
+                    file,
+                    None,
+                    format!("unique-identifier {} 没有对应的 dc:identifier", unique),
+                );
+            }
+        } else {
+            self.error(file, None, "package 缺少 unique-identifier");
+        }
+        if package.titles == 0 {
+            self.error(file, None, "缺少 dc:title");
+        }
+        if package.languages == 0 {
+            self.error(file, None, "缺少 dc:language");
+        }
+        if package.version.starts_with('3') && !package.modified {
+            self.error(file, None, "epub3 缺少 dcterms:modified");
+        }
+    }
+
+    fn check_manifest(&mut self, opf_path: &str, package: &Package) {
+        let file = Some(opf_path);
+        let mut ids = HashSet::new();
+        for (id, line) in &package.ids {
+            if !ids.insert(id.as_str()) {
+                self.error(file, Some(*line), format!("id {} 重复", id));
+            }
+        }
+
+        let mut hrefs = HashSet::new();
+        for item in &package.items {
+            if item.href.is_empty() {
+                continue;
+            }
+            if !hrefs.insert(item.href.as_str()) {
+                self.error(
+                    file,
+                    Some(item.line),
+                    format!("文件 {} 重复声明", item.href),
+                );
+            }
+            if self.find_file(&item.href).is_none() {
+                self.error(file, Some(item.line), format!("文件 {} 不存在", item.href));
+            }
+            if let Some(expected) = expected_media_types(&item.href) {
+                if !expected.contains(&item.media_type.as_str()) {
+                    self.warning(
+                        file,
+                        Some(item.line),
+                        format!(
+                            "文件 {} 的 media-type {} 和后缀不一致，应该是 {}",
+                            item.href, item.media_type, expected[0]
+                        ),
+                    );
+                }
+            }
+        }
+
+        // 没有声明的文件
+        let declared: HashSet<String> = package
+            .items
+            .iter()
+            .filter_map(|f| self.find_file(&f.href))
+            .collect();
+        let undeclared: Vec<String> = self
+            .archive
+            .file_names()
+            .filter(|f| {
+                !f.ends_with('/')
+                    && *f != "mimetype"
+                    && *f != opf_path
+                    && !f.starts_with("META-INF/")
+                    && !declared.contains(*f)
+            })
+            .map(|f| f.to_string())
+            .collect();
+        for ele in undeclared {
+            self.warning(Some(ele.as_str()), None, "文件没有在 manifest 中声明");
+        }
+    }
+
+    fn check_spine(&mut self, opf_path: &str, package: &Package) {
+        let file = Some(opf_path);
+        if package.spine.is_empty() {
+            self.error(file, None, "spine 不能为空");
+        }
+        let mut refs = HashSet::new();
+        for (idref, line) in &package.spine {
+            if !refs.insert(idref.as_str()) {
+                self.error(file, Some(*line), format!("itemref {} 重复", idref));
+            }
+            match package.items.iter().find(|f| &f.id == idref) {
+                Some(item) => {
+                    if item.media_type != "application/xhtml+xml"
+                        && item.media_type != "image/svg+xml"
+                    {
+                        self.warning(
+                            file,
+                            Some(*line),
+                            format!("spine 中的 {} 不是 xhtml 或 svg", item.href),
+                        );
+                    }
+                }
+                None => {
+                    self.error(
+                        file,
+                        Some(*line),
+                        format!("itemref {} 在 manifest 中不存在", idref),
+                    );
+                }
+            }
+        }
+
+        if package.version.starts_with('3') {
+            if !package
+                .items
+                .iter()
+                .any(|f| f.properties.split_whitespace().any(|p| p == "nav"))
+            {
+                self.error(file, None, "epub3 缺少 nav 导航文件");
+            }
+        } else {
+            match &package.spine_toc {
+                Some(toc) => {
+                    if !package.items.iter().any(|f| &f.id == toc) {
+                        self.error(
+                            file,
+                            None,
+                            format!("spine 的 toc {} 在 manifest 中不存在", toc),
+                        );
+                    }
+                }
+                None => self.error(file, None, "epub2 的 spine 缺少 toc"),
+            }
+        }
+    }
+
+    ///
+    /// 解析 xhtml 或 ncx，记录 id 和链接
+    ///
+    fn parse_document(&mut self, file: &str, content: &str, is_ncx: bool) -> Option<Document> {
+        let mut doc = Document::default();
+        let mut reader = quick_xml::reader::Reader::from_str(content);
+        let mut lines = LineCounter::new(content);
+        // 当前所在的 nav 层级，用于判断是否在目录中
+        let mut depth = 0;
+        let mut toc_depth = None;
+        loop {
+            let line = lines.line(reader.buffer_position() as usize);
+            let (e, is_empty) = match reader.read_event() {
+                Ok(Event::Eof) => break,
+                Err(e) => {
+                    let line = lines.line(reader.error_position() as usize);
+                    self.error(Some(file), Some(line), format!("xml 格式错误: {}", e));
+                    return None;
+                }
+                Ok(Event::Start(e)) => (e, false),
+                Ok(Event::Empty(e)) => (e, true),
+                Ok(Event::End(_)) => {
+                    if toc_depth == Some(depth) {
+                        toc_depth = None;
+                    }
+                    depth -= 1;
+                    continue;
+                }
+                _ => continue,
+            };
+            if !is_empty {
+                depth += 1;
+            }
+            for a in e.attributes() {
+                if let Err(err) = a {
+                    self.error(Some(file), Some(line), format!("属性格式错误: {}", err));
+                }
+            }
+            if let Some(id) = attr(&e, "id") {
+                if !doc.ids.insert(id.clone()) {
+                    self.error(Some(file), Some(line), format!("id {} 重复", id));
+                }
+            }
+            let link = match e.local_name().as_ref() {
+                b"a" | b"area" | b"link" => attr(&e, "href"),
+                b"img" | b"script" | b"audio" | b"video" | b"source" | b"iframe" | b"embed" => {
+                    attr(&e, "src")
+                }
+                b"image" => attr(&e, "xlink:href").or_else(|| attr(&e, "href")),
+                b"content" if is_ncx => attr(&e, "src"),
+                b"nav" if !is_empty => {
+                    if attr(&e, "epub:type")
+                        .is_some_and(|f| f.split_whitespace().any(|t| t == "toc"))
+                    {
+                        toc_depth = Some(depth);
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(link) = link {
+                if (is_ncx || toc_depth.is_some()) && !is_external(&link) {
+                    doc.toc.push(resolve(parent_dir(file), link.as_str()));
+                }
+                doc.links.push((link, line));
+            }
+        }
+        Some(doc)
+    }
+
+    /// 检查内部链接和锚点
+    fn check_links(&mut self, docs: &HashMap<String, Document>) {
+        let mut files: Vec<&String> = docs.keys().collect();
+        files.sort();
+        for file in files {
+            let doc = &docs[file];
+            for (link, line) in &doc.links {
+                if is_external(link) {
+                    continue;
+                }
+                let (path, fragment) = match link.split_once('#') {
+                    Some((p, f)) => (p, Some(f)),
+                    None => (link.as_str(), None),
+                };
+                let target = if path.is_empty() {
+                    file.clone()
+                } else {
+                    resolve(parent_dir(file), path)
+                };
+                let Some(target) = self.find_file(&target) else {
+                    self.error(
+                        Some(file),
+                        Some(*line),
+                        format!("链接的文件 {} 不存在", link),
+                    );
+                    continue;
+                };
+                if let Some(fragment) = fragment.filter(|f| !f.is_empty()) {
+                    let fragment = crate::common::urldecode_enhanced(fragment)
+                        .unwrap_or_else(|_| fragment.to_string());
+                    if docs
+                        .get(&target)
+                        .is_some_and(|f| !f.ids.contains(fragment.as_str()))
+                    {
+                        self.error(
+                            Some(file),
+                            Some(*line),
+                            format!("链接的锚点 {} 不存在", link),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// 检查 nav 和 ncx 的目录是否一致
+    fn check_toc(&mut self, package: &Package, docs: &HashMap<String, Document>) {
+        let nav = package
+            .items
+            .iter()
+            .find(|f| f.properties.split_whitespace().any(|p| p == "nav"))
+            .and_then(|f| docs.get(&f.href).map(|d| (f.href.as_str(), d)));
+        let ncx = package
+            .items
+            .iter()
+            .find(|f| f.media_type == "application/x-dtbncx+xml")
+            .and_then(|f| docs.get(&f.href).map(|d| (f.href.as_str(), d)));
+        let (Some((nav_file, nav)), Some((ncx_file, ncx))) = (nav, ncx) else {
+            return;
+        };
+        let nav_set: HashSet<&String> = nav.toc.iter().collect();
+        let ncx_set: HashSet<&String> = ncx.toc.iter().collect();
+        for ele in &ncx.toc {
+            if !nav_set.contains(ele) {
+                self.warning(
+                    Some(ncx_file),
+                    None,
+                    format!("目录 {} 在 {} 中不存在", ele, nav_file),
+                );
+            }
+        }
+        for ele in &nav.toc {
+            if !ncx_set.contains(ele) {
+                self.warning(
+                    Some(nav_file),
+                    None,
+                    format!("目录 {} 在 {} 中不存在", ele, ncx_file),
+                );
+            }
+        }
+    }
+}
+
+fn attr(e: &BytesStart, key: &str) -> Option<String> {
+    e.try_get_attribute(key)
+        .ok()
+        .flatten()
+        .and_then(|f| f.unescape_value().ok().map(|v| v.to_string()))
+}
+
+///
+/// 计算行号
+///
+/// 解析时位置只会递增，从上一次的位置继续计算，避免每次都从头扫描
+///
+struct LineCounter<'a> {
+    content: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> LineCounter<'a> {
+    fn new(content: &'a str) -> Self {
+        LineCounter {
+            content: content.as_bytes(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn line(&mut self, pos: usize) -> usize {
+        let pos = pos.min(self.content.len());
+        if pos < self.pos {
+            self.pos = 0;
+            self.line = 1;
+        }
+        self.line += self.content[self.pos..pos]
+            .iter()
+            .filter(|f| **f == b'\n')
+            .count();
+        self.pos = pos;
+        self.line
+    }
+}
+
+/// 所在目录，带有末尾的 /
+fn parent_dir(file: &str) -> &str {
+    &file[..file.rfind('/').map_or(0, |f| f + 1)]
+}
+
+/// 计算相对路径对应的 zip 中的完整路径
+fn resolve(dir: &str, href: &str) -> String {
+    let mut paths: Vec<&str> = dir.split('/').filter(|f| !f.is_empty()).collect();
+    for ele in href.split('/') {
+        match ele {
+            ".." => {
+                paths.pop();
+            }
+            "." | "" => {}
+            v => paths.push(v),
+        }
+    }
+    paths.join("/")
+}
+
+/// 外部链接
+fn is_external(link: &str) -> bool {
+    link.contains("://")
+        || ["mailto:", "data:", "javascript:", "tel:"]
+            .iter()
+            .any(|f| link.starts_with(f))
+}
+
+/// 根据后缀获取允许的 media-type，第一个为推荐值
+fn expected_media_types(file: &str) -> Option<&'static [&'static str]> {
+    let ext = file.rsplit_once('.')?.1.to_lowercase();
+    Some(match ext.as_str() {
+        "xhtml" => &["application/xhtml+xml"],
+        "css" => &["text/css"],
+        "ncx" => &["application/x-dtbncx+xml"],
+        "jpg" | "jpeg" => &["image/jpeg"],
+        "png" => &["image/png"],
+        "gif" => &["image/gif"],
+        "svg" => &["image/svg+xml"],
+        "webp" => &["image/webp"],
+        "js" => &[
+            "application/javascript",
+            "text/javascript",
+            "application/ecmascript",
+        ],
+        "ttf" => &[
+            "font/ttf",
+            "application/font-sfnt",
+            "application/x-font-ttf",
+            "application/x-font-truetype",
+            "application/vnd.ms-opentype",
+        ],
+        "otf" => &[
+            "font/otf",
+            "application/font-sfnt",
+            "application/x-font-otf",
+            "application/x-font-opentype",
+            "application/vnd.ms-opentype",
+        ],
+        "woff" => &["font/woff", "application/font-woff"],
+        "woff2" => &["font/woff2"],
+        "mp3" => &["audio/mpeg"],
+        "smil" => &["application/smil+xml"],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> EpubBuilder {
+        EpubBuilder::default()
+            .with_version("3.0")
+            .with_title("书名")
+            .with_language("zh")
+            .with_identifier("urn:uuid:5b4d4e4c-1f3c-4c56-9b8e-6b1c5e3b1a2d")
+            .add_assets("style.css", b"p{}".to_vec())
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第一章")
+                    .with_file_name("0.xhtml")
+                    .with_data(
+                        r##"<p id="a">1</p><a href="1.xhtml#b">下一章</a>"##
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_title("第二章")
+                    .with_file_name("1.xhtml")
+                    .with_data(r##"<p id="b">2</p>"##.as_bytes().to_vec()),
+            )
+    }
+
+    #[test]
+    fn test_line_counter() {
+        let content = "a\nb\nc\nd";
+        let mut lines = LineCounter::new(content);
+        assert_eq!(1, lines.line(0));
+        assert_eq!(2, lines.line(2));
+        assert_eq!(4, lines.line(6));
+        assert_eq!(4, lines.line(100));
+        // 出错的位置可能在当前位置之前
+        assert_eq!(2, lines.line(3));
+    }
+
+    fn mem() -> Vec<u8> {
+        book().mem().unwrap()
+    }
+
+    /// 重新打包，修改其中的文件
+    fn repack(data: Vec<u8>, f: impl Fn(&str, Vec<u8>) -> Option<(String, Vec<u8>)>) -> Vec<u8> {
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            if let Some((name, content)) = f(file.name(), content) {
+                out.start_file(name, options).unwrap();
+                std::io::Write::write_all(&mut out, &content).unwrap();
+            }
+        }
+        out.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_validate_ok() {
+        let data = mem();
+        let result = validate_vec(data).unwrap();
+        assert!(
+            result.iter().all(|f| f.severity() != Severity::Error),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_validate_epub2_ok() {
+        let data = book().with_version("2.0").mem().unwrap();
+        let result = validate_vec(data).unwrap();
+        assert!(
+            result.iter().all(|f| f.severity() != Severity::Error),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_validate_no_language() {
+        let data = repack(mem(), |name, content| match name {
+            "OEBPS/content.opf" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace("<dc:language>zh</dc:language>", "")
+                    .into_bytes(),
+            )),
+            _ => Some((name.to_string(), content)),
+        });
+        let result = validate_vec(data).unwrap();
+        assert!(result.iter().any(|f| f.message() == "缺少 dc:language"));
+    }
+
+    #[test]
+    fn test_validate_prefix() {
+        // 元数据使用其他的命名空间前缀
+        let data = repack(mem(), |name, content| match name {
+            "OEBPS/content.opf" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace("xmlns:dc=", "xmlns:d=")
+                    .replace("<dc:", "<d:")
+                    .replace("</dc:", "</d:")
+                    .into_bytes(),
+            )),
+            _ => Some((name.to_string(), content)),
+        });
+        let result = validate_vec(data).unwrap();
+        assert!(
+            result.iter().all(|f| f.severity() != Severity::Error),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let data = repack(mem(), |name, content| match name {
+            "OEBPS/style.css" => None,
+            "OEBPS/1.xhtml" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace(r#"<p id="b">2</p>"#, r#"<p id="c">2</p><p id="c"><br></p>"#)
+                    .into_bytes(),
+            )),
+            "OEBPS/content.opf" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace(r#"media-type="text/css""#, r#"media-type="text/plain""#)
+                    .into_bytes(),
+            )),
+            _ => Some((name.to_string(), content)),
+        });
+        let data = repack(data, |name, content| {
+            if name == "mimetype" {
+                None
+            } else {
+                Some((name.to_string(), content))
+            }
+        });
+
+        let result = validate_vec(data).unwrap();
+        let has = |severity: Severity, file: &str, message: &str| {
+            result.iter().any(|f| {
+                f.severity() == severity && f.file() == Some(file) && f.message().contains(message)
+            })
+        };
+        assert!(result.iter().any(|f| f.message() == "缺少 mimetype"));
+        assert!(has(
+            Severity::Error,
+            "OEBPS/content.opf",
+            "OEBPS/style.css 不存在"
+        ));
+        assert!(has(
+            Severity::Warning,
+            "OEBPS/content.opf",
+            "media-type text/plain"
+        ));
+        assert!(has(Severity::Error, "OEBPS/1.xhtml", "xml 格式错误"));
+    }
+
+    #[test]
+    fn test_validate_links() {
+        let data = repack(mem(), |name, content| match name {
+            "OEBPS/0.xhtml" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace("1.xhtml#b", "1.xhtml#c")
+                    .replace(
+                        r#"<p id="a">1</p>"#,
+                        r#"<p id="a">1</p><img src="no.png"/>"#,
+                    )
+                    .into_bytes(),
+            )),
+            "OEBPS/content.opf" => Some((
+                name.to_string(),
+                String::from_utf8(content)
+                    .unwrap()
+                    .replace(
+                        r#"<meta property="dcterms:modified">"#,
+                        r#"<meta property="x">"#,
+                    )
+                    .into_bytes(),
+            )),
+            _ => Some((name.to_string(), content)),
+        });
+        let result = validate_vec(data).unwrap();
+        let errors: Vec<String> = result
+            .iter()
+            .filter(|f| f.severity() == Severity::Error)
+            .map(|f| f.to_string())
+            .collect();
+        assert!(
+            errors.iter().any(|f| f.contains("锚点 1.xhtml#c 不存在")),
+            "{:?}",
+            errors
+        );
+        assert!(
+            errors.iter().any(|f| f.contains("文件 no.png 不存在")),
+            "{:?}",
+            errors
+        );
+        assert!(
+            errors.iter().any(|f| f.contains("dcterms:modified")),
+            "{:?}",
+            errors
+        );
+        assert!(errors.iter().any(|f| f.starts_with("ERROR(OEBPS/0.xhtml:")));
+    }
+
+    #[test]
+    fn test_validate_dir() {
+        let data = repack(mem(), |name, content| match name {
+            "OEBPS/style.css" => None,
+            _ => Some((name.to_string(), content)),
+        });
+        let dir = std::env::temp_dir().join("iepub_test_validate_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        zip::ZipArchive::new(std::io::Cursor::new(data.clone()))
+            .unwrap()
+            .extract(&dir)
+            .unwrap();
+
+        // 解压后的检查结果和压缩包一致
+        let expect: Vec<String> = validate_vec(data)
+            .unwrap()
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        let result: Vec<String> = validate_dir(&dir)
+            .unwrap()
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(expect, result);
+        assert!(
+            result.iter().any(|f| f.contains("style.css")),
+            "{:?}",
+            result
+        );
+
+        std::fs::remove_file(dir.join("mimetype")).unwrap();
+        assert!(validate_dir(&dir)
+            .unwrap()
+            .iter()
+            .any(|f| f.message() == "缺少 mimetype"));
+        assert!(validate_dir(dir.join("META-INF")).is_ok());
+        assert!(validate_dir(dir.join("nothing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
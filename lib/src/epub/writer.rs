@@ -1,6 +1,6 @@
 use std::{
     fs::File,
-    io::{Seek, Write},
+    io::{Read, Seek, Write},
     path::Path,
 };
 
@@ -9,8 +9,9 @@ use zip::ZipWriter;
 use crate::prelude::*;
 
 use super::{
-    common,
-    html::{to_html, to_nav_html, to_opf, to_toc_xml},
+    common, convert, encryption,
+    html::{chapter_properties, get_landmarks, to_html, to_nav_html, to_opf, to_toc_xml},
+    overlay,
 };
 
 impl From<zip::result::ZipError> for IError {
@@ -36,13 +37,180 @@ pub(crate) trait EpubWriterTrait {
     /// data 要写入的数据
     ///
     fn write_file(&mut self, file: &str, data: &[u8]) -> IResult<()>;
+
+    ///
+    /// 流式写入，不需要把整个文件读取到内存中
+    ///
+    fn write_stream(&mut self, file: &str, data: &mut dyn Read) -> IResult<()>;
+}
+
+///
+/// 压缩方式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpubCompression {
+    /// 不压缩
+    Stored,
+    /// deflate 压缩，参数为压缩等级，None 则使用默认等级
+    ///
+    /// 0-9 为普通 deflate，更高的等级会使用 zopfli，速度会慢很多
+    Deflate(Option<i64>),
 }
+
+///
+/// 输出配置
+///
+/// - mimetype 始终不压缩
+/// - 图片、音视频、woff字体等本身已压缩的文件始终不压缩
+/// - 其他文件（xhtml、css、ncx、opf等）使用 [EpubCompression]
+///
+#[derive(Debug, Clone)]
+pub struct EpubWriteOptions {
+    /// 文本文件的压缩方式，默认不压缩
+    compression: EpubCompression,
+    /// 可重现模式，同一本书多次输出得到完全一致的文件
+    reproducible: bool,
+    /// 写入 dcterms:modified 的时间，会覆盖书本的最后修改时间
+    modified: Option<String>,
+    /// 混淆所有字体文件
+    font_obfuscation: Option<FontObfuscation>,
+    /// opf 文件的路径，会覆盖书本中记录的路径
+    opf_path: Option<String>,
+}
+
+impl Default for EpubWriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: EpubCompression::Stored,
+            reproducible: false,
+            modified: None,
+            font_obfuscation: None,
+            opf_path: None,
+        }
+    }
+}
+
+impl EpubWriteOptions {
+    /// 可重现模式下，没有指定修改时间时使用的默认值，和zip中的文件时间保持一致
+    pub(crate) const REPRODUCIBLE_MODIFIED: &str = "1980-01-01T00:00:00Z";
+
+    pub fn with_compression(mut self, compression: EpubCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn compression(&self) -> EpubCompression {
+        self.compression
+    }
+
+    ///
+    /// 设置可重现模式
+    ///
+    /// 开启后zip内文件时间固定为 1980-01-01 00:00:00，mimetype 固定为第一个文件，
+    /// 其余文件按书本内的顺序写入。
+    ///
+    /// 如果书本和配置中都没有最后修改时间，将使用 1980-01-01T00:00:00Z
+    ///
+    pub fn with_reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    pub fn reproducible(&self) -> bool {
+        self.reproducible
+    }
+
+    ///
+    /// 设置 dcterms:modified
+    ///
+    /// 例如 2024-06-28T03:07:07Z
+    ///
+    pub fn with_modified<T: Into<String>>(mut self, modified: T) -> Self {
+        self.modified = Some(modified.into());
+        self
+    }
+
+    pub fn modified(&self) -> Option<&str> {
+        self.modified.as_deref()
+    }
+
+    ///
+    /// 混淆所有字体文件
+    ///
+    /// 单个资源文件可以通过 [EpubAssets::with_obfuscation] 设置，优先级更高
+    ///
+    /// Adobe 算法要求书本标识为 urn:uuid 格式，否则将使用 IDPF 算法
+    ///
+    pub fn with_font_obfuscation(mut self, obfuscation: FontObfuscation) -> Self {
+        self.font_obfuscation = Some(obfuscation);
+        self
+    }
+
+    pub fn font_obfuscation(&self) -> Option<FontObfuscation> {
+        self.font_obfuscation
+    }
+
+    ///
+    /// 设置 opf 文件的路径，例如 OPS/package.opf
+    ///
+    /// 章节、资源、目录等文件都会写入到 opf 所在的目录下。
+    /// 没有设置时使用书本读取时的路径 [EpubBook::opf_path]，都没有则使用 OEBPS/content.opf
+    ///
+    pub fn with_opf_path<T: Into<String>>(mut self, opf_path: T) -> Self {
+        self.opf_path = Some(opf_path.into());
+        self
+    }
+
+    pub fn opf_path(&self) -> Option<&str> {
+        self.opf_path.as_deref()
+    }
+
+    /// 生成对应文件的zip配置
+    pub(crate) fn file_options(&self, file: &str) -> zip::write::SimpleFileOptions {
+        let mut options = zip::write::SimpleFileOptions::default().unix_permissions(0o755);
+        options = match self.compression {
+            EpubCompression::Deflate(level) if file != "mimetype" && !is_compressed(file) => {
+                options
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .compression_level(level)
+            }
+            _ => options.compression_method(zip::CompressionMethod::Stored),
+        };
+        if self.reproducible {
+            options = options.last_modified_time(zip::DateTime::default());
+        }
+        options
+    }
+}
+
+/// 是否是本身已经压缩过的文件，再次压缩收益很小
+fn is_compressed(file: &str) -> bool {
+    let media_type = crate::common::get_media_type(file);
+    (media_type.starts_with("image/") && media_type != "image/svg+xml")
+        || media_type.starts_with("audio/")
+        || media_type.starts_with("video/")
+        || media_type.contains("woff")
+}
+
+///
+/// 输出目标
+///
+pub(crate) enum EpubOutput<T: Write + Seek> {
+    /// 打包为zip
+    Zip(Box<ZipWriter<T>>),
+    /// 解压后的目录
+    Dir(std::path::PathBuf),
+}
+
 ///
 /// 写入到文件
 ///
 pub struct EpubWriter<T: Write + Seek> {
-    pub(crate) inner: zip::ZipWriter<T>,
+    pub(crate) inner: EpubOutput<T>,
     pub(crate) append_title: bool,
+    pub(crate) options: EpubWriteOptions,
+    /// 已写入的文件
+    pub(crate) files: std::collections::HashSet<String>,
 }
 static CONTAINER_XML: &str = r#"<?xml version='1.0' encoding='utf-8'?>
 <container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
@@ -70,6 +238,31 @@ impl EpubWriter<File> {
             )
             .and_then(|mut w| w.write(book))
     }
+
+    ///
+    /// 写入到目录，目录结构与epub文件相同
+    ///
+    /// 不存在的目录会被创建，已存在的同名文件会被覆盖，但不会删除目录中其他的文件
+    ///
+    pub fn new_dir<P: AsRef<Path>>(dir: P) -> Self {
+        EpubWriter {
+            inner: EpubOutput::Dir(dir.as_ref().to_path_buf()),
+            append_title: true,
+            options: EpubWriteOptions::default(),
+            files: std::collections::HashSet::new(),
+        }
+    }
+
+    /// 解压写入目录
+    pub fn write_to_dir<P: AsRef<Path>>(
+        dir: P,
+        book: &mut EpubBook,
+        append_title: bool,
+    ) -> IResult<()> {
+        EpubWriter::new_dir(dir)
+            .with_append_title(append_title)
+            .write(book)
+    }
 }
 
 impl EpubWriter<std::io::Cursor<Vec<u8>>> {
@@ -87,8 +280,10 @@ impl EpubWriter<std::io::Cursor<Vec<u8>>> {
 impl<T: Write + Seek> EpubWriter<T> {
     pub fn new(inner: T) -> Self {
         EpubWriter {
-            inner: ZipWriter::new(inner),
+            inner: EpubOutput::Zip(Box::new(ZipWriter::new(inner))),
             append_title: true,
+            options: EpubWriteOptions::default(),
+            files: std::collections::HashSet::new(),
         }
     }
 
@@ -97,30 +292,117 @@ impl<T: Write + Seek> EpubWriter<T> {
         self
     }
 
+    /// 设置输出配置
+    pub fn with_options(mut self, options: EpubWriteOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    ///
+    /// 写入电子书
+    ///
+    /// 读取到的导航页、封面页以及生成的元数据会被移除，写入时重新生成
+    ///
     pub fn write(&mut self, book: &mut EpubBook) -> IResult<()> {
+        convert::remove_generated(book);
+        self.set_modified(book);
+        // 规范要求 mimetype 必须是第一个文件
+        self.write_file("mimetype", "application/epub+zip".as_bytes())?;
         self.write_cover(book)?;
 
         self.write_base(book)?;
         self.write_assets(book)?;
         self.write_chapters(book)?;
         self.write_nav(book)?;
+        self.write_extra_files(book)?;
 
         Ok(())
     }
 
+    ///
+    /// 开始流式写入，适用于章节很多的书
+    ///
+    /// [book] 中已有的章节和资源会立即写入，其他内容在 [EpubStreamWriter::finish] 时写入
+    ///
+    pub fn stream(mut self, mut book: EpubBook) -> IResult<EpubStreamWriter<T>> {
+        // 规范要求 mimetype 必须是第一个文件
+        self.write_file("mimetype", "application/epub+zip".as_bytes())?;
+        convert::remove_generated(&mut book);
+        // 先取出已有的章节和资源，写入时需要用到 book 的标识符、方向等
+        let chapters = std::mem::take(&mut book.chapters);
+        let assets = std::mem::take(&mut book.assets);
+        let mut writer = EpubStreamWriter {
+            root: self.root(&book),
+            writer: self,
+            book,
+            encrypted: Vec::new(),
+        };
+        for ele in chapters {
+            writer.add_chapter(ele)?;
+        }
+        for ele in assets {
+            writer.add_assets(ele)?;
+        }
+        Ok(writer)
+    }
+
+    /// 设置最后修改时间
+    pub(crate) fn set_modified(&self, book: &mut EpubBook) {
+        if let Some(modified) = self.options.modified() {
+            book.set_last_modify(modified);
+        } else if self.options.reproducible() && book.last_modify().is_none() {
+            book.set_last_modify(EpubWriteOptions::REPRODUCIBLE_MODIFIED);
+        }
+    }
+
+    ///
+    /// 原样写入其他文件
+    ///
+    /// 和已生成文件重名的将被跳过
+    ///
+    fn write_extra_files(&mut self, book: &mut EpubBook) -> IResult<()> {
+        for ele in book.extra_files_mut() {
+            if self.files.contains(ele.file_name()) {
+                continue;
+            }
+            let file = ele.file_name().to_string();
+            if let Ok(mut reader) = ele.reader() {
+                self.write_stream(file.as_str(), &mut reader)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// opf 文件在 epub 中的路径
+    pub(crate) fn opf_path(&self, book: &EpubBook) -> String {
+        self.options
+            .opf_path()
+            .or(book.opf_path())
+            .map(|f| f.trim_start_matches('/'))
+            .filter(|f| !f.is_empty())
+            .unwrap_or(common::OPF)
+            .to_string()
+    }
+
+    /// opf 所在的目录，其他文件都相对于该目录写入
+    pub(crate) fn root(&self, book: &EpubBook) -> String {
+        let opf = self.opf_path(book);
+        opf[..opf.rfind('/').map_or(0, |f| f + 1)].to_string()
+    }
+
     /// 写入基础的文件
     fn write_base(&mut self, book: &mut EpubBook) -> IResult<()> {
         if book.version().is_empty() {
             book.set_version("2.0");
         }
+        let opf = self.opf_path(book);
         self.write_file(
             "META-INF/container.xml",
-            CONTAINER_XML.replace("{opf}", common::OPF).as_bytes(),
+            CONTAINER_XML.replace("{opf}", opf.as_str()).as_bytes(),
         )?;
-        self.write_file("mimetype", "application/epub+zip".as_bytes())?;
 
         self.write_file(
-            common::OPF,
+            opf.as_str(),
             to_opf(book, crate::common::info::PKG_NAME).as_bytes(),
         )?;
 
@@ -129,14 +411,63 @@ impl<T: Write + Seek> EpubWriter<T> {
 
     /// 写入资源文件
     fn write_assets(&mut self, book: &mut EpubBook) -> IResult<()> {
+        let identifier = book.identifier().to_string();
+        let root = self.root(book);
+        let mut encrypted = Vec::new();
         let m = book.assets_mut();
         for ele in m {
-            if ele.data_mut().is_none() {
-                continue;
+            if let Some(v) = self.write_asset(root.as_str(), identifier.as_str(), ele)? {
+                encrypted.push(v);
             }
+        }
+        self.write_encryption(&encrypted)
+    }
+
+    ///
+    /// 写入单个资源文件
+    ///
+    /// 返回混淆后的文件路径以及算法
+    ///
+    fn write_asset(
+        &mut self,
+        root: &str,
+        identifier: &str,
+        ele: &mut EpubAssets,
+    ) -> IResult<Option<(String, FontObfuscation)>> {
+        // 没有读取到内存中的文件直接从原epub复制，不再缓存
+        let Ok(mut reader) = ele.reader() else {
+            return Ok(None);
+        };
+        let file = format!("{}{}", root, ele.file_name());
+        let obfuscation = ele.obfuscation().or_else(|| {
+            self.options
+                .font_obfuscation()
+                .filter(|_| encryption::is_font(ele.file_name(), ele.media_type.as_str()))
+        });
+        if let Some(obfuscation) = obfuscation {
+            // Adobe 算法需要 uuid，没有的话退化为 IDPF 算法
+            let (obfuscation, key) = match obfuscation.key(identifier) {
+                Some(key) => (obfuscation, key),
+                None => (
+                    FontObfuscation::Idpf,
+                    FontObfuscation::Idpf.key(identifier).unwrap_or_default(),
+                ),
+            };
+            let mut reader = encryption::ObfuscationReader::new(reader, obfuscation, key);
+            self.write_stream(file.as_str(), &mut reader)?;
+            Ok(Some((file, obfuscation)))
+        } else {
+            self.write_stream(file.as_str(), &mut reader)?;
+            Ok(None)
+        }
+    }
+
+    /// 写入 encryption.xml
+    fn write_encryption(&mut self, encrypted: &[(String, FontObfuscation)]) -> IResult<()> {
+        if !encrypted.is_empty() {
             self.write_file(
-                format!("{}{}", common::EPUB, ele.file_name()).as_str(),
-                ele.data_mut().unwrap(),
+                encryption::ENCRYPTION,
+                encryption::to_encryption_xml(encrypted).as_bytes(),
             )?;
         }
         Ok(())
@@ -145,34 +476,55 @@ impl<T: Write + Seek> EpubWriter<T> {
     /// 写入章节文件
     fn write_chapters(&mut self, book: &mut EpubBook) -> IResult<()> {
         let dir = book.direction.as_ref().cloned();
+        let epub2 = book.is_strict_epub2();
+        let root = self.root(book);
         let chap = book.chapters_mut();
         for ele in chap {
-            if ele.data_mut().is_none() {
-                continue;
-            }
+            self.write_chapter(root.as_str(), ele, &dir, epub2)?;
+        }
 
-            let html = to_html(ele, self.append_title, &dir);
+        Ok(())
+    }
 
+    /// 写入单个章节以及对应的媒体覆盖
+    pub(crate) fn write_chapter(
+        &mut self,
+        root: &str,
+        ele: &mut EpubHtml,
+        dir: &Option<Direction>,
+        epub2: bool,
+    ) -> IResult<()> {
+        if let Some(overlay) = ele.media_overlay().filter(|_| !epub2) {
+            let smil = overlay::to_smil(overlay, ele.file_name())?;
             self.write_file(
-                format!("{}{}", common::EPUB, ele.file_name()).as_str(),
-                html.as_bytes(),
+                format!("{}{}", root, overlay.resolve_file_name(ele.file_name())).as_str(),
+                smil.as_bytes(),
             )?;
         }
+        if ele.data_mut().is_none() {
+            return Ok(());
+        }
 
-        Ok(())
+        let html = to_html(ele, self.append_title, dir, epub2);
+
+        self.write_file(
+            format!("{}{}", root, ele.file_name()).as_str(),
+            html.as_bytes(),
+        )
     }
     /// 写入目录
-    fn write_nav(&mut self, book: &mut EpubBook) -> IResult<()> {
+    pub(crate) fn write_nav(&mut self, book: &mut EpubBook) -> IResult<()> {
         // 目录包括两部分，一是自定义的用于书本导航的html，二是epub规范里的toc.ncx文件
-        if book
-            .chapters()
-            .all(|f| f.file_name() != common::NAV.replace(common::EPUB, ""))
-        {
+        // 严格的 epub2 只有 toc.ncx
+        let root = self.root(book);
+        if !book.is_strict_epub2() {
             self.write_file(
-                common::NAV,
+                format!("{}{}", root, common::NAV.replace(common::EPUB, "")).as_str(),
                 to_nav_html(
                     book.title(),
                     book.nav(),
+                    &get_landmarks(book),
+                    book.page_list(),
                     book.language().unwrap_or(""),
                     &book.direction,
                 )
@@ -180,7 +532,10 @@ impl<T: Write + Seek> EpubWriter<T> {
             )?;
         }
 
-        self.write_file(common::TOC, to_toc_xml(book.title(), book.nav()).as_bytes())?;
+        self.write_file(
+            format!("{}{}", root, common::TOC.replace(common::EPUB, "")).as_str(),
+            to_toc_xml(book.title(), book.nav(), book.page_list()).as_bytes(),
+        )?;
 
         Ok(())
     }
@@ -190,10 +545,12 @@ impl<T: Write + Seek> EpubWriter<T> {
     ///
     /// 拷贝资源文件以及生成对应的xhtml文件
     ///
-    fn write_cover(&mut self, book: &mut EpubBook) -> IResult<()> {
+    pub(crate) fn write_cover(&mut self, book: &mut EpubBook) -> IResult<()> {
         let has_cover_xhtml = book
             .get_chapter(common::COVER.replace(common::EPUB, ""))
             .is_some();
+        let epub2 = book.is_strict_epub2();
+        let root = self.root(book);
         book.cover_chapter = if let Some(cover) = book.cover_mut() {
             // 生成 cover 页
             let src = cover.file_name();
@@ -208,8 +565,8 @@ impl<T: Write + Seek> EpubWriter<T> {
                 );
             if !has_cover_xhtml {
                 self.write_file(
-                    common::COVER,
-                    to_html(&mut c, false, &book.direction).as_bytes(),
+                    format!("{}{}", root, common::COVER.replace(common::EPUB, "")).as_str(),
+                    to_html(&mut c, false, &book.direction, epub2).as_bytes(),
                 )?;
             }
             Some(c)
@@ -221,10 +578,11 @@ impl<T: Write + Seek> EpubWriter<T> {
             .cover()
             .filter(|f| book.get_assets(f.file_name()).is_none())
         {
-            if let Some(data) = cover.data() {
-                self.write_file(
-                    format!("{}{}", common::EPUB, cover.file_name()).as_str(),
-                    data,
+            // 读取到的封面可能还没有加载到内存中
+            if let Ok(mut reader) = cover.reader() {
+                self.write_stream(
+                    format!("{}{}", root, cover.file_name()).as_str(),
+                    &mut reader,
                 )?;
             }
         }
@@ -232,13 +590,368 @@ impl<T: Write + Seek> EpubWriter<T> {
     }
 }
 
+///
+/// 流式写入
+///
+/// 章节和资源添加后立即写入并释放内容，内存中只保留标题、文件名等索引信息，
+/// 结束时再根据这些信息写入 opf、toc.ncx 和 nav.xhtml，内存占用不随章节数量增长
+///
+/// ```no_run
+/// use iepub::prelude::*;
+///
+/// let mut book = EpubBook::default();
+/// book.set_title("书名");
+/// let mut writer = EpubWriter::new(std::fs::File::create("book.epub").unwrap())
+///     .stream(book)
+///     .unwrap();
+/// for i in 0..5000 {
+///     writer
+///         .add_chapter(
+///             EpubHtml::default()
+///                 .with_title(format!("第{i}章"))
+///                 .with_file_name(format!("{i}.xhtml"))
+///                 .with_data(format!("<p>{i}</p>").into_bytes()),
+///         )
+///         .unwrap();
+/// }
+/// writer.finish().unwrap();
+/// ```
+///
+pub struct EpubStreamWriter<T: Write + Seek> {
+    writer: EpubWriter<T>,
+    /// 元数据以及已写入文件的索引
+    book: EpubBook,
+    /// opf 所在的目录
+    root: String,
+    /// 被混淆的字体
+    encrypted: Vec<(String, FontObfuscation)>,
+}
+
+impl<T: Write + Seek> EpubStreamWriter<T> {
+    /// 写入章节并释放内容
+    fn write_chapter(&mut self, ele: &mut EpubHtml) -> IResult<()> {
+        let dir = self.book.direction.clone();
+        let epub2 = self.book.is_strict_epub2();
+        self.writer
+            .write_chapter(self.root.as_str(), ele, &dir, epub2)?;
+        ele.manifest_properties = Some(chapter_properties(ele));
+        ele.release_data();
+        ele.release_raw_data();
+        Ok(())
+    }
+
+    /// 写入资源并释放内容
+    fn write_asset(&mut self, ele: &mut EpubAssets) -> IResult<()> {
+        if let Some(v) = self
+            .writer
+            .write_asset(self.root.as_str(), self.book.identifier(), ele)?
+        {
+            self.encrypted.push(v);
+        }
+        ele.release_data();
+        Ok(())
+    }
+
+    /// 添加章节，立即写入
+    pub fn add_chapter(&mut self, mut chap: EpubHtml) -> IResult<()> {
+        self.write_chapter(&mut chap)?;
+        self.book.add_chapter(chap);
+        Ok(())
+    }
+
+    /// 添加资源文件，立即写入
+    pub fn add_assets(&mut self, mut assets: EpubAssets) -> IResult<()> {
+        self.write_asset(&mut assets)?;
+        self.book.add_assets(assets);
+        Ok(())
+    }
+
+    pub fn book(&self) -> &EpubBook {
+        &self.book
+    }
+
+    ///
+    /// 修改元数据、目录等，结束前修改都会生效
+    ///
+    /// 已经写入的章节和资源修改内容无效
+    ///
+    /// 混淆字体使用标识符作为密钥，写入字体后再修改标识符会导致字体无法解混淆
+    ///
+    pub fn book_mut(&mut self) -> &mut EpubBook {
+        &mut self.book
+    }
+
+    ///
+    /// 结束写入
+    ///
+    /// 没有目录时按照章节标题生成
+    ///
+    pub fn finish(mut self) -> IResult<()> {
+        let book = &mut self.book;
+        if book.nav().next().is_none() {
+            let nav: Vec<EpubNav> = book
+                .chapters()
+                .enumerate()
+                .map(|(index, ele)| {
+                    EpubNav::default()
+                        .with_title(format!("{}. {}", index + 1, ele.title()))
+                        .with_file_name(ele.file_name())
+                })
+                .collect();
+            for ele in nav {
+                book.add_nav(ele);
+            }
+        }
+        self.writer.set_modified(book);
+        self.writer.write_cover(book)?;
+        self.writer.write_base(book)?;
+        self.writer.write_nav(book)?;
+        self.writer.write_encryption(&self.encrypted)?;
+        self.writer.write_extra_files(book)?;
+        if let EpubOutput::Zip(zip) = self.writer.inner {
+            zip.finish()?;
+        }
+        Ok(())
+    }
+}
+
 impl<T: Write + Seek> EpubWriterTrait for EpubWriter<T> {
     fn write_file(&mut self, file: &str, data: &[u8]) -> IResult<()> {
-        let options = zip::write::SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Stored)
-            .unix_permissions(0o755);
-        self.inner.start_file(file, options)?;
-        self.inner.write_all(data)?;
+        self.write_stream(file, &mut std::io::Cursor::new(data))
+    }
+
+    fn write_stream(&mut self, file: &str, data: &mut dyn Read) -> IResult<()> {
+        match &mut self.inner {
+            EpubOutput::Zip(zip) => {
+                zip.start_file(file, self.options.file_options(file))?;
+                std::io::copy(data, zip)?;
+            }
+            EpubOutput::Dir(dir) => write_to_dir_file(dir, file, data)?,
+        }
+        self.files.insert(file.to_string());
         Ok(())
     }
 }
+
+///
+/// 写入目录中的文件
+///
+/// 先写入临时文件再替换，因为数据可能正是从同一个文件中读取的
+///
+fn write_to_dir_file(dir: &Path, file: &str, data: &mut dyn Read) -> IResult<()> {
+    // 和读取时一样，只接受相对路径
+    if file
+        .split('/')
+        .any(|f| f.is_empty() || f == "." || f == "..")
+    {
+        return Err(IError::InvalidArchive(std::borrow::Cow::from(format!(
+            "invalid file name {file}"
+        ))));
+    }
+    let path = dir.join(file);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+    let result = File::create(&temp)
+        .and_then(|f| {
+            let mut out = std::io::BufWriter::new(f);
+            std::io::copy(data, &mut out)?;
+            out.flush()
+        })
+        .and_then(|_| std::fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn test_rewrite() {
+        use std::io::Read;
+
+        let read_opf = |data: &[u8]| {
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+            let mut opf = String::new();
+            zip.by_name("OEBPS/content.opf")
+                .unwrap()
+                .read_to_string(&mut opf)
+                .unwrap();
+            opf
+        };
+        let data = EpubBuilder::default()
+            .with_title("书名")
+            .with_version("3.0")
+            .with_language("zh")
+            .cover("cover.jpg", vec![1, 2, 3])
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>0</p>".as_bytes().to_vec()),
+            )
+            .mem()
+            .unwrap();
+        let origin = read_opf(&data);
+
+        // 多次读取写入后，导航页、封面页和生成的元数据不会重复
+        let mut data = data;
+        for _ in 0..2 {
+            let mut book = read_from_vec(data).unwrap();
+            data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+            let opf = read_opf(&data);
+            for ele in [
+                r#"<item href="nav.xhtml""#,
+                r#"<item href="cover.xhtml""#,
+                r#"<item href="cover.jpg""#,
+                r#"name="generator""#,
+                r#"name="cover""#,
+                "dcterms:modified",
+                "<dc:language>zh</dc:language>",
+            ] {
+                assert_eq!(1, opf.matches(ele).count(), "{ele} {opf}");
+            }
+            assert_eq!(
+                origin.matches("<itemref").count(),
+                opf.matches("<itemref").count()
+            );
+        }
+        let mut book = read_from_vec(data).unwrap();
+        assert_eq!(
+            Some([1u8, 2, 3].as_slice()),
+            book.cover_mut().unwrap().data_mut()
+        );
+    }
+
+    #[test]
+    fn test_stream_writer() {
+        use std::io::Read;
+
+        let mut book = EpubBook::default();
+        book.set_title("书名");
+        book.set_version("3.0");
+        book.add_assets(
+            EpubAssets::default()
+                .with_file_name("style.css")
+                .with_data(b"p{}".to_vec()),
+        );
+        let mut v = std::io::Cursor::new(Vec::new());
+        let mut writer = EpubWriter::new(&mut v)
+            .with_append_title(false)
+            .stream(book)
+            .unwrap();
+        for i in 0..3 {
+            writer
+                .add_chapter(
+                    EpubHtml::default()
+                        .with_title(format!("第{i}章"))
+                        .with_file_name(format!("text/{i}.xhtml"))
+                        .with_data(format!("<p>{i}</p><svg></svg>").into_bytes()),
+                )
+                .unwrap();
+        }
+        writer
+            .add_assets(
+                EpubAssets::default()
+                    .with_file_name("images/1.png")
+                    .with_data(vec![1, 2, 3]),
+            )
+            .unwrap();
+        // 写入后内容已经释放
+        assert!(writer.book().chapters().all(|f| f.data().is_none()));
+        assert!(writer.book().assets().all(|f| f.data().is_none()));
+        writer.finish().unwrap();
+
+        let data = v.into_inner();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+        assert_eq!("mimetype", zip.by_index(0).unwrap().name());
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains(
+            r#"<item href="text/2.xhtml" id="chap_2" media-type="application/xhtml+xml" properties="svg"/>"#
+        ));
+        assert!(opf.contains(r#"href="style.css""#));
+
+        let mut book = read_from_vec(data).unwrap();
+        assert_eq!("书名", book.title());
+        // 多出来的一个是导航 nav.xhtml
+        assert_eq!(4, book.chapters().count());
+        assert_eq!("1. 第0章", book.nav().next().unwrap().title());
+        assert!(String::from_utf8(
+            book.get_chapter_mut("text/1.xhtml")
+                .unwrap()
+                .data_mut()
+                .unwrap()
+                .to_vec()
+        )
+        .unwrap()
+        .contains("<p>1</p>"));
+        assert_eq!(
+            Some([1, 2, 3].as_slice()),
+            book.get_assets_mut("images/1.png").unwrap().data_mut()
+        );
+    }
+
+    #[test]
+    fn test_stream_writer_font() {
+        use std::io::Read;
+
+        let font: Vec<u8> = (0..3000).map(|f| (f % 251) as u8).collect();
+        let mut book = EpubBook::default();
+        book.set_title("书名");
+        book.set_identifier("urn:uuid:12345678-9abc-def0-1234-56789abcdef0");
+        book.set_direction(Direction::RTL);
+        book.add_assets(
+            EpubAssets::default()
+                .with_file_name("fonts/1.ttf")
+                .with_data(font.clone()),
+        );
+        book.add_chapter(
+            EpubHtml::default()
+                .with_title("第一章")
+                .with_file_name("0.xhtml")
+                .with_data(b"<p>1</p>".to_vec()),
+        );
+        let mut v = std::io::Cursor::new(Vec::new());
+        let mut writer = EpubWriter::new(&mut v)
+            .with_options(EpubWriteOptions::default().with_font_obfuscation(FontObfuscation::Idpf))
+            .stream(book)
+            .unwrap();
+        writer
+            .add_assets(
+                EpubAssets::default()
+                    .with_file_name("fonts/2.ttf")
+                    .with_data(font.clone()),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let data = v.into_inner();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data.clone())).unwrap();
+        let mut html = String::new();
+        zip.by_name("OEBPS/0.xhtml")
+            .unwrap()
+            .read_to_string(&mut html)
+            .unwrap();
+        assert!(html.contains(r#"dir="rtl""#));
+
+        let mut book = read_from_vec(data).unwrap();
+        // 传入 stream 的字体和之后添加的字体使用相同的密钥
+        for name in ["fonts/1.ttf", "fonts/2.ttf"] {
+            let f = book.get_assets_mut(name).unwrap();
+            assert_eq!(Some(FontObfuscation::Idpf), f.obfuscation());
+            assert_eq!(font, f.data_mut().unwrap());
+        }
+    }
+}
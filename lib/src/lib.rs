@@ -9,6 +9,9 @@ mod epub;
 mod mobi;
 pub mod parser;
 pub mod path;
+mod search;
+mod stats;
+mod txt;
 pub use crate::common::ContentItem;
 pub use crate::common::ContentType;
 pub use crate::common::DateTimeFormater;
@@ -17,6 +20,7 @@ pub mod prelude {
     pub use crate::common::IError;
     pub use crate::common::IResult;
 
+    pub use crate::common::decode_text;
     pub use crate::common::escape_xml;
 
     pub use crate::epub::builder::EpubBuilder;
@@ -25,15 +29,87 @@ pub mod prelude {
     pub use crate::epub::core::EpubAssets;
     pub use crate::epub::core::EpubBook;
     pub use crate::epub::core::EpubHtml;
+    pub use crate::epub::core::EpubIdentifier;
+    pub use crate::epub::core::EpubLandmark;
     pub use crate::epub::core::EpubLink;
     pub use crate::epub::core::EpubMetaData;
     pub use crate::epub::core::EpubNav;
+    pub use crate::epub::core::EpubPage;
+    pub use crate::epub::core::EpubPerson;
+    pub use crate::epub::core::EpubTitle;
+    pub use crate::epub::core::PageSpread;
+    pub use crate::epub::core::RenditionLayout;
+    pub use crate::epub::core::RenditionOrientation;
+    pub use crate::epub::core::RenditionSpread;
+    pub use crate::epub::core::TitleType;
+    pub use crate::epub::encryption::FontObfuscation;
+    pub use crate::epub::overlay::EpubClip;
+    pub use crate::epub::overlay::EpubMediaOverlay;
+    pub use crate::epub::reader::read_from_dir;
+    pub use crate::epub::reader::read_from_dir_lenient;
     pub use crate::epub::reader::read_from_file;
+    pub use crate::epub::reader::read_from_file_lenient;
+    pub use crate::epub::reader::read_from_reader;
+    pub use crate::epub::reader::read_from_reader_lenient;
+    pub use crate::epub::reader::read_from_shared;
     pub use crate::epub::reader::read_from_vec;
+    pub use crate::epub::reader::read_from_vec_lenient;
+    pub use crate::epub::reader::EpubWarning;
+    pub use crate::epub::validator::validate_dir;
+    pub use crate::epub::validator::validate_file;
+    pub use crate::epub::validator::validate_vec;
+    pub use crate::epub::validator::EpubDiagnostic;
+    pub use crate::epub::validator::Severity;
+    pub use crate::epub::writer::EpubCompression;
+    pub use crate::epub::writer::EpubStreamWriter;
+    pub use crate::epub::writer::EpubWriteOptions;
     pub use crate::epub::writer::EpubWriter;
 
     pub mod appender {
         pub use crate::epub::appender::write_metadata;
+        pub use crate::epub::appender::EpubAppender;
+    }
+
+    pub mod cfi {
+        pub use crate::epub::cfi::resolve_cfi;
+        pub use crate::epub::cfi::resolve_range_cfi;
+        pub use crate::epub::cfi::to_cfi;
+        pub use crate::epub::cfi::to_range_cfi;
+        pub use crate::epub::cfi::CfiPosition;
+    }
+
+    pub mod convert {
+        pub use crate::epub::convert::downgrade_to_epub2;
+        pub use crate::epub::convert::upgrade_to_epub3;
+    }
+
+    pub mod diff {
+        pub use crate::adapter::core::diff_mobi;
+        pub use crate::epub::diff::diff_book;
+        pub use crate::epub::diff::to_json;
+        pub use crate::epub::diff::BookChange;
+    }
+
+    pub mod search {
+        pub use crate::search::search_epub;
+        pub use crate::search::search_mobi;
+        pub use crate::search::SearchHit;
+        pub use crate::search::SearchQuery;
+    }
+
+    pub mod stats {
+        pub use crate::stats::stats_epub;
+        pub use crate::stats::stats_mobi;
+        pub use crate::stats::BookStats;
+        pub use crate::stats::ChapterStats;
+        pub use crate::stats::ReadingSpeed;
+    }
+
+    pub mod txt {
+        pub use crate::txt::epub_to_txt;
+        pub use crate::txt::mobi_to_txt;
+        pub use crate::txt::TxtEncoding;
+        pub use crate::txt::TxtOptions;
     }
 
     pub use crate::mobi::builder::MobiBuilder;
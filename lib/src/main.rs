@@ -57,7 +57,14 @@ mod commands {
             FormatConvert,
             Concat,
             Replace,
-            Optimize
+            Optimize,
+            Validate,
+            Unpack,
+            Pack,
+            Append,
+            Diff,
+            Search,
+            Stats
         );
     }
     pub(crate) mod mobi {
@@ -70,7 +77,10 @@ mod commands {
             FormatConvert,
             NavScanner,
             GetChapter,
-            Replace
+            Replace,
+            Diff,
+            Search,
+            Stats
         );
     }
 }
@@ -97,6 +107,8 @@ pub(crate) enum Book<'a> {
 /// 检查文件类型
 ///
 /// [return] 0 epub 1 mobi,None 没有指定文件参数
+///
+/// 文件夹视为解压后的epub
 fn check_input_type(arg: &Arg) -> Option<(usize, String)> {
     let check_method: Vec<fn(&mut File) -> IResult<bool>> = vec![
         iepub::prelude::check::is_epub,
@@ -106,6 +118,9 @@ fn check_input_type(arg: &Arg) -> Option<(usize, String)> {
     if let Some(opt) = arg.find_opt("i") {
         let path = opt.value.as_ref().unwrap().as_str();
         msg!("opening file {}", path);
+        if std::path::Path::new(path).is_dir() {
+            return Some((0, path.to_string()));
+        }
         let v = std::fs::File::open(path);
         if let Err(e) = v {
             exec_err!("open file err: {}", e);
@@ -189,7 +204,19 @@ fn main() {
     // 打开文件并执行
     if res == 0 {
         // epub
-        match read_from_file(path.as_str()) {
+        // 检查结构时尽量读取不规范的文件，问题由检查结果给出
+        let validate = arg.group.iter().any(|f| f.command == "validate");
+        // 只检查结构时由 validate 自行读取，不用再解析一遍
+        let only_validate = validate && arg.group.iter().all(|f| f.command == "validate");
+        let dir = std::path::Path::new(path.as_str()).is_dir();
+        let result = match (validate, dir) {
+            _ if only_validate => Ok(EpubBook::default()),
+            (true, true) => read_from_dir_lenient(path.as_str()).map(|f| f.0),
+            (true, false) => read_from_file_lenient(path.as_str()).map(|f| f.0),
+            (false, true) => read_from_dir(path.as_str()),
+            (false, false) => read_from_file(path.as_str()),
+        };
+        match result {
             Ok(mut book) => {
                 exec_epub(&arg, &mut book, exe_file_name.as_str());
             }
@@ -108,6 +108,19 @@ impl MobiBuilder {
         self.book.set_subject(subject);
         self
     }
+    ///
+    /// 设置系列和在系列中的序号
+    ///
+    /// mobi 没有对应的 EXTH 记录，只用于和 epub 相互转换，写入 mobi 文件时不会保存
+    ///
+    pub fn with_series<T: AsRef<str>>(mut self, series: T) -> Self {
+        self.book.set_series(series);
+        self
+    }
+    pub fn with_series_index<T: AsRef<str>>(mut self, index: T) -> Self {
+        self.book.set_series_index(index);
+        self
+    }
 
     pub fn with_last_modify<T: AsRef<str>>(mut self, last_modify: T) -> Self {
         self.book.set_last_modify(last_modify);
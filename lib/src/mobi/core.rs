@@ -197,6 +197,17 @@ impl MobiBook {
     iepub_derive::option_string_method!("format", info, format);
     iepub_derive::option_string_method!("出版社", info, publisher);
     iepub_derive::option_string_method!("主题", info, subject);
+    // mobi 没有对应的 EXTH 记录，只在内存中保留，用于和epub相互转换
+    iepub_derive::option_string_method!(
+        "系列，mobi 没有对应的 EXTH 记录，MobiWriter 写入时不会保存",
+        info,
+        series
+    );
+    iepub_derive::option_string_method!(
+        "在系列中的序号，和系列一样写入时不会保存",
+        info,
+        series_index
+    );
     // /
     // / 设置epub最后修改时间
     // /
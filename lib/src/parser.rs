@@ -152,6 +152,14 @@ impl HtmlParser {
                     }
                 }
 
+                Ok(Event::GeneralRef(ref e)) if in_body => {
+                    if let (Some(v), Some(item)) =
+                        (crate::common::resolve_entity(e), stack.last_mut())
+                    {
+                        item.add_text(v.encode_utf8(&mut [0; 4]));
+                    }
+                }
+
                 Ok(Event::CData(ref e)) => {
                     if in_body {
                         let text = String::from_utf8_lossy(e.as_ref());
@@ -333,4 +341,13 @@ mod tests {
         assert_eq!(headings.len(), 1);
         assert_eq!(headings[0].0, 1);
     }
+
+    #[test]
+    fn test_parse_html_entity() {
+        let mut parser = HtmlParser::new();
+        parser
+            .parse("<p>&ldquo;好&rdquo;&mdash;&#x4e2d;&amp;&hellip;</p>")
+            .unwrap();
+        assert_eq!(vec!["“好”—中&…"], parser.extract_paragraphs());
+    }
 }
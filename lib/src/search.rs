@@ -0,0 +1,386 @@
+//! 全文搜索
+//!
+//! 使用 [`crate::parser::HtmlParser::extract_paragraphs`] 提取章节中的段落后逐段匹配，支持普通文本和正则表达式
+//!
+//! epub 的章节逐个读取，搜索完成后释放之前没有加载的章节，不会同时把所有章节读取到内存中；
+//! mobi 读取时已经加载了全部内容，直接搜索
+//!
+
+use std::collections::{HashMap, VecDeque};
+
+use regex::Regex;
+
+use crate::epub::common;
+use crate::prelude::*;
+
+///
+/// 搜索条件
+///
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    regex: Regex,
+    /// 匹配内容前后保留的字符数
+    context: usize,
+}
+
+impl SearchQuery {
+    ///
+    /// 搜索普通文本
+    ///
+    pub fn new<T: AsRef<str>>(text: T) -> Self {
+        SearchQuery {
+            regex: Regex::new(regex::escape(text.as_ref()).as_str()).unwrap(),
+            context: 20,
+        }
+    }
+
+    ///
+    /// 使用正则表达式搜索，例如 `(?i)chapter \d+`
+    ///
+    pub fn regex<T: AsRef<str>>(pattern: T) -> IResult<Self> {
+        Ok(SearchQuery {
+            regex: Regex::new(pattern.as_ref())?,
+            context: 20,
+        })
+    }
+
+    ///
+    /// 上下文中匹配内容前后保留的字符数，默认 20
+    ///
+    pub fn with_context(mut self, context: usize) -> Self {
+        self.context = context;
+        self
+    }
+
+    fn find(
+        &self,
+        chapter: usize,
+        file_name: Option<&str>,
+        title: &str,
+        paragraphs: Vec<String>,
+    ) -> VecDeque<SearchHit> {
+        let mut hits = VecDeque::new();
+        for (index, text) in paragraphs.iter().enumerate() {
+            for m in self.regex.find_iter(text) {
+                if m.as_str().is_empty() {
+                    continue;
+                }
+                let before = &text[..m.start()];
+                let after = &text[m.end()..];
+                let start = match self.context {
+                    0 => before.len(),
+                    n => before.char_indices().rev().nth(n - 1).map_or(0, |f| f.0),
+                };
+                let end = after
+                    .char_indices()
+                    .nth(self.context)
+                    .map_or(after.len(), |f| f.0);
+                hits.push_back(SearchHit {
+                    chapter,
+                    file_name: file_name.map(|f| f.to_string()),
+                    title: title.to_string(),
+                    paragraph: index,
+                    offset: before.chars().count(),
+                    text: m.as_str().to_string(),
+                    context: format!(
+                        "{}{}{}{}{}",
+                        if start > 0 { "…" } else { "" },
+                        &before[start..],
+                        m.as_str(),
+                        &after[..end],
+                        if end < after.len() { "…" } else { "" }
+                    ),
+                });
+            }
+        }
+        hits
+    }
+}
+
+///
+/// 搜索结果
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    chapter: usize,
+    file_name: Option<String>,
+    title: String,
+    paragraph: usize,
+    offset: usize,
+    text: String,
+    context: String,
+}
+
+impl SearchHit {
+    ///
+    /// 章节的序号，从 0 开始
+    ///
+    pub fn chapter(&self) -> usize {
+        self.chapter
+    }
+
+    ///
+    /// 章节文件名，mobi 没有文件名
+    ///
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    ///
+    /// 章节对应的目录标题，没有目录时使用章节标题
+    ///
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    ///
+    /// 段落在章节中的序号，从 0 开始
+    ///
+    pub fn paragraph(&self) -> usize {
+        self.paragraph
+    }
+
+    ///
+    /// 匹配内容在段落中的位置，按字符计算
+    ///
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    ///
+    /// 匹配到的内容
+    ///
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    ///
+    /// 包括匹配内容的上下文，截断的位置使用 … 表示
+    ///
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+}
+
+impl std::fmt::Display for SearchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file_name {
+            Some(file_name) => write!(f, "{file_name}")?,
+            None => write!(f, "#{}", self.chapter)?,
+        }
+        write!(
+            f,
+            " [{}] 段落 {}: {}",
+            self.title, self.paragraph, self.context
+        )
+    }
+}
+
+pub(crate) fn epub_nav_titles(
+    nav: std::slice::Iter<'_, EpubNav>,
+    out: &mut HashMap<String, String>,
+) {
+    for ele in nav {
+        let file_name = ele.file_name().split('#').next().unwrap_or_default();
+        if !out.contains_key(file_name) {
+            out.insert(file_name.to_string(), ele.title().to_string());
+        }
+        epub_nav_titles(ele.child(), out);
+    }
+}
+
+struct EpubSearch<'a> {
+    book: &'a mut EpubBook,
+    query: &'a SearchQuery,
+    titles: HashMap<String, String>,
+    index: usize,
+    hits: VecDeque<SearchHit>,
+}
+
+impl Iterator for EpubSearch<'_> {
+    type Item = SearchHit;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nav = common::NAV.replace(common::EPUB, "");
+        loop {
+            if let Some(hit) = self.hits.pop_front() {
+                return Some(hit);
+            }
+            let index = self.index;
+            let chap = self.book.chapters_mut().nth(index)?;
+            self.index += 1;
+            if chap.file_name() == nav {
+                continue;
+            }
+            let loaded = chap.data().is_some();
+            let paragraphs = chap
+                .parser()
+                .map(|f| f.extract_paragraphs())
+                .unwrap_or_default();
+            if !loaded {
+                chap.release_data();
+            }
+            let file_name = chap.file_name().split('#').next().unwrap_or_default();
+            let title = self
+                .titles
+                .get(file_name)
+                .map_or(chap.title(), |f| f.as_str());
+            self.hits = self
+                .query
+                .find(index, Some(chap.file_name()), title, paragraphs);
+        }
+    }
+}
+
+///
+/// 搜索epub
+///
+/// 返回的迭代器按顺序逐个读取章节，可以在读取到需要的结果后提前结束
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::search::{search_epub, SearchQuery};
+///
+/// let mut book = read_from_file("example.epub").unwrap();
+/// let query = SearchQuery::regex(r"第\d+章").unwrap();
+/// for hit in search_epub(&mut book, &query) {
+///     println!("{hit}");
+/// }
+/// ```
+pub fn search_epub<'a>(
+    book: &'a mut EpubBook,
+    query: &'a SearchQuery,
+) -> impl Iterator<Item = SearchHit> + 'a {
+    let mut titles = HashMap::new();
+    epub_nav_titles(book.nav(), &mut titles);
+    EpubSearch {
+        book,
+        query,
+        titles,
+        index: 0,
+        hits: VecDeque::new(),
+    }
+}
+
+pub(crate) fn mobi_nav_title(nav: std::slice::Iter<'_, MobiNav>, id: usize) -> Option<&str> {
+    for ele in nav {
+        if ele.id() == id {
+            return Some(ele.title());
+        }
+        if let Some(v) = mobi_nav_title(ele.child(), id) {
+            return Some(v);
+        }
+    }
+    None
+}
+
+///
+/// 搜索mobi
+///
+pub fn search_mobi<'a>(
+    book: &'a MobiBook,
+    query: &'a SearchQuery,
+) -> impl Iterator<Item = SearchHit> + 'a {
+    book.chapters().enumerate().flat_map(move |(index, chap)| {
+        let mut parser = crate::parser::HtmlParser::new();
+        let paragraphs = parser
+            .parse(chap.string_data().as_str())
+            .map(|_| parser.extract_paragraphs())
+            .unwrap_or_default();
+        let title = mobi_nav_title(book.nav(), chap.nav_id()).unwrap_or(chap.title());
+        query.find(index, None, title, paragraphs)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let paragraphs = vec!["一二三四五六七八九十".to_string(), "a.b a*b".to_string()];
+        let hits = SearchQuery::new("五").with_context(2).find(
+            0,
+            Some("0.xhtml"),
+            "标题",
+            paragraphs.clone(),
+        );
+        assert_eq!(1, hits.len());
+        assert_eq!(4, hits[0].offset());
+        assert_eq!("…三四五六七…", hits[0].context());
+        assert_eq!("0.xhtml [标题] 段落 0: …三四五六七…", hits[0].to_string());
+
+        // 普通文本中的特殊字符不作为正则表达式
+        let hits = SearchQuery::new("a*b").find(0, None, "", paragraphs.clone());
+        assert_eq!(1, hits.len());
+        assert_eq!(1, hits[0].paragraph());
+        assert_eq!("a.b a*b", hits[0].context());
+
+        let hits = SearchQuery::regex(r"a.b")
+            .unwrap()
+            .with_context(0)
+            .find(0, None, "", paragraphs);
+        assert_eq!(
+            vec!["a.b…", "…a*b"],
+            hits.iter().map(|f| f.context()).collect::<Vec<&str>>()
+        );
+
+        assert!(SearchQuery::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_search_epub() {
+        let mut book = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>天气很好</p><p>没有内容</p>".as_bytes().to_vec()),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_title("第二章")
+                    .with_data("<p>没有内容</p><p>天气不好，天气</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut book = read_from_vec(data).unwrap();
+
+        let query = SearchQuery::new("天气");
+        let hits: Vec<SearchHit> = search_epub(&mut book, &query).collect();
+        assert_eq!(3, hits.len());
+        assert_eq!(Some("0.xhtml"), hits[0].file_name());
+        assert_eq!("1. 第一章", hits[0].title());
+        // 读取到的章节中包括 nav.xhtml
+        assert_eq!(
+            (2, 1, 0),
+            (hits[1].chapter(), hits[1].paragraph(), hits[1].offset())
+        );
+        assert_eq!(5, hits[2].offset());
+        // 搜索后释放章节
+        assert!(book.chapters().all(|f| f.data().is_none()));
+    }
+
+    #[test]
+    fn test_search_mobi() {
+        let book = MobiBuilder::new()
+            .with_title("书名")
+            .cover(vec![1, 2, 3])
+            .add_chapter(
+                MobiHtml::new(0)
+                    .with_title("第一章")
+                    .with_data("<p>天气很好</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let query = SearchQuery::new("很好");
+        let hits: Vec<SearchHit> = search_mobi(&book, &query).collect();
+        assert_eq!(1, hits.len());
+        assert_eq!(None, hits[0].file_name());
+        assert_eq!("#0 [1. 第一章] 段落 0: 天气很好", hits[0].to_string());
+    }
+}
@@ -0,0 +1,381 @@
+//! 统计字数、段落、图片以及预计阅读时间
+//!
+//! 使用 [`crate::parser::HtmlParser::extract_plain_text`] 提取章节的文本，中日韩文字按字计算，其他文字按单词计算
+//!
+//! epub 的章节逐个读取，统计完成后释放之前没有加载的章节
+//!
+
+use crate::common::json_string;
+use crate::epub::common;
+use crate::parser::HtmlParser;
+use crate::prelude::*;
+
+///
+/// 阅读速度
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadingSpeed {
+    cjk: usize,
+    words: usize,
+}
+
+impl Default for ReadingSpeed {
+    fn default() -> Self {
+        ReadingSpeed {
+            cjk: 400,
+            words: 250,
+        }
+    }
+}
+
+impl ReadingSpeed {
+    ///
+    /// [cjk] 每分钟阅读的中日韩文字数
+    ///
+    /// [words] 每分钟阅读的单词数
+    ///
+    pub fn new(cjk: usize, words: usize) -> Self {
+        ReadingSpeed {
+            cjk: cjk.max(1),
+            words: words.max(1),
+        }
+    }
+
+    pub fn cjk(&self) -> usize {
+        self.cjk
+    }
+
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    fn minutes(&self, cjk: usize, words: usize) -> f64 {
+        cjk as f64 / self.cjk as f64 + words as f64 / self.words as f64
+    }
+}
+
+///
+/// 章节或者整本书的统计结果
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterStats {
+    file_name: Option<String>,
+    title: String,
+    cjk: usize,
+    words: usize,
+    characters: usize,
+    paragraphs: usize,
+    images: usize,
+    minutes: f64,
+}
+
+impl ChapterStats {
+    fn new(file_name: Option<&str>, title: &str, html: &str, speed: &ReadingSpeed) -> Self {
+        let mut parser = HtmlParser::new();
+        let parsed = parser.parse(html).is_ok();
+        let text = if parsed {
+            parser.extract_plain_text()
+        } else {
+            String::new()
+        };
+        let (cjk, words) = count_text(text.as_str());
+        ChapterStats {
+            file_name: file_name.map(|f| f.to_string()),
+            title: title.to_string(),
+            cjk,
+            words,
+            characters: text.chars().filter(|f| !f.is_whitespace()).count(),
+            paragraphs: if parsed {
+                parser.extract_paragraphs().len()
+            } else {
+                0
+            },
+            images: if parsed {
+                parser.extract_images().len()
+            } else {
+                0
+            },
+            minutes: speed.minutes(cjk, words),
+        }
+    }
+
+    ///
+    /// 章节文件名，mobi 和整本书的统计没有文件名
+    ///
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    ///
+    /// 中日韩文字数，不包括标点
+    ///
+    pub fn cjk(&self) -> usize {
+        self.cjk
+    }
+
+    ///
+    /// 中日韩文字以外的单词数
+    ///
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    ///
+    /// 除空白外的字符数，包括标点
+    ///
+    pub fn characters(&self) -> usize {
+        self.characters
+    }
+
+    pub fn paragraphs(&self) -> usize {
+        self.paragraphs
+    }
+
+    pub fn images(&self) -> usize {
+        self.images
+    }
+
+    ///
+    /// 预计阅读时间，单位为分钟
+    ///
+    pub fn minutes(&self) -> f64 {
+        self.minutes
+    }
+
+    ///
+    /// 转换为json对象
+    ///
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"file_name":{},"title":{},"cjk":{},"words":{},"characters":{},"paragraphs":{},"images":{},"minutes":{:.1}}}"#,
+            self.file_name
+                .as_deref()
+                .map_or("null".to_string(), json_string),
+            json_string(self.title.as_str()),
+            self.cjk,
+            self.words,
+            self.characters,
+            self.paragraphs,
+            self.images,
+            self.minutes
+        )
+    }
+}
+
+///
+/// 整本书的统计结果
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookStats {
+    chapters: Vec<ChapterStats>,
+    total: ChapterStats,
+}
+
+impl BookStats {
+    fn new(title: &str, chapters: Vec<ChapterStats>) -> Self {
+        let mut total = ChapterStats {
+            file_name: None,
+            title: title.to_string(),
+            cjk: 0,
+            words: 0,
+            characters: 0,
+            paragraphs: 0,
+            images: 0,
+            minutes: 0.0,
+        };
+        for ele in &chapters {
+            total.cjk += ele.cjk;
+            total.words += ele.words;
+            total.characters += ele.characters;
+            total.paragraphs += ele.paragraphs;
+            total.images += ele.images;
+            total.minutes += ele.minutes;
+        }
+        BookStats { chapters, total }
+    }
+
+    pub fn chapters(&self) -> &[ChapterStats] {
+        &self.chapters
+    }
+
+    ///
+    /// 所有章节的合计，标题为书名
+    ///
+    pub fn total(&self) -> &ChapterStats {
+        &self.total
+    }
+
+    ///
+    /// 转换为json，包括 total 和 chapters
+    ///
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"total":{},"chapters":[{}]}}"#,
+            self.total.to_json(),
+            self.chapters
+                .iter()
+                .map(|f| f.to_json())
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+}
+
+/// 是否是中日韩文字，不包括标点
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3400..=0x4DBF // 扩展A
+        | 0x4E00..=0x9FFF // 基本汉字
+        | 0xF900..=0xFAFF // 兼容汉字
+        | 0x20000..=0x323AF // 扩展B及以后
+        | 0x3040..=0x309F // 平假名
+        | 0x30A0..=0x30FF // 片假名
+        | 0x31F0..=0x31FF
+        | 0x1100..=0x11FF // 谚文
+        | 0x3130..=0x318F
+        | 0xAC00..=0xD7AF
+        | 0x3005 // 々
+    )
+}
+
+///
+/// 统计中日韩文字数和单词数
+///
+/// 单词由连续的字母、数字组成，中间可以包括 ' 和 -
+///
+fn count_text(text: &str) -> (usize, usize) {
+    let mut cjk = 0;
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            cjk += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+        } else if !(in_word && matches!(c, '\'' | '’' | '-')) {
+            in_word = false;
+        }
+    }
+    (cjk, words)
+}
+
+///
+/// 统计epub
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::stats::{stats_epub, ReadingSpeed};
+///
+/// let mut book = read_from_file("example.epub").unwrap();
+/// let stats = stats_epub(&mut book, &ReadingSpeed::default());
+/// println!("{} {:.0}", stats.total().cjk(), stats.total().minutes());
+/// ```
+pub fn stats_epub(book: &mut EpubBook, speed: &ReadingSpeed) -> BookStats {
+    let nav = common::NAV.replace(common::EPUB, "");
+    let mut chapters = Vec::new();
+    for chap in book.chapters_mut() {
+        if chap.file_name() == nav {
+            continue;
+        }
+        let loaded = chap.data().is_some();
+        let html = chap.string_data();
+        chapters.push(ChapterStats::new(
+            Some(chap.file_name()),
+            chap.title(),
+            html.as_str(),
+            speed,
+        ));
+        if !loaded {
+            chap.release_data();
+        }
+    }
+    BookStats::new(book.title(), chapters)
+}
+
+///
+/// 统计mobi
+///
+pub fn stats_mobi(book: &MobiBook, speed: &ReadingSpeed) -> BookStats {
+    let chapters = book
+        .chapters()
+        .map(|f| ChapterStats::new(None, f.title(), f.string_data().as_str(), speed))
+        .collect();
+    BookStats::new(book.title(), chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_text() {
+        assert_eq!((4, 0), count_text("天气很好。"));
+        assert_eq!((0, 4), count_text("It's a well-known fact"));
+        assert_eq!((3, 2), count_text("第3章 Chapter 天"));
+        assert_eq!((6, 0), count_text("ひらがなカ 한"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut book = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data(
+                        r#"<p>天气很好。</p><p>Hello world</p><img src="a.png"/>"#
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_title("第二章")
+                    .with_data("<p>没有内容</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut book = read_from_vec(data).unwrap();
+
+        let stats = stats_epub(&mut book, &ReadingSpeed::new(2, 1));
+        assert_eq!(2, stats.chapters().len());
+        let chap = &stats.chapters()[0];
+        assert_eq!(Some("0.xhtml"), chap.file_name());
+        assert_eq!(
+            (4, 2, 2, 1),
+            (chap.cjk(), chap.words(), chap.paragraphs(), chap.images())
+        );
+        assert_eq!(15, chap.characters());
+        assert_eq!(4.0, chap.minutes());
+
+        let total = stats.total();
+        assert_eq!("书名", total.title());
+        assert_eq!(
+            (8, 2, 3, 1),
+            (
+                total.cjk(),
+                total.words(),
+                total.paragraphs(),
+                total.images()
+            )
+        );
+        assert_eq!(6.0, total.minutes());
+        assert!(book.chapters().all(|f| f.data().is_none()));
+
+        assert!(stats.to_json().starts_with(
+            r#"{"total":{"file_name":null,"title":"书名","cjk":8,"words":2,"characters":19,"paragraphs":3,"images":1,"minutes":6.0},"chapters":[{"file_name":"0.xhtml""#
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! 导出纯文本（txt）
+//!
+//! 按照 spine（mobi 为文本章节）的顺序输出，目录标题作为章节标题，段落支持缩进和空行，图片可以替换为占位文本或者删除
+//!
+//! epub 的章节逐个读取，导出完成后释放之前没有加载的章节
+//!
+
+use std::collections::HashMap;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::epub::common;
+use crate::prelude::*;
+use crate::search::{epub_nav_titles, mobi_nav_title};
+
+///
+/// txt 编码
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TxtEncoding {
+    #[default]
+    Utf8,
+    /// 兼容 GBK，部分旧设备只能识别该编码
+    Gb18030,
+}
+
+///
+/// txt 导出选项
+///
+#[derive(Debug, Clone)]
+pub struct TxtOptions {
+    /// 段落缩进的字符数
+    indent: usize,
+    /// 缩进使用的字符
+    indent_char: char,
+    /// 段落之间的空行数
+    blank_lines: usize,
+    /// 图片的占位文本，为 None 时删除图片
+    image: Option<String>,
+    /// 是否输出目录标题
+    title: bool,
+    encoding: TxtEncoding,
+}
+
+impl Default for TxtOptions {
+    fn default() -> Self {
+        TxtOptions {
+            indent: 2,
+            indent_char: '\u{3000}',
+            blank_lines: 0,
+            image: Some("[图片]".to_string()),
+            title: true,
+            encoding: TxtEncoding::Utf8,
+        }
+    }
+}
+
+impl TxtOptions {
+    ///
+    /// 段落缩进的字符数，默认 2
+    ///
+    pub fn with_indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    ///
+    /// 缩进使用的字符，默认为全角空格
+    ///
+    pub fn with_indent_char(mut self, indent_char: char) -> Self {
+        self.indent_char = indent_char;
+        self
+    }
+
+    ///
+    /// 段落之间的空行数，默认 0
+    ///
+    pub fn with_blank_lines(mut self, blank_lines: usize) -> Self {
+        self.blank_lines = blank_lines;
+        self
+    }
+
+    ///
+    /// 图片的占位文本，默认为 `[图片]`，传入 None 时删除图片
+    ///
+    pub fn with_image_placeholder<T: Into<String>>(mut self, image: Option<T>) -> Self {
+        self.image = image.map(|f| f.into());
+        self
+    }
+
+    ///
+    /// 是否输出目录标题，默认输出
+    ///
+    pub fn with_title(mut self, title: bool) -> Self {
+        self.title = title;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: TxtEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    fn encode(&self, text: String) -> Vec<u8> {
+        match self.encoding {
+            TxtEncoding::Utf8 => text.into_bytes(),
+            TxtEncoding::Gb18030 => encoding_rs::GB18030.encode(text.as_str()).0.into_owned(),
+        }
+    }
+}
+
+/// 提取出的一行内容
+enum TxtLine {
+    Heading(String),
+    Paragraph(String),
+    Image(String),
+}
+
+/// 提取html中的标题、段落和图片，块级标签和 br 作为换行
+fn html_lines(html: &str) -> Vec<TxtLine> {
+    let mut reader = Reader::from_str(html);
+    reader.config_mut().check_end_names = false;
+
+    let mut lines = Vec::new();
+    let mut text = String::new();
+    let mut heading = false;
+    // head、style、script 等标签内的文本不输出
+    let mut skip: usize = 0;
+
+    let flush = |lines: &mut Vec<TxtLine>, text: &mut String, heading: bool| {
+        let v = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        text.clear();
+        if !v.is_empty() {
+            lines.push(if heading {
+                TxtLine::Heading(v)
+            } else {
+                TxtLine::Paragraph(v)
+            });
+        }
+    };
+
+    loop {
+        let (name, start, attrs) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e.local_name().as_ref().to_ascii_lowercase(), true, Some(e)),
+            Ok(Event::Empty(e)) => (e.local_name().as_ref().to_ascii_lowercase(), false, Some(e)),
+            Ok(Event::End(e)) => {
+                let name = e.local_name().as_ref().to_ascii_lowercase();
+                match name.as_slice() {
+                    b"head" | b"style" | b"script" | b"title" => skip = skip.saturating_sub(1),
+                    b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                        flush(&mut lines, &mut text, heading);
+                        heading = false;
+                    }
+                    b"p" | b"div" | b"li" | b"blockquote" | b"pre" | b"tr" | b"dt" | b"dd"
+                    | b"section" | b"article" | b"figcaption" | b"td" | b"th" => {
+                        flush(&mut lines, &mut text, heading)
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            Ok(Event::Text(e)) => {
+                if skip == 0 {
+                    text.push_str(e.decode().unwrap_or_default().as_ref());
+                }
+                continue;
+            }
+            Ok(Event::CData(e)) => {
+                if skip == 0 {
+                    text.push_str(String::from_utf8_lossy(e.as_ref()).as_ref());
+                }
+                continue;
+            }
+            Ok(Event::GeneralRef(e)) => {
+                if let (Some(v), 0) = (crate::common::resolve_entity(&e), skip) {
+                    text.push(v);
+                }
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => continue,
+        };
+        match name.as_slice() {
+            b"head" | b"style" | b"script" | b"title" if start => skip += 1,
+            b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                flush(&mut lines, &mut text, heading);
+                heading = true;
+            }
+            b"br" | b"p" | b"div" | b"li" | b"blockquote" | b"pre" | b"tr" | b"dt" | b"dd"
+            | b"section" | b"article" | b"figcaption" | b"td" | b"th" => {
+                flush(&mut lines, &mut text, heading)
+            }
+            b"img" | b"image" => {
+                flush(&mut lines, &mut text, heading);
+                let alt = attrs
+                    .as_ref()
+                    .and_then(|f| f.try_get_attribute("alt").ok().flatten())
+                    .and_then(|f| f.unescape_value().ok().map(|v| v.trim().to_string()))
+                    .unwrap_or_default();
+                lines.push(TxtLine::Image(alt));
+            }
+            _ => {}
+        }
+    }
+    flush(&mut lines, &mut text, heading);
+    lines
+}
+
+/// 输出一个章节
+fn write_chapter(out: &mut String, title: Option<&str>, html: &str, options: &TxtOptions) {
+    let mut lines = html_lines(html);
+    let title = title.map(|f| f.trim()).filter(|f| !f.is_empty());
+    if let (Some(title), true) = (title, options.title) {
+        // 正文中通常已经包括了标题，不再重复输出，目录标题可能带有序号，例如 `1. 第一章`
+        if let Some(TxtLine::Heading(v)) = lines.first() {
+            if title.ends_with(v.as_str()) {
+                lines.remove(0);
+            }
+        }
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(title);
+        out.push_str("\n\n");
+    }
+    let indent = options.indent_char.to_string().repeat(options.indent);
+    for line in lines {
+        match line {
+            TxtLine::Heading(v) => out.push_str(v.as_str()),
+            TxtLine::Paragraph(v) => {
+                out.push_str(indent.as_str());
+                out.push_str(v.as_str());
+            }
+            TxtLine::Image(alt) => match &options.image {
+                Some(image) if alt.is_empty() => out.push_str(image.as_str()),
+                Some(image) => {
+                    out.push_str(image.as_str());
+                    out.push(' ');
+                    out.push_str(alt.as_str());
+                }
+                None => continue,
+            },
+        }
+        out.push('\n');
+        out.push_str("\n".repeat(options.blank_lines).as_str());
+    }
+}
+
+///
+/// 导出epub为txt，返回按照选项编码后的内容
+///
+/// # Examples
+/// ```no_run
+/// use iepub::prelude::*;
+/// use iepub::prelude::txt::{epub_to_txt, TxtEncoding, TxtOptions};
+///
+/// let mut book = read_from_file("example.epub").unwrap();
+/// let options = TxtOptions::default()
+///     .with_blank_lines(1)
+///     .with_encoding(TxtEncoding::Gb18030);
+/// std::fs::write("example.txt", epub_to_txt(&mut book, &options)).unwrap();
+/// ```
+pub fn epub_to_txt(book: &mut EpubBook, options: &TxtOptions) -> Vec<u8> {
+    let nav = common::NAV.replace(common::EPUB, "");
+    let mut titles = HashMap::new();
+    epub_nav_titles(book.nav(), &mut titles);
+
+    let mut out = String::new();
+    for chap in book.chapters_mut() {
+        if chap.file_name() == nav {
+            continue;
+        }
+        let loaded = chap.data().is_some();
+        let html = chap.string_data();
+        if !loaded {
+            chap.release_data();
+        }
+        write_chapter(
+            &mut out,
+            titles.get(chap.file_name()).map(|f| f.as_str()),
+            html.as_str(),
+            options,
+        );
+    }
+    options.encode(out)
+}
+
+///
+/// 导出mobi为txt，返回按照选项编码后的内容
+///
+pub fn mobi_to_txt(book: &MobiBook, options: &TxtOptions) -> Vec<u8> {
+    let mut out = String::new();
+    for chap in book.chapters() {
+        let title = mobi_nav_title(book.nav(), chap.nav_id()).unwrap_or(chap.title());
+        write_chapter(&mut out, Some(title), chap.string_data().as_str(), options);
+    }
+    options.encode(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_chapter() {
+        let html = r#"<html><head><title>标题</title><style>p{}</style></head><body>
+<h1>第一章</h1>
+<p>天气<b>很好</b>&amp;
+  不错</p><p> </p>
+<div>第一行<br/>第二行</div>
+<img src="a.png" alt="插图"/><img src="b.png"/>
+<h2>小节</h2>
+</body></html>"#;
+        let mut out = String::new();
+        write_chapter(&mut out, Some("第一章"), html, &TxtOptions::default());
+        assert_eq!(
+            "第一章\n\n　　天气很好& 不错\n　　第一行\n　　第二行\n[图片] 插图\n[图片]\n小节\n",
+            out
+        );
+
+        let options = TxtOptions::default()
+            .with_indent(4)
+            .with_indent_char(' ')
+            .with_blank_lines(1)
+            .with_image_placeholder(None::<String>);
+        write_chapter(&mut out, Some("第二章"), html, &options);
+        assert!(out.ends_with(
+            "\n\n第二章\n\n第一章\n\n    天气很好& 不错\n\n    第一行\n\n    第二行\n\n小节\n\n"
+        ));
+
+        let mut out = String::new();
+        write_chapter(
+            &mut out,
+            Some("1. 第一章"),
+            "<h1>第一章</h1><p>内容</p>",
+            &TxtOptions::default(),
+        );
+        assert_eq!("1. 第一章\n\n　　内容\n", out);
+
+        let mut out = String::new();
+        write_chapter(
+            &mut out,
+            Some("第一章"),
+            "<p>内容</p>",
+            &TxtOptions::default().with_title(false),
+        );
+        assert_eq!("　　内容\n", out);
+
+        // 命名实体和字符引用
+        let mut out = String::new();
+        write_chapter(
+            &mut out,
+            None,
+            "<p>&ldquo;好&rdquo;&hellip;&mdash;&#x4e2d;&nbsp;&lt;</p>",
+            &TxtOptions::default(),
+        );
+        assert_eq!("　　“好”…—中 <\n", out);
+    }
+
+    #[test]
+    fn test_epub_to_txt() {
+        let mut book = EpubBuilder::default()
+            .with_title("书名")
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("0.xhtml")
+                    .with_title("第一章")
+                    .with_data("<p>天气很好</p>".as_bytes().to_vec()),
+            )
+            .add_chapter(
+                EpubHtml::default()
+                    .with_file_name("1.xhtml")
+                    .with_title("第二章")
+                    .with_data("<p>没有内容</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let data = EpubWriter::write_to_mem(&mut book, false).unwrap();
+        let mut book = read_from_vec(data).unwrap();
+
+        let txt = epub_to_txt(&mut book, &TxtOptions::default());
+        assert_eq!(
+            "1. 第一章\n\n　　天气很好\n\n2. 第二章\n\n　　没有内容\n",
+            String::from_utf8(txt).unwrap()
+        );
+        assert!(book.chapters().all(|f| f.data().is_none()));
+
+        let txt = epub_to_txt(
+            &mut book,
+            &TxtOptions::default().with_encoding(TxtEncoding::Gb18030),
+        );
+        let (v, _, _) = encoding_rs::GB18030.decode(&txt);
+        assert!(v.contains("天气很好"));
+        assert_ne!(std::str::from_utf8(&txt).ok(), Some(v.as_ref()));
+    }
+
+    #[test]
+    fn test_mobi_to_txt() {
+        let book = MobiBuilder::new()
+            .with_title("书名")
+            .cover(vec![1, 2, 3])
+            .add_chapter(
+                MobiHtml::new(0)
+                    .with_title("第一章")
+                    .with_data("<p>天气很好</p>".as_bytes().to_vec()),
+            )
+            .book()
+            .unwrap();
+        let txt = mobi_to_txt(&book, &TxtOptions::default().with_indent(0));
+        assert_eq!("1. 第一章\n\n天气很好\n", String::from_utf8(txt).unwrap());
+    }
+}